@@ -0,0 +1,27 @@
+-- Transactions reference users by their immutable userid instead of username.
+ALTER TABLE Transactions
+    ADD COLUMN from_userid UUID,
+    ADD COLUMN to_userid UUID;
+
+UPDATE Transactions t
+SET from_userid = u.userid
+FROM Users u
+WHERE u.username = t.from_username;
+
+UPDATE Transactions t
+SET to_userid = u.userid
+FROM Users u
+WHERE u.username = t.to_username;
+
+ALTER TABLE Transactions
+    ALTER COLUMN from_userid SET NOT NULL,
+    ALTER COLUMN to_userid SET NOT NULL,
+    ADD CONSTRAINT transactions_from_userid_fkey FOREIGN KEY (from_userid) REFERENCES Users(userid),
+    ADD CONSTRAINT transactions_to_userid_fkey FOREIGN KEY (to_userid) REFERENCES Users(userid);
+
+ALTER TABLE Transactions
+    DROP COLUMN from_username,
+    DROP COLUMN to_username;
+
+CREATE INDEX IF NOT EXISTS transactions_from_userid_idx ON Transactions (from_userid);
+CREATE INDEX IF NOT EXISTS transactions_to_userid_idx ON Transactions (to_userid);
@@ -0,0 +1,11 @@
+-- Previous usernames stay reserved for their owner for a cooldown period, and
+-- transfers addressed to them resolve to the current account until it ends.
+CREATE TABLE IF NOT EXISTS Username_History (
+    old_username TEXT NOT NULL,
+    userid UUID NOT NULL REFERENCES Users(userid),
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    reserved_until TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (old_username, changed_at)
+);
+
+CREATE INDEX IF NOT EXISTS username_history_userid_idx ON Username_History (userid);
@@ -0,0 +1,7 @@
+-- Accounts are never deleted: they move between active, frozen and closed.
+ALTER TABLE Users
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'frozen', 'closed')),
+    ADD COLUMN status_reason TEXT,
+    ADD COLUMN status_changed_at TIMESTAMPTZ,
+    ADD COLUMN closed_at TIMESTAMPTZ,
+    ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,41 @@
+ALTER TABLE Users
+    ADD COLUMN kyc_level TEXT NOT NULL DEFAULT 'unverified'
+        CHECK (kyc_level IN ('unverified', 'basic', 'verified'));
+
+-- Server-side insertion time; `time` is supplied by the client and cannot be
+-- trusted for limit windows.
+ALTER TABLE Transactions
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX IF NOT EXISTS transactions_from_userid_created_at_idx
+    ON Transactions (from_userid, created_at);
+
+-- NULL means no limit for that tier.
+CREATE TABLE IF NOT EXISTS Kyc_Limits (
+    kyc_level TEXT PRIMARY KEY,
+    max_single_transfer DOUBLE PRECISION,
+    max_daily_outflow DOUBLE PRECISION,
+    max_balance DOUBLE PRECISION
+);
+
+INSERT INTO Kyc_Limits (kyc_level, max_single_transfer, max_daily_outflow, max_balance) VALUES
+    ('unverified', 1000, 2500, 10000),
+    ('basic', 10000, 25000, 100000),
+    ('verified', 100000, 250000, NULL)
+ON CONFLICT (kyc_level) DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS Kyc_Verifications (
+    verification_id UUID PRIMARY KEY,
+    userid UUID NOT NULL REFERENCES Users(userid),
+    requested_level TEXT NOT NULL CHECK (requested_level IN ('basic', 'verified')),
+    document_type TEXT NOT NULL,
+    document_number TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+    notes TEXT,
+    submitted_by TEXT NOT NULL,
+    submitted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    reviewed_by TEXT,
+    reviewed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS kyc_verifications_userid_idx ON Kyc_Verifications (userid);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS Payees (
+    payee_id UUID PRIMARY KEY,
+    owner_userid UUID NOT NULL REFERENCES Users(userid),
+    payee_userid UUID NOT NULL REFERENCES Users(userid),
+    nickname TEXT,
+    favorite BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (owner_userid, payee_userid)
+);
@@ -0,0 +1,39 @@
+-- Phone numbers are stored as normalize_phone() writes them: trimmed, with an
+-- optional leading '+' and then only digits. Numbers it would reject are left
+-- as they are.
+UPDATE Users
+SET phno = regexp_replace(regexp_replace(phno, '^\s+|\s+$', '', 'g'), '[ .()-]', '', 'g')
+WHERE phno ~ '^\s*\+?[0-9 .()-]*\s*$';
+
+-- Numbers must be unique so they can identify a recipient. Where accounts
+-- share one, the lowest userid keeps it; the others get a placeholder no
+-- lookup can match, and their number is kept here until support sorts it out.
+CREATE TABLE IF NOT EXISTS Phone_Number_Conflicts (
+    userid UUID PRIMARY KEY REFERENCES Users(userid),
+    phno TEXT NOT NULL,
+    detected_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO Phone_Number_Conflicts (userid, phno)
+SELECT userid, phno FROM (
+    SELECT userid, phno, ROW_NUMBER() OVER (PARTITION BY phno ORDER BY userid) AS n
+    FROM Users
+) ranked
+WHERE n > 1;
+
+UPDATE Users u SET phno = 'conflict:' || u.userid
+FROM Phone_Number_Conflicts c
+WHERE c.userid = u.userid;
+
+ALTER TABLE Users
+    ADD CONSTRAINT users_phno_key UNIQUE (phno),
+    ADD COLUMN email TEXT,
+    ADD COLUMN discoverable BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD CONSTRAINT users_email_key UNIQUE (email);
+
+CREATE TABLE IF NOT EXISTS Recipient_Lookups (
+    userid UUID NOT NULL REFERENCES Users(userid),
+    looked_up_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS recipient_lookups_userid_idx ON Recipient_Lookups (userid, looked_up_at);
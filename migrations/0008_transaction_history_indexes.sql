@@ -0,0 +1,9 @@
+-- Keyset pagination walks each side of a user's history newest first.
+CREATE INDEX IF NOT EXISTS transactions_from_userid_time_idx
+    ON Transactions (from_userid, time DESC, txn_id DESC);
+CREATE INDEX IF NOT EXISTS transactions_to_userid_time_idx
+    ON Transactions (to_userid, time DESC, txn_id DESC);
+
+-- Superseded by the composite indexes above.
+DROP INDEX IF EXISTS transactions_from_userid_idx;
+DROP INDEX IF EXISTS transactions_to_userid_idx;
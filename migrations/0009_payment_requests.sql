@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS Payment_Requests (
+    request_id UUID PRIMARY KEY,
+    requester_userid UUID NOT NULL REFERENCES Users(userid),
+    payer_userid UUID NOT NULL REFERENCES Users(userid),
+    amount DOUBLE PRECISION NOT NULL CHECK (amount > 0),
+    memo TEXT,
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'approved', 'declined', 'cancelled')),
+    -- Set when the request is approved and paid.
+    txn_id UUID REFERENCES Transactions(txn_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS payment_requests_payer_idx
+    ON Payment_Requests (payer_userid, created_at DESC);
+CREATE INDEX IF NOT EXISTS payment_requests_requester_idx
+    ON Payment_Requests (requester_userid, created_at DESC);
@@ -0,0 +1,40 @@
+CREATE TABLE IF NOT EXISTS Scheduled_Transfers (
+    schedule_id UUID PRIMARY KEY,
+    from_userid UUID NOT NULL REFERENCES Users(userid),
+    to_userid UUID NOT NULL REFERENCES Users(userid),
+    amount DOUBLE PRECISION NOT NULL CHECK (amount > 0),
+    frequency TEXT NOT NULL CHECK (frequency IN ('once', 'daily', 'weekly', 'monthly', 'cron')),
+    cron_expr TEXT,
+    -- Recurrences are counted from start_at so monthly runs keep their day.
+    start_at TIMESTAMPTZ NOT NULL,
+    end_at TIMESTAMPTZ,
+    -- NULL once the schedule has no more runs.
+    next_run_at TIMESTAMPTZ,
+    status TEXT NOT NULL DEFAULT 'active'
+        CHECK (status IN ('active', 'paused', 'completed', 'cancelled')),
+    -- Consecutive failures of the current run; the schedule pauses once this
+    -- exceeds max_retries.
+    failure_count INT NOT NULL DEFAULT 0,
+    max_retries INT NOT NULL DEFAULT 3,
+    last_error TEXT,
+    last_run_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK ((frequency = 'cron') = (cron_expr IS NOT NULL))
+);
+
+CREATE INDEX IF NOT EXISTS scheduled_transfers_due_idx
+    ON Scheduled_Transfers (next_run_at) WHERE status = 'active';
+CREATE INDEX IF NOT EXISTS scheduled_transfers_from_userid_idx
+    ON Scheduled_Transfers (from_userid);
+
+CREATE TABLE IF NOT EXISTS Scheduled_Transfer_Runs (
+    run_id UUID PRIMARY KEY,
+    schedule_id UUID NOT NULL REFERENCES Scheduled_Transfers(schedule_id),
+    scheduled_for TIMESTAMPTZ NOT NULL,
+    ran_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    txn_id UUID REFERENCES Transactions(txn_id),
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS scheduled_transfer_runs_schedule_idx
+    ON Scheduled_Transfer_Runs (schedule_id, ran_at DESC);
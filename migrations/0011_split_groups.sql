@@ -0,0 +1,49 @@
+CREATE TABLE IF NOT EXISTS Split_Groups (
+    group_id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_by UUID NOT NULL REFERENCES Users(userid),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS Split_Group_Members (
+    group_id UUID NOT NULL REFERENCES Split_Groups(group_id),
+    userid UUID NOT NULL REFERENCES Users(userid),
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (group_id, userid)
+);
+
+CREATE INDEX IF NOT EXISTS split_group_members_userid_idx ON Split_Group_Members (userid);
+
+CREATE TABLE IF NOT EXISTS Split_Expenses (
+    expense_id UUID PRIMARY KEY,
+    group_id UUID NOT NULL REFERENCES Split_Groups(group_id),
+    paid_by UUID NOT NULL REFERENCES Users(userid),
+    amount DOUBLE PRECISION NOT NULL CHECK (amount > 0),
+    description TEXT NOT NULL,
+    split_type TEXT NOT NULL CHECK (split_type IN ('equal', 'percentage', 'exact')),
+    created_by UUID NOT NULL REFERENCES Users(userid),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS split_expenses_group_idx ON Split_Expenses (group_id, created_at DESC);
+
+-- What each participant owes towards an expense; shares add up to the amount.
+CREATE TABLE IF NOT EXISTS Split_Expense_Shares (
+    expense_id UUID NOT NULL REFERENCES Split_Expenses(expense_id),
+    userid UUID NOT NULL REFERENCES Users(userid),
+    amount DOUBLE PRECISION NOT NULL CHECK (amount >= 0),
+    PRIMARY KEY (expense_id, userid)
+);
+
+-- Real transfers made to settle group debts.
+CREATE TABLE IF NOT EXISTS Split_Settlements (
+    settlement_id UUID PRIMARY KEY,
+    group_id UUID NOT NULL REFERENCES Split_Groups(group_id),
+    txn_id UUID NOT NULL REFERENCES Transactions(txn_id),
+    from_userid UUID NOT NULL REFERENCES Users(userid),
+    to_userid UUID NOT NULL REFERENCES Users(userid),
+    amount DOUBLE PRECISION NOT NULL CHECK (amount > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS split_settlements_group_idx ON Split_Settlements (group_id);
@@ -0,0 +1,27 @@
+CREATE TABLE IF NOT EXISTS Transfer_Batches (
+    -- Chosen by the client, so a retried batch is rejected instead of paid twice.
+    batch_id UUID PRIMARY KEY,
+    from_userid UUID NOT NULL REFERENCES Users(userid),
+    atomic BOOLEAN NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('completed', 'partial', 'failed')),
+    item_count INT NOT NULL,
+    succeeded_count INT NOT NULL,
+    -- Sum of the items that were paid.
+    total_amount DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS transfer_batches_from_userid_idx
+    ON Transfer_Batches (from_userid, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS Transfer_Batch_Items (
+    batch_id UUID NOT NULL REFERENCES Transfer_Batches(batch_id),
+    item_index INT NOT NULL,
+    to_username TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('succeeded', 'failed', 'rolled_back', 'skipped')),
+    -- No foreign key: items of a failed atomic batch name transfers that were rolled back.
+    txn_id UUID,
+    error TEXT,
+    PRIMARY KEY (batch_id, item_index)
+);
@@ -0,0 +1,19 @@
+-- Funds reserved from payer for payee. Active, unexpired holds reduce the
+-- payer's available balance; nothing moves until capture.
+CREATE TABLE IF NOT EXISTS Holds (
+    hold_id UUID PRIMARY KEY,
+    payer_userid UUID NOT NULL REFERENCES Users(userid),
+    payee_userid UUID NOT NULL REFERENCES Users(userid),
+    amount DOUBLE PRECISION NOT NULL CHECK (amount > 0),
+    captured_amount DOUBLE PRECISION,
+    status TEXT NOT NULL DEFAULT 'active'
+        CHECK (status IN ('active', 'captured', 'voided', 'expired')),
+    txn_id UUID REFERENCES Transactions(txn_id),
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS holds_active_payer_idx ON Holds (payer_userid) WHERE status = 'active';
+CREATE INDEX IF NOT EXISTS holds_active_expires_idx ON Holds (expires_at) WHERE status = 'active';
+CREATE INDEX IF NOT EXISTS holds_payee_idx ON Holds (payee_userid, created_at DESC);
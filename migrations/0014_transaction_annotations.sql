@@ -0,0 +1,17 @@
+-- Set by the sender and visible to both participants.
+ALTER TABLE Transactions ADD COLUMN IF NOT EXISTS memo TEXT;
+
+-- Each participant's private tags and category for a transaction.
+CREATE TABLE IF NOT EXISTS Transaction_Annotations (
+    txn_id UUID NOT NULL REFERENCES Transactions(txn_id),
+    userid UUID NOT NULL REFERENCES Users(userid),
+    category TEXT,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (txn_id, userid)
+);
+
+CREATE INDEX IF NOT EXISTS transaction_annotations_category_idx
+    ON Transaction_Annotations (userid, category);
+CREATE INDEX IF NOT EXISTS transaction_annotations_tags_idx
+    ON Transaction_Annotations USING GIN (tags);
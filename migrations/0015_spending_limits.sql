@@ -0,0 +1,14 @@
+-- Limits a user sets on their own outflows. NULL means no limit. Tighter
+-- limits apply at once; looser ones are staged in the pending_* columns and
+-- replace the current limits at pending_effective_at.
+CREATE TABLE IF NOT EXISTS Spending_Limits (
+    userid UUID PRIMARY KEY REFERENCES Users(userid),
+    max_per_transaction DOUBLE PRECISION CHECK (max_per_transaction > 0),
+    max_daily DOUBLE PRECISION CHECK (max_daily > 0),
+    max_monthly DOUBLE PRECISION CHECK (max_monthly > 0),
+    pending_max_per_transaction DOUBLE PRECISION,
+    pending_max_daily DOUBLE PRECISION,
+    pending_max_monthly DOUBLE PRECISION,
+    pending_effective_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,42 @@
+-- A user who already took the fee account's name is renamed to make room.
+UPDATE Users SET username = username || '_' || LEFT(userid::TEXT, 8)
+WHERE username = 'payfree_fees' AND userid <> '00000000-0000-0000-0000-00000000fee5';
+
+-- Fees are paid by the sender on top of the amount and credited to this
+-- account in the same database transaction. It cannot log in.
+INSERT INTO Users (userid, name, username, phno, address, balance, password_hash)
+VALUES ('00000000-0000-0000-0000-00000000fee5', 'Payfree Fees', 'payfree_fees', 'system:fees',
+    'system', 0, '!')
+ON CONFLICT (userid) DO NOTHING;
+
+ALTER TABLE Transactions
+    ADD COLUMN kind TEXT NOT NULL DEFAULT 'p2p'
+        CHECK (kind IN ('p2p', 'request', 'scheduled', 'batch', 'hold', 'settlement', 'payout')),
+    ADD COLUMN fee DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (fee >= 0);
+
+-- Rules are never deleted so past fees stay explainable; replacing a rule
+-- deactivates the old one. NULL kyc_level matches any tier.
+CREATE TABLE IF NOT EXISTS Fee_Rules (
+    rule_id UUID PRIMARY KEY,
+    kind TEXT NOT NULL,
+    kyc_level TEXT CHECK (kyc_level IN ('unverified', 'basic', 'verified')),
+    fee_type TEXT NOT NULL CHECK (fee_type IN ('flat', 'percentage', 'tiered')),
+    flat_amount DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (flat_amount >= 0),
+    percentage DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (percentage >= 0),
+    min_fee DOUBLE PRECISION CHECK (min_fee >= 0),
+    max_fee DOUBLE PRECISION CHECK (max_fee >= 0),
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS fee_rules_active_idx
+    ON Fee_Rules (kind, COALESCE(kyc_level, '')) WHERE active;
+
+CREATE TABLE IF NOT EXISTS Fee_Rule_Tiers (
+    rule_id UUID NOT NULL REFERENCES Fee_Rules(rule_id),
+    tier_index INTEGER NOT NULL,
+    up_to DOUBLE PRECISION,
+    flat_amount DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (flat_amount >= 0),
+    percentage DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (percentage >= 0),
+    PRIMARY KEY (rule_id, tier_index)
+);
@@ -0,0 +1,47 @@
+-- A user who already took the interest account's name is renamed to make room.
+UPDATE Users SET username = username || '_' || LEFT(userid::TEXT, 8)
+WHERE username = 'payfree_interest' AND userid <> '00000000-0000-0000-0000-0000000001e7';
+
+-- Interest is paid from this account, which may go negative: its balance is
+-- the interest paid out so far. It cannot log in.
+INSERT INTO Users (userid, name, username, phno, address, balance, password_hash)
+VALUES ('00000000-0000-0000-0000-0000000001e7', 'Payfree Interest', 'payfree_interest',
+    'system:interest', 'system', 0, '!')
+ON CONFLICT (userid) DO NOTHING;
+
+ALTER TABLE Transactions DROP CONSTRAINT IF EXISTS transactions_kind_check;
+ALTER TABLE Transactions ADD CONSTRAINT transactions_kind_check
+    CHECK (kind IN ('p2p', 'request', 'scheduled', 'batch', 'hold', 'settlement', 'payout',
+        'interest'));
+
+-- annual_rate is a percentage, e.g. 3.5 for 3.5% a year.
+CREATE TABLE IF NOT EXISTS Interest_Products (
+    product_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    annual_rate DOUBLE PRECISION NOT NULL CHECK (annual_rate >= 0),
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS Interest_Enrollments (
+    userid UUID PRIMARY KEY REFERENCES Users(userid),
+    product_id TEXT NOT NULL REFERENCES Interest_Products(product_id),
+    enrolled_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One row per user and UTC day, so rerunning a day never accrues twice.
+-- payout_txn_id is set when the month's interest is paid.
+CREATE TABLE IF NOT EXISTS Interest_Accruals (
+    userid UUID NOT NULL REFERENCES Users(userid),
+    accrual_date DATE NOT NULL,
+    product_id TEXT NOT NULL REFERENCES Interest_Products(product_id),
+    annual_rate DOUBLE PRECISION NOT NULL,
+    balance DOUBLE PRECISION NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    payout_txn_id UUID REFERENCES Transactions(txn_id),
+    PRIMARY KEY (userid, accrual_date)
+);
+
+CREATE INDEX IF NOT EXISTS interest_accruals_unpaid_idx
+    ON Interest_Accruals (userid, accrual_date) WHERE payout_txn_id IS NULL;
@@ -0,0 +1,11 @@
+-- Lets viewer_userid read owner_userid's transactions, e.g. an accountant or
+-- a parent. Delegation is read-only and can be revoked by the owner.
+CREATE TABLE IF NOT EXISTS Viewer_Delegations (
+    owner_userid UUID NOT NULL REFERENCES Users(userid),
+    viewer_userid UUID NOT NULL REFERENCES Users(userid),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (owner_userid, viewer_userid),
+    CHECK (owner_userid <> viewer_userid)
+);
+
+CREATE INDEX IF NOT EXISTS viewer_delegations_viewer_idx ON Viewer_Delegations (viewer_userid);
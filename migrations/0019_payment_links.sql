@@ -0,0 +1,30 @@
+ALTER TABLE Transactions DROP CONSTRAINT IF EXISTS transactions_kind_check;
+ALTER TABLE Transactions ADD CONSTRAINT transactions_kind_check
+    CHECK (kind IN ('p2p', 'request', 'scheduled', 'batch', 'hold', 'settlement', 'payout',
+        'interest', 'link'));
+
+-- The signed token carries the same details; this row tracks whether the
+-- link can still be used. A NULL amount lets the payer choose it.
+CREATE TABLE IF NOT EXISTS Payment_Links (
+    link_id UUID PRIMARY KEY,
+    recipient_userid UUID NOT NULL REFERENCES Users(userid),
+    amount DOUBLE PRECISION CHECK (amount > 0),
+    memo TEXT,
+    multi_use BOOLEAN NOT NULL DEFAULT FALSE,
+    status TEXT NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'used', 'revoked')),
+    use_count INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS payment_links_recipient_idx
+    ON Payment_Links (recipient_userid, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS Payment_Link_Redemptions (
+    link_id UUID NOT NULL REFERENCES Payment_Links(link_id),
+    txn_id UUID PRIMARY KEY REFERENCES Transactions(txn_id),
+    payer_userid UUID NOT NULL REFERENCES Users(userid),
+    redeemed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS payment_link_redemptions_link_idx ON Payment_Link_Redemptions (link_id);
@@ -0,0 +1,51 @@
+-- A statement is a snapshot of one user's account for one calendar month,
+-- with period_end being the first day after it. Names and entries are copied
+-- at generation time and both tables reject changes, so a later correction
+-- shows up in the next statement instead of rewriting a past one.
+CREATE TABLE IF NOT EXISTS Statements (
+    statement_id UUID PRIMARY KEY,
+    userid UUID NOT NULL REFERENCES Users(userid),
+    username TEXT NOT NULL,
+    account_name TEXT NOT NULL,
+    period_start DATE NOT NULL,
+    period_end DATE NOT NULL,
+    opening_balance DOUBLE PRECISION NOT NULL,
+    closing_balance DOUBLE PRECISION NOT NULL,
+    total_in DOUBLE PRECISION NOT NULL,
+    total_out DOUBLE PRECISION NOT NULL,
+    generated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (userid, period_start)
+);
+
+-- amount is signed: negative for money leaving the account, fee included.
+CREATE TABLE IF NOT EXISTS Statement_Entries (
+    statement_id UUID NOT NULL REFERENCES Statements(statement_id),
+    line_no INTEGER NOT NULL,
+    txn_id UUID NOT NULL REFERENCES Transactions(txn_id),
+    posted_at TIMESTAMPTZ NOT NULL,
+    kind TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    counterparty_username TEXT NOT NULL,
+    memo TEXT,
+    amount DOUBLE PRECISION NOT NULL,
+    fee DOUBLE PRECISION NOT NULL,
+    balance_after DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (statement_id, line_no)
+);
+
+CREATE OR REPLACE FUNCTION reject_statement_changes() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'statements are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER statements_immutable
+    BEFORE UPDATE OR DELETE ON Statements
+    FOR EACH ROW EXECUTE FUNCTION reject_statement_changes();
+
+CREATE OR REPLACE TRIGGER statement_entries_immutable
+    BEFORE UPDATE OR DELETE ON Statement_Entries
+    FOR EACH ROW EXECUTE FUNCTION reject_statement_changes();
+
+CREATE INDEX IF NOT EXISTS transactions_to_userid_created_at_idx
+    ON Transactions (to_userid, created_at);
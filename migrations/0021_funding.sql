@@ -0,0 +1,40 @@
+-- A user who already took the funding account's name is renamed to make room.
+UPDATE Users SET username = username || '_' || LEFT(userid::TEXT, 8)
+WHERE username = 'payfree_funding' AND userid <> '00000000-0000-0000-0000-00000000f0d5';
+
+-- Money entering or leaving Payfree passes through this account, so its
+-- balance is withdrawals less deposits and usually negative. It cannot log in.
+INSERT INTO Users (userid, name, username, phno, address, balance, password_hash)
+VALUES ('00000000-0000-0000-0000-00000000f0d5', 'Payfree Funding', 'payfree_funding',
+    'system:funding', 'system', 0, '!')
+ON CONFLICT (userid) DO NOTHING;
+
+ALTER TABLE Transactions DROP CONSTRAINT IF EXISTS transactions_kind_check;
+ALTER TABLE Transactions ADD CONSTRAINT transactions_kind_check
+    CHECK (kind IN ('p2p', 'request', 'scheduled', 'batch', 'hold', 'settlement', 'payout',
+        'interest', 'link', 'deposit', 'withdrawal'));
+
+-- A deposit moves money when the provider confirms it. A withdrawal moves it
+-- when requested (txn_id) and moves it back if the provider fails it
+-- (return_txn_id). destination only keeps the last digits of the account.
+CREATE TABLE IF NOT EXISTS Funding_Transfers (
+    funding_id UUID PRIMARY KEY,
+    userid UUID NOT NULL REFERENCES Users(userid),
+    direction TEXT NOT NULL CHECK (direction IN ('deposit', 'withdrawal')),
+    amount DOUBLE PRECISION NOT NULL CHECK (amount > 0),
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'completed', 'failed')),
+    provider TEXT NOT NULL,
+    provider_ref TEXT,
+    destination TEXT,
+    failure_reason TEXT,
+    txn_id UUID REFERENCES Transactions(txn_id),
+    return_txn_id UUID REFERENCES Transactions(txn_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, provider_ref)
+);
+
+CREATE INDEX IF NOT EXISTS funding_transfers_userid_idx
+    ON Funding_Transfers (userid, created_at DESC);
+CREATE INDEX IF NOT EXISTS funding_transfers_pending_idx
+    ON Funding_Transfers (provider, created_at) WHERE status = 'pending';
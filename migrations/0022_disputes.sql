@@ -0,0 +1,41 @@
+ALTER TABLE Transactions DROP CONSTRAINT IF EXISTS transactions_kind_check;
+ALTER TABLE Transactions ADD CONSTRAINT transactions_kind_check
+    CHECK (kind IN ('p2p', 'request', 'scheduled', 'batch', 'hold', 'settlement', 'payout',
+        'interest', 'link', 'deposit', 'withdrawal', 'reversal'));
+
+-- One dispute per transaction, opened by its payer. While the dispute is
+-- open or under review, frozen_amount of the payee's balance cannot be spent.
+CREATE TABLE IF NOT EXISTS Disputes (
+    dispute_id UUID PRIMARY KEY,
+    txn_id UUID NOT NULL UNIQUE REFERENCES Transactions(txn_id),
+    payer_userid UUID NOT NULL REFERENCES Users(userid),
+    payee_userid UUID NOT NULL REFERENCES Users(userid),
+    amount DOUBLE PRECISION NOT NULL,
+    reason TEXT NOT NULL
+        CHECK (reason IN ('unauthorized', 'not_received', 'wrong_amount', 'duplicate', 'other')),
+    description TEXT,
+    status TEXT NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'under_review', 'resolved_payer', 'resolved_payee')),
+    frozen_amount DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (frozen_amount >= 0),
+    resolution_note TEXT,
+    resolved_by UUID REFERENCES Users(userid),
+    reversal_txn_id UUID REFERENCES Transactions(txn_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS disputes_payer_idx ON Disputes (payer_userid, created_at DESC);
+CREATE INDEX IF NOT EXISTS disputes_payee_idx ON Disputes (payee_userid, created_at DESC);
+CREATE INDEX IF NOT EXISTS disputes_frozen_idx ON Disputes (payee_userid)
+    WHERE status IN ('open', 'under_review') AND frozen_amount > 0;
+
+CREATE TABLE IF NOT EXISTS Dispute_Evidence (
+    evidence_id UUID PRIMARY KEY,
+    dispute_id UUID NOT NULL REFERENCES Disputes(dispute_id),
+    author_userid UUID NOT NULL REFERENCES Users(userid),
+    note TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS dispute_evidence_dispute_idx ON Dispute_Evidence (dispute_id, created_at);
@@ -0,0 +1,60 @@
+ALTER TABLE Transactions DROP CONSTRAINT IF EXISTS transactions_kind_check;
+ALTER TABLE Transactions ADD CONSTRAINT transactions_kind_check
+    CHECK (kind IN ('p2p', 'request', 'scheduled', 'batch', 'hold', 'settlement', 'payout',
+        'interest', 'link', 'deposit', 'withdrawal', 'reversal', 'invoice'));
+
+-- A user with a row here is a merchant. Unless settlement is manual, the
+-- balance above settlement_reserve is paid out to the bank account once per
+-- schedule period. The full account number is kept because the provider
+-- needs it; responses only show its last digits.
+CREATE TABLE IF NOT EXISTS Merchants (
+    userid UUID PRIMARY KEY REFERENCES Users(userid),
+    display_name TEXT NOT NULL,
+    settlement_schedule TEXT NOT NULL DEFAULT 'manual'
+        CHECK (settlement_schedule IN ('manual', 'daily', 'weekly')),
+    settlement_reserve DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (settlement_reserve >= 0),
+    account_number TEXT,
+    bank_code TEXT,
+    holder_name TEXT,
+    next_invoice_no INTEGER NOT NULL DEFAULT 1,
+    last_settled_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (settlement_schedule = 'manual' OR account_number IS NOT NULL)
+);
+
+-- Totals are stored as billed so later rounding changes cannot alter an
+-- issued invoice. 'overdue' is never stored: it is an open invoice past its
+-- due date.
+CREATE TABLE IF NOT EXISTS Invoices (
+    invoice_id UUID PRIMARY KEY,
+    merchant_userid UUID NOT NULL REFERENCES Merchants(userid),
+    invoice_no INTEGER NOT NULL,
+    customer_userid UUID NOT NULL REFERENCES Users(userid),
+    memo TEXT,
+    subtotal DOUBLE PRECISION NOT NULL CHECK (subtotal >= 0),
+    tax DOUBLE PRECISION NOT NULL CHECK (tax >= 0),
+    total DOUBLE PRECISION NOT NULL CHECK (total > 0),
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'paid', 'void')),
+    due_date DATE NOT NULL,
+    txn_id UUID REFERENCES Transactions(txn_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    paid_at TIMESTAMPTZ,
+    voided_at TIMESTAMPTZ,
+    UNIQUE (merchant_userid, invoice_no)
+);
+
+CREATE INDEX IF NOT EXISTS invoices_merchant_idx ON Invoices (merchant_userid, created_at DESC);
+CREATE INDEX IF NOT EXISTS invoices_customer_idx ON Invoices (customer_userid, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS Invoice_Items (
+    invoice_id UUID NOT NULL REFERENCES Invoices(invoice_id),
+    line_no INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    quantity DOUBLE PRECISION NOT NULL CHECK (quantity > 0),
+    unit_price DOUBLE PRECISION NOT NULL CHECK (unit_price >= 0),
+    tax_rate DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (tax_rate >= 0),
+    amount DOUBLE PRECISION NOT NULL,
+    tax DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (invoice_id, line_no)
+);
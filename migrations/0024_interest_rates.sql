@@ -0,0 +1,13 @@
+-- Every rate a product has had, so a day accrued late still earns the rate
+-- in force at its end. Rates from before this table existed are taken to
+-- have applied since the product was created.
+CREATE TABLE IF NOT EXISTS Interest_Rates (
+    product_id TEXT NOT NULL REFERENCES Interest_Products(product_id),
+    annual_rate DOUBLE PRECISION NOT NULL CHECK (annual_rate >= 0),
+    effective_from TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (product_id, effective_from)
+);
+
+INSERT INTO Interest_Rates (product_id, annual_rate, effective_from)
+SELECT product_id, annual_rate, created_at FROM Interest_Products
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,48 @@
+-- Each side's balance right after the transaction was posted. `time` comes
+-- from the client, so running balances cannot be worked out by ordering on
+-- it; they are recorded when the transfer is written instead. Existing rows
+-- are backfilled by walking back from the current balances in posting order.
+-- Rows from before 0005 all share the created_at that migration gave them, so
+-- their own `time` breaks the tie.
+ALTER TABLE Transactions
+    ADD COLUMN IF NOT EXISTS from_balance_after DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS to_balance_after DOUBLE PRECISION;
+
+WITH entries AS (
+    SELECT txn_id, from_userid AS userid, TRUE AS outgoing, created_at, time,
+        -(amount + fee) AS signed_amount
+    FROM Transactions
+    UNION ALL
+    SELECT txn_id, to_userid, FALSE, created_at, time, amount
+    FROM Transactions
+),
+running AS (
+    SELECT e.txn_id, e.outgoing,
+        u.balance - COALESCE(SUM(e.signed_amount) OVER (
+            PARTITION BY e.userid
+            ORDER BY e.created_at DESC, e.time DESC, e.txn_id DESC
+            ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+        ), 0) AS balance_after
+    FROM entries e
+    JOIN Users u ON u.userid = e.userid
+)
+UPDATE Transactions t
+SET from_balance_after = r.from_balance_after, to_balance_after = r.to_balance_after
+FROM (
+    SELECT txn_id,
+        MAX(balance_after) FILTER (WHERE outgoing) AS from_balance_after,
+        MAX(balance_after) FILTER (WHERE NOT outgoing) AS to_balance_after
+    FROM running
+    GROUP BY txn_id
+) r
+WHERE r.txn_id = t.txn_id AND t.from_balance_after IS NULL;
+
+ALTER TABLE Transactions
+    ALTER COLUMN from_balance_after SET NOT NULL,
+    ALTER COLUMN to_balance_after SET NOT NULL;
+
+-- History is paged in the same posting order the recorded balances follow.
+CREATE INDEX IF NOT EXISTS transactions_from_userid_posted_idx
+    ON Transactions (from_userid, created_at DESC, time DESC, txn_id DESC);
+CREATE INDEX IF NOT EXISTS transactions_to_userid_posted_idx
+    ON Transactions (to_userid, created_at DESC, time DESC, txn_id DESC);
@@ -0,0 +1,4 @@
+-- A delegation can be limited in time, e.g. for an accountant during tax
+-- season. NULL means it lasts until the owner revokes it; expired rows grant
+-- nothing and are kept until revoked.
+ALTER TABLE Viewer_Delegations ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
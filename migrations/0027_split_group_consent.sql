@@ -0,0 +1,16 @@
+-- Members added by someone else are only invited until they accept, so
+-- nobody can be put on the hook for a group's expenses without consent.
+-- Existing members count as accepted.
+ALTER TABLE Split_Group_Members ADD COLUMN IF NOT EXISTS accepted_at TIMESTAMPTZ DEFAULT NOW();
+ALTER TABLE Split_Group_Members ALTER COLUMN accepted_at DROP DEFAULT;
+
+-- A participant can contest their share of an expense. Contested expenses
+-- count towards nobody's balance until the contest is withdrawn.
+ALTER TABLE Split_Expense_Shares ADD COLUMN IF NOT EXISTS contested_at TIMESTAMPTZ;
+
+-- Settlements of a single expense share, as opposed to settling up the whole
+-- group. Each share can be settled once.
+ALTER TABLE Split_Settlements
+    ADD COLUMN IF NOT EXISTS expense_id UUID REFERENCES Split_Expenses(expense_id);
+CREATE UNIQUE INDEX IF NOT EXISTS split_settlements_expense_share_idx
+    ON Split_Settlements (expense_id, from_userid) WHERE expense_id IS NOT NULL;
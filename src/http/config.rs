@@ -0,0 +1,472 @@
+//! Server settings, loaded once at startup and shared with handlers as app
+//! data. Each setting comes from, in increasing priority: its default, the
+//! TOML file named by `PAYFREE_CONFIG` (or `payfree.toml` if it exists), and
+//! the environment. Secrets can also be read from files, e.g.
+//! `JWT_SECRET_FILE` or `jwt_secret_file`, so they stay out of the
+//! environment and the config file.
+
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Names the config file when set.
+pub const CONFIG_PATH_VAR: &str = "PAYFREE_CONFIG";
+/// Read when `PAYFREE_CONFIG` is unset, if it exists.
+pub const DEFAULT_CONFIG_PATH: &str = "payfree.toml";
+/// HS256 keys shorter than the hash output (256 bits) weaken the signature.
+pub const MIN_SECRET_LEN: usize = 32;
+
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 4040;
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 20;
+pub const DEFAULT_TOKEN_EXPIRY_SECS: u64 = 3600;
+pub const DEFAULT_SETTLE_AFTER_SECS: u64 = 30;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("{0} must be set")]
+    Missing(&'static str),
+    #[error("{key} {reason}")]
+    Invalid { key: &'static str, reason: String },
+}
+
+/// A value that is left out of `Debug` output, so it cannot end up in logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub url: Secret,
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Signs login tokens and payment links.
+    pub jwt_secret: Secret,
+    pub token_expiry_secs: u64,
+}
+
+/// Which funding provider moves money in and out of the platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FundingProviderKind {
+    /// Completes every transfer by itself. Only for development and tests:
+    /// it lets any user deposit money that never arrived.
+    Simulated,
+}
+
+impl FromStr for FundingProviderKind {
+    type Err = ();
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "simulated" => Ok(FundingProviderKind::Simulated),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FundingConfig {
+    /// Has no default, so a deployment cannot end up on the simulator by
+    /// leaving it out.
+    pub provider: FundingProviderKind,
+    /// Signs funding provider callbacks.
+    pub webhook_secret: Secret,
+    /// How long the simulated provider leaves transfers pending.
+    pub settle_after_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub funding: FundingConfig,
+}
+
+/// The config file's layout. Every key is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    server: FileServer,
+    database: FileDatabase,
+    auth: FileAuth,
+    funding: FileFunding,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileServer {
+    host: Option<String>,
+    port: Option<u16>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileDatabase {
+    url: Option<String>,
+    url_file: Option<PathBuf>,
+    max_connections: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileAuth {
+    jwt_secret: Option<String>,
+    jwt_secret_file: Option<PathBuf>,
+    token_expiry_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileFunding {
+    provider: Option<FundingProviderKind>,
+    webhook_secret: Option<String>,
+    webhook_secret_file: Option<PathBuf>,
+    settle_after_secs: Option<u64>,
+}
+
+fn read_secret_file(path: &Path) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path)
+        .map(|contents| contents.trim().to_string())
+        .map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Resolves a value the environment can override, reading the `_FILE`
+/// variant of either source when it is given. A source may name the value
+/// directly or a file, not both.
+fn resolve_secret(
+    env: &impl Fn(&str) -> Option<String>,
+    key: &'static str,
+    file_key: &'static str,
+    from_file: (Option<String>, Option<PathBuf>),
+) -> Result<Option<String>, ConfigError> {
+    let pick = |value: Option<String>, path: Option<PathBuf>| match (value, path) {
+        (Some(_), Some(_)) => Err(ConfigError::Invalid {
+            key,
+            reason: format!("and {} cannot both be set", file_key),
+        }),
+        (_, Some(path)) => read_secret_file(&path).map(Some),
+        (value, None) => Ok(value),
+    };
+    let set = |name| env(name).filter(|value: &String| !value.trim().is_empty());
+    let from_env = pick(set(key), set(file_key).map(PathBuf::from))?;
+    match from_env {
+        Some(value) => Ok(Some(value)),
+        None => pick(from_file.0, from_file.1),
+    }
+}
+
+fn parse_env<T: FromStr>(
+    env: &impl Fn(&str) -> Option<String>,
+    key: &'static str,
+) -> Result<Option<T>, ConfigError> {
+    env(key)
+        .map(|raw| {
+            raw.trim().parse().map_err(|_| ConfigError::Invalid {
+                key,
+                reason: format!("is not a valid number: {:?}", raw),
+            })
+        })
+        .transpose()
+}
+
+fn require_secret(key: &'static str, value: Option<String>) -> Result<Secret, ConfigError> {
+    let value = value.ok_or(ConfigError::Missing(key))?;
+    if value.len() < MIN_SECRET_LEN {
+        return Err(ConfigError::Invalid {
+            key,
+            reason: format!("must be at least {} bytes long", MIN_SECRET_LEN),
+        });
+    }
+    Ok(Secret(value))
+}
+
+impl Config {
+    /// Loads `.env`, then the config file and the environment, and checks
+    /// the result. The server refuses to start if this fails.
+    pub fn load() -> Result<Config, ConfigError> {
+        let _ = dotenvy::dotenv();
+        let path = std::env::var(CONFIG_PATH_VAR).ok().map(PathBuf::from);
+        let file = match path {
+            Some(path) => Some(
+                std::fs::read_to_string(&path)
+                    .map_err(|source| ConfigError::Read { path, source })?,
+            ),
+            None => std::fs::read_to_string(DEFAULT_CONFIG_PATH).ok(),
+        };
+        Config::from_sources(file.as_deref(), |key| std::env::var(key).ok())
+    }
+
+    /// Builds the config from a config file's contents and an environment
+    /// lookup.
+    pub fn from_sources(
+        file: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, ConfigError> {
+        let file: FileConfig = match file {
+            Some(contents) => toml::from_str(contents)?,
+            None => FileConfig::default(),
+        };
+
+        let host = env("PAYFREE_HOST")
+            .or(file.server.host)
+            .unwrap_or_else(|| DEFAULT_HOST.to_string());
+        let port = parse_env(&env, "PAYFREE_PORT")?
+            .or(file.server.port)
+            .unwrap_or(DEFAULT_PORT);
+        let database_url = resolve_secret(
+            &env,
+            "DATABASE_URL",
+            "DATABASE_URL_FILE",
+            (file.database.url, file.database.url_file),
+        )?
+        .filter(|url| !url.trim().is_empty())
+        .ok_or(ConfigError::Missing("DATABASE_URL"))?;
+        let max_connections = parse_env(&env, "DATABASE_MAX_CONNECTIONS")?
+            .or(file.database.max_connections)
+            .unwrap_or(DEFAULT_MAX_CONNECTIONS);
+        let jwt_secret = require_secret(
+            "JWT_SECRET",
+            resolve_secret(
+                &env,
+                "JWT_SECRET",
+                "JWT_SECRET_FILE",
+                (file.auth.jwt_secret, file.auth.jwt_secret_file),
+            )?,
+        )?;
+        let token_expiry_secs = parse_env(&env, "JWT_EXPIRY_SECS")?
+            .or(file.auth.token_expiry_secs)
+            .unwrap_or(DEFAULT_TOKEN_EXPIRY_SECS);
+        let provider = match env("FUNDING_PROVIDER") {
+            Some(raw) => Some(raw.trim().parse().map_err(|_| ConfigError::Invalid {
+                key: "FUNDING_PROVIDER",
+                reason: format!("is not a supported provider: {:?}", raw),
+            })?),
+            None => file.funding.provider,
+        }
+        .ok_or(ConfigError::Missing("FUNDING_PROVIDER"))?;
+        let webhook_secret = require_secret(
+            "FUNDING_WEBHOOK_SECRET",
+            resolve_secret(
+                &env,
+                "FUNDING_WEBHOOK_SECRET",
+                "FUNDING_WEBHOOK_SECRET_FILE",
+                (
+                    file.funding.webhook_secret,
+                    file.funding.webhook_secret_file,
+                ),
+            )?,
+        )?;
+        let settle_after_secs = parse_env(&env, "FUNDING_SETTLE_AFTER_SECS")?
+            .or(file.funding.settle_after_secs)
+            .unwrap_or(DEFAULT_SETTLE_AFTER_SECS);
+
+        let config = Config {
+            server: ServerConfig { host, port },
+            database: DatabaseConfig {
+                url: Secret(database_url),
+                max_connections,
+            },
+            auth: AuthConfig {
+                jwt_secret,
+                token_expiry_secs,
+            },
+            funding: FundingConfig {
+                provider,
+                webhook_secret,
+                settle_after_secs,
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key, reason: &str| {
+            Err(ConfigError::Invalid {
+                key,
+                reason: reason.to_string(),
+            })
+        };
+        if self.server.host.trim().is_empty() {
+            return invalid("PAYFREE_HOST", "must not be empty");
+        }
+        if self.server.port == 0 {
+            return invalid("PAYFREE_PORT", "must not be 0");
+        }
+        if self.database.max_connections == 0 {
+            return invalid("DATABASE_MAX_CONNECTIONS", "must be at least 1");
+        }
+        if self.auth.token_expiry_secs == 0 {
+            return invalid("JWT_EXPIRY_SECS", "must be at least 1");
+        }
+        if self.auth.jwt_secret == self.funding.webhook_secret {
+            return invalid("FUNDING_WEBHOOK_SECRET", "must differ from JWT_SECRET");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const JWT_SECRET: &str = "jwt_secret_for_config_tests_0123456789";
+    const WEBHOOK_SECRET: &str = "webhook_secret_for_config_tests_0123456789";
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_environment_overrides_file_and_defaults_fill_the_rest() {
+        let file = r#"
+            [server]
+            port = 8080
+
+            [database]
+            url = "postgres://file/payfree"
+            max_connections = 5
+
+            [auth]
+            token_expiry_secs = 600
+
+            [funding]
+            provider = "simulated"
+        "#;
+        let config = Config::from_sources(
+            Some(file),
+            env(&[
+                ("PAYFREE_PORT", "9090"),
+                ("JWT_SECRET", JWT_SECRET),
+                ("FUNDING_PROVIDER", "simulated"),
+                ("FUNDING_WEBHOOK_SECRET", WEBHOOK_SECRET),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.server.host, DEFAULT_HOST);
+        assert_eq!(config.server.port, 9090);
+        assert_eq!(config.database.url.expose(), "postgres://file/payfree");
+        assert_eq!(config.database.max_connections, 5);
+        assert_eq!(config.auth.token_expiry_secs, 600);
+        assert_eq!(config.funding.provider, FundingProviderKind::Simulated);
+        assert_eq!(config.funding.settle_after_secs, DEFAULT_SETTLE_AFTER_SECS);
+        assert!(!format!("{:?}", config).contains(JWT_SECRET));
+    }
+
+    #[test]
+    fn test_missing_or_weak_secrets_are_rejected() {
+        let base = [
+            ("DATABASE_URL", "postgres://env/payfree"),
+            ("FUNDING_PROVIDER", "simulated"),
+            ("FUNDING_WEBHOOK_SECRET", WEBHOOK_SECRET),
+        ];
+        assert!(matches!(
+            Config::from_sources(None, env(&base)),
+            Err(ConfigError::Missing("JWT_SECRET"))
+        ));
+        let weak = [base[0], base[1], base[2], ("JWT_SECRET", "dev_secret")];
+        assert!(matches!(
+            Config::from_sources(None, env(&weak)),
+            Err(ConfigError::Invalid {
+                key: "JWT_SECRET",
+                ..
+            })
+        ));
+        let unknown = "[auth]\njwt_secrett = \"typo\"";
+        assert!(matches!(
+            Config::from_sources(Some(unknown), env(&base)),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_funding_provider_must_be_chosen_explicitly() {
+        let base = [
+            ("DATABASE_URL", "postgres://env/payfree"),
+            ("JWT_SECRET", JWT_SECRET),
+            ("FUNDING_WEBHOOK_SECRET", WEBHOOK_SECRET),
+        ];
+        assert!(matches!(
+            Config::from_sources(None, env(&base)),
+            Err(ConfigError::Missing("FUNDING_PROVIDER"))
+        ));
+        let unknown = [base[0], base[1], base[2], ("FUNDING_PROVIDER", "stripe")];
+        assert!(matches!(
+            Config::from_sources(None, env(&unknown)),
+            Err(ConfigError::Invalid {
+                key: "FUNDING_PROVIDER",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_secret_files_are_read_and_trimmed() {
+        let path = std::env::temp_dir().join(format!("payfree_jwt_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, format!("{}\n", JWT_SECRET)).unwrap();
+        let path_str = path.to_str().unwrap();
+        let config = Config::from_sources(
+            None,
+            env(&[
+                ("DATABASE_URL", "postgres://env/payfree"),
+                ("JWT_SECRET_FILE", path_str),
+                ("FUNDING_PROVIDER", "simulated"),
+                ("FUNDING_WEBHOOK_SECRET", WEBHOOK_SECRET),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.auth.jwt_secret.expose(), JWT_SECRET);
+
+        let both = Config::from_sources(
+            None,
+            env(&[
+                ("DATABASE_URL", "postgres://env/payfree"),
+                ("JWT_SECRET", JWT_SECRET),
+                ("JWT_SECRET_FILE", path_str),
+                ("FUNDING_PROVIDER", "simulated"),
+                ("FUNDING_WEBHOOK_SECRET", WEBHOOK_SECRET),
+            ]),
+        );
+        assert!(matches!(both, Err(ConfigError::Invalid { .. })));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,2 +1,4 @@
 pub mod model;
 pub mod queries;
+#[cfg(test)]
+pub mod test_utils;
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::Executor;
 use sqlx::FromRow;
@@ -14,6 +14,8 @@ pub struct User {
     pub address: String,
     pub balance: f64,
     pub password_hash: String,
+    pub email: Option<String>,
+    pub discoverable: bool,
 }
 
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
@@ -23,6 +25,814 @@ pub struct Transaction {
     pub from_username: String,
     pub to_username: String,
     pub time: DateTime<Utc>,
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub kind: TransferKind,
+    /// Charged to the sender on top of `amount`. Worked out by `transfer`
+    /// from the fee rules; the value passed in is ignored.
+    #[serde(default)]
+    pub fee: f64,
+}
+
+/// What started a transfer. Fee rules are keyed by it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum TransferKind {
+    #[default]
+    P2p,
+    Request,
+    Scheduled,
+    Batch,
+    Hold,
+    Settlement,
+    Payout,
+    Interest,
+    Link,
+    Deposit,
+    Withdrawal,
+    Reversal,
+    Invoice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum TransferDirection {
+    Incoming,
+    Outgoing,
+}
+
+/// Filters and keyset position for a page of transaction history.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TransactionFilter {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub direction: Option<TransferDirection>,
+    pub counterparty: Option<String>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub tag: Option<String>,
+    pub category: Option<String>,
+}
+
+/// A transaction as seen from one participant's side of their history.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct TransactionEntry {
+    pub txn_id: Uuid,
+    pub amount: f64,
+    pub from_username: String,
+    pub to_username: String,
+    pub time: DateTime<Utc>,
+    /// When the server recorded the transaction; history is ordered by this.
+    pub posted_at: DateTime<Utc>,
+    pub kind: TransferKind,
+    pub fee: f64,
+    pub direction: TransferDirection,
+    pub counterparty_username: String,
+    pub counterparty_name: String,
+    /// Negative for money leaving the account, fee included.
+    pub signed_amount: f64,
+    /// The account balance right after this entry.
+    pub balance_after: f64,
+    pub memo: Option<String>,
+    /// The caller's own category and tags; never the counterparty's.
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionPage {
+    pub transactions: Vec<TransactionEntry>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum AccountStatus {
+    Active,
+    Frozen,
+    Closed,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct AccountState {
+    pub status: AccountStatus,
+    pub status_reason: Option<String>,
+    pub status_changed_at: Option<DateTime<Utc>>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+/// Ordered from the lowest tier to the highest.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum KycLevel {
+    Unverified,
+    Basic,
+    Verified,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct KycLimits {
+    pub kyc_level: KycLevel,
+    pub max_single_transfer: Option<f64>,
+    pub max_daily_outflow: Option<f64>,
+    pub max_balance: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum VerificationStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct KycVerification {
+    pub verification_id: Uuid,
+    pub userid: Uuid,
+    pub requested_level: KycLevel,
+    pub document_type: String,
+    pub document_number: String,
+    pub status: VerificationStatus,
+    pub notes: Option<String>,
+    pub submitted_by: String,
+    pub submitted_at: DateTime<Utc>,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+}
+
+/// A saved payee, or a recent counterparty that has not been saved yet
+/// (in which case `payee_id` is `None`).
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Payee {
+    pub payee_id: Option<Uuid>,
+    pub username: String,
+    pub name: String,
+    pub nickname: Option<String>,
+    pub favorite: bool,
+    pub last_transacted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Recipient {
+    pub username: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum RequestStatus {
+    Pending,
+    Approved,
+    Declined,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub request_id: Uuid,
+    pub requester_username: String,
+    pub payer_username: String,
+    pub amount: f64,
+    pub memo: Option<String>,
+    pub status: RequestStatus,
+    pub txn_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum ScheduleFrequency {
+    Once,
+    Daily,
+    Weekly,
+    Monthly,
+    Cron,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum ScheduleStatus {
+    Active,
+    Paused,
+    Completed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct ScheduledTransfer {
+    pub schedule_id: Uuid,
+    pub from_username: String,
+    pub to_username: String,
+    pub amount: f64,
+    pub frequency: ScheduleFrequency,
+    pub cron_expr: Option<String>,
+    pub start_at: DateTime<Utc>,
+    pub end_at: Option<DateTime<Utc>>,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub status: ScheduleStatus,
+    pub failure_count: i32,
+    pub max_retries: i32,
+    pub last_error: Option<String>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewSchedule {
+    pub to_username: String,
+    pub amount: f64,
+    pub frequency: ScheduleFrequency,
+    /// Required for, and only allowed with, the `cron` frequency.
+    pub cron_expr: Option<String>,
+    /// Defaults to now.
+    pub start_at: Option<DateTime<Utc>>,
+    pub end_at: Option<DateTime<Utc>>,
+    pub max_retries: Option<i32>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct ScheduledRun {
+    pub run_id: Uuid,
+    pub scheduled_for: DateTime<Utc>,
+    pub ran_at: DateTime<Utc>,
+    pub txn_id: Option<Uuid>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct SplitGroup {
+    pub group_id: Uuid,
+    pub name: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub members: Vec<String>,
+    /// Users added by a member who have not accepted yet. They take no part
+    /// in expenses until they do.
+    pub invited: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum SplitType {
+    Equal,
+    Percentage,
+    Exact,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShareInput {
+    pub username: String,
+    /// A percentage for percentage splits, an amount for exact splits, and
+    /// ignored for equal splits.
+    pub value: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewExpense {
+    pub amount: f64,
+    pub description: String,
+    /// Defaults to the member adding the expense.
+    pub paid_by: Option<String>,
+    pub split_type: SplitType,
+    /// Defaults to every member, which only suits equal splits.
+    pub shares: Option<Vec<ShareInput>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct ExpenseShare {
+    pub username: String,
+    pub amount: f64,
+    /// The participant disputes this expense, so it counts towards nobody's
+    /// balance.
+    pub contested: bool,
+    /// The participant paid this share on its own.
+    pub settled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupExpense {
+    pub expense_id: Uuid,
+    pub paid_by: String,
+    pub amount: f64,
+    pub description: String,
+    pub split_type: SplitType,
+    pub created_at: DateTime<Utc>,
+    pub shares: Vec<ExpenseShare>,
+}
+
+/// A member's position in a group: positive when others owe them.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MemberBalance {
+    pub username: String,
+    pub net: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Debt {
+    pub from_username: String,
+    pub to_username: String,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupBalances {
+    pub balances: Vec<MemberBalance>,
+    /// The fewest transfers that settle every balance.
+    pub debts: Vec<Debt>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchItem {
+    pub to_username: String,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewBatch {
+    /// Defaults to a fresh id. Reusing an id is rejected, so retries are safe.
+    pub batch_id: Option<Uuid>,
+    /// All-or-nothing when true; otherwise failed items are skipped.
+    #[serde(default = "default_atomic")]
+    pub atomic: bool,
+    pub items: Vec<BatchItem>,
+}
+
+fn default_atomic() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum BatchStatus {
+    Completed,
+    Partial,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Succeeded,
+    Failed,
+    /// Paid, then undone because a later item of an atomic batch failed.
+    RolledBack,
+    /// Not attempted because an earlier item of an atomic batch failed.
+    Skipped,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    pub item_index: i32,
+    pub to_username: String,
+    pub amount: f64,
+    pub status: BatchItemStatus,
+    pub txn_id: Option<Uuid>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct TransferBatch {
+    pub batch_id: Uuid,
+    pub from_username: String,
+    pub atomic: bool,
+    pub status: BatchStatus,
+    pub item_count: i32,
+    pub succeeded_count: i32,
+    pub total_amount: f64,
+    pub created_at: DateTime<Utc>,
+    #[sqlx(skip)]
+    pub items: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromRow, Serialize, Deserialize)]
+pub struct Balance {
+    pub ledger_balance: f64,
+    /// The ledger balance less active holds and funds frozen by disputes;
+    /// what can be spent right now.
+    pub available_balance: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum HoldStatus {
+    Active,
+    Captured,
+    Voided,
+    Expired,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Hold {
+    pub hold_id: Uuid,
+    pub payer_username: String,
+    pub payee_username: String,
+    pub amount: f64,
+    pub captured_amount: Option<f64>,
+    pub status: HoldStatus,
+    pub txn_id: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct TransactionAnnotation {
+    pub txn_id: Uuid,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A user's own outflow limits. `None` means no limit. The `pending_*`
+/// fields hold a loosening that takes over at `pending_effective_at`.
+#[derive(Debug, Clone, Default, FromRow, Serialize, Deserialize)]
+pub struct SpendingLimits {
+    pub max_per_transaction: Option<f64>,
+    pub max_daily: Option<f64>,
+    pub max_monthly: Option<f64>,
+    pub pending_max_per_transaction: Option<f64>,
+    pub pending_max_daily: Option<f64>,
+    pub pending_max_monthly: Option<f64>,
+    pub pending_effective_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NewSpendingLimits {
+    pub max_per_transaction: Option<f64>,
+    pub max_daily: Option<f64>,
+    pub max_monthly: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum FeeType {
+    Flat,
+    Percentage,
+    Tiered,
+}
+
+/// One bracket of a tiered fee: applies to amounts up to and including
+/// `up_to`, or to any amount when `up_to` is `None`.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub up_to: Option<f64>,
+    #[serde(default)]
+    pub flat_amount: f64,
+    #[serde(default)]
+    pub percentage: f64,
+}
+
+/// Applies to transfers of `kind` from senders at `kyc_level`, or at any
+/// level when `kyc_level` is `None`.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct FeeRule {
+    pub rule_id: Uuid,
+    pub kind: TransferKind,
+    pub kyc_level: Option<KycLevel>,
+    pub fee_type: FeeType,
+    pub flat_amount: f64,
+    pub percentage: f64,
+    pub min_fee: Option<f64>,
+    pub max_fee: Option<f64>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    #[sqlx(skip)]
+    pub tiers: Vec<FeeTier>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewFeeRule {
+    pub kind: TransferKind,
+    pub kyc_level: Option<KycLevel>,
+    pub fee_type: FeeType,
+    #[serde(default)]
+    pub flat_amount: f64,
+    #[serde(default)]
+    pub percentage: f64,
+    pub min_fee: Option<f64>,
+    pub max_fee: Option<f64>,
+    #[serde(default)]
+    pub tiers: Vec<FeeTier>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeQuote {
+    pub kind: TransferKind,
+    pub amount: f64,
+    pub fee: f64,
+    /// What the sender pays in total.
+    pub total: f64,
+    pub rule_id: Option<Uuid>,
+}
+
+/// A savings product. `annual_rate` is a percentage.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct InterestProduct {
+    pub product_id: String,
+    pub name: String,
+    pub annual_rate: f64,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewInterestProduct {
+    pub product_id: String,
+    pub name: String,
+    pub annual_rate: f64,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct InterestAccrual {
+    pub accrual_date: NaiveDate,
+    pub product_id: String,
+    pub annual_rate: f64,
+    /// The end-of-day balance interest was accrued on.
+    pub balance: f64,
+    pub amount: f64,
+    pub payout_txn_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterestSummary {
+    pub product_id: Option<String>,
+    pub annual_rate: Option<f64>,
+    pub enrolled_at: Option<DateTime<Utc>>,
+    /// Accrued but not yet paid out.
+    pub unpaid_interest: f64,
+    pub accruals: Vec<InterestAccrual>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct ViewerDelegation {
+    pub viewer_username: String,
+    pub viewer_name: String,
+    pub created_at: DateTime<Utc>,
+    /// `None` when the delegation lasts until revoked.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum PaymentLinkStatus {
+    Active,
+    Used,
+    Revoked,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct PaymentLink {
+    pub link_id: Uuid,
+    pub recipient_username: String,
+    pub recipient_name: String,
+    /// `None` lets the payer choose the amount.
+    pub amount: Option<f64>,
+    pub memo: Option<String>,
+    pub multi_use: bool,
+    pub status: PaymentLinkStatus,
+    pub use_count: i32,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A payment link with the signed token to share it by.
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedPaymentLink {
+    #[serde(flatten)]
+    pub link: PaymentLink,
+    pub token: String,
+    /// What the link's QR code encodes.
+    pub uri: String,
+}
+
+/// One line of a statement, as it stood when the statement was generated.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct StatementEntry {
+    pub txn_id: Uuid,
+    pub posted_at: DateTime<Utc>,
+    pub kind: TransferKind,
+    pub direction: TransferDirection,
+    pub counterparty_username: String,
+    pub memo: Option<String>,
+    /// Negative for money leaving the account, fee included.
+    pub amount: f64,
+    pub fee: f64,
+    pub balance_after: f64,
+}
+
+/// An immutable monthly account statement. `period_end` is the first day
+/// after the period.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Statement {
+    pub statement_id: Uuid,
+    pub username: String,
+    pub account_name: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub opening_balance: f64,
+    pub closing_balance: f64,
+    pub total_in: f64,
+    /// Fees included.
+    pub total_out: f64,
+    pub generated_at: DateTime<Utc>,
+    #[sqlx(skip)]
+    pub entries: Vec<StatementEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum FundingDirection {
+    Deposit,
+    Withdrawal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum FundingStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+/// Money moving between a Payfree account and the outside world through a
+/// funding provider.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct FundingTransfer {
+    pub funding_id: Uuid,
+    pub username: String,
+    pub direction: FundingDirection,
+    pub amount: f64,
+    pub status: FundingStatus,
+    pub provider: String,
+    pub provider_ref: Option<String>,
+    /// The masked bank account a withdrawal is paid to.
+    pub destination: Option<String>,
+    pub failure_reason: Option<String>,
+    pub txn_id: Option<Uuid>,
+    /// Returns a failed withdrawal's money to the account.
+    pub return_txn_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum DisputeReason {
+    Unauthorized,
+    NotReceived,
+    WrongAmount,
+    Duplicate,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum DisputeStatus {
+    Open,
+    UnderReview,
+    ResolvedPayer,
+    ResolvedPayee,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct DisputeEvidence {
+    pub evidence_id: Uuid,
+    pub author_username: String,
+    pub note: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Dispute {
+    pub dispute_id: Uuid,
+    pub txn_id: Uuid,
+    pub payer_username: String,
+    pub payee_username: String,
+    pub amount: f64,
+    pub reason: DisputeReason,
+    pub description: Option<String>,
+    pub status: DisputeStatus,
+    /// Payee funds that cannot be spent while the dispute is unresolved.
+    pub frozen_amount: f64,
+    pub resolution_note: Option<String>,
+    pub resolved_by: Option<String>,
+    /// Set when the dispute was resolved for the payer.
+    pub reversal_txn_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    #[sqlx(skip)]
+    pub evidence: Vec<DisputeEvidence>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum SettlementSchedule {
+    Manual,
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Merchant {
+    pub username: String,
+    pub display_name: String,
+    pub settlement_schedule: SettlementSchedule,
+    /// Balance kept back from every settlement.
+    pub settlement_reserve: f64,
+    /// The settlement bank account, showing only its last four characters.
+    pub settlement_destination: Option<String>,
+    pub last_settled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum InvoiceStatus {
+    Open,
+    /// Open and past its due date. It can still be paid.
+    Overdue,
+    Paid,
+    Void,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewInvoiceItem {
+    pub description: String,
+    pub quantity: f64,
+    pub unit_price: f64,
+    /// Percentage of the line amount charged as tax.
+    #[serde(default)]
+    pub tax_rate: f64,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct InvoiceItem {
+    pub line_no: i32,
+    pub description: String,
+    pub quantity: f64,
+    pub unit_price: f64,
+    pub tax_rate: f64,
+    /// `quantity * unit_price`, rounded to whole cents.
+    pub amount: f64,
+    pub tax: f64,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Invoice {
+    pub invoice_id: Uuid,
+    /// The merchant's own sequential number, e.g. `INV-000042`.
+    pub number: String,
+    pub merchant_username: String,
+    pub merchant_display_name: String,
+    pub customer_username: String,
+    pub memo: Option<String>,
+    pub subtotal: f64,
+    pub tax: f64,
+    pub total: f64,
+    pub status: InvoiceStatus,
+    pub due_date: NaiveDate,
+    /// The payment, once paid.
+    pub txn_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub paid_at: Option<DateTime<Utc>>,
+    pub voided_at: Option<DateTime<Utc>>,
+    #[sqlx(skip)]
+    pub items: Vec<InvoiceItem>,
 }
 
 pub async fn init_db(pool: &PgPool) -> anyhow::Result<()> {
@@ -46,8 +856,8 @@ pub async fn init_db(pool: &PgPool) -> anyhow::Result<()> {
         CREATE TABLE IF NOT EXISTS Transactions (
             txn_id UUID PRIMARY KEY,
             amount DOUBLE PRECISION NOT NULL,
-            from_username TEXT NOT NULL REFERENCES Users(username),
-            to_username TEXT NOT NULL REFERENCES Users(username),
+            from_userid UUID NOT NULL REFERENCES Users(userid),
+            to_userid UUID NOT NULL REFERENCES Users(userid),
             time TIMESTAMPTZ NOT NULL DEFAULT NOW()
         );
         "#,
@@ -1,38 +1,103 @@
-use crate::http::db::model::{Transaction, User};
+use crate::http::db::model::{
+    AccountStatus, Balance, Transaction, TransactionEntry, TransactionFilter, TransactionPage,
+    TransferDirection, TransferKind, User,
+};
 use crate::http::errors::{ApiError, Result};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, SecondsFormat, Utc};
 use log::debug;
-use sqlx::{PgPool, Row};
+use sqlx::{PgConnection, PgPool, Row};
 use uuid::Uuid;
 
+pub mod accounts;
+pub mod annotations;
+pub mod batches;
+pub mod delegations;
+pub mod discovery;
+pub mod disputes;
+pub mod fees;
+pub mod funding;
+pub mod groups;
+pub mod holds;
+pub mod interest;
+pub mod invoices;
+pub mod kyc;
+pub mod limits;
+pub mod links;
+pub mod merchants;
+pub mod payees;
+pub mod requests;
+pub mod schedules;
+pub mod statements;
+pub mod usernames;
+
+/// Accounts Payfree moves its own money through. They may go negative, pay
+/// no fees and are exempt from KYC and spending limits.
+pub const SYSTEM_ACCOUNTS: [Uuid; 3] = [
+    fees::FEE_ACCOUNT_ID,
+    interest::INTEREST_ACCOUNT_ID,
+    funding::FUNDING_ACCOUNT_ID,
+];
+
+pub fn is_system_account(userid: Uuid) -> bool {
+    SYSTEM_ACCOUNTS.contains(&userid)
+}
+
+/// Creates an account. Accounts always open empty, whatever `user.balance`
+/// says; money only arrives through deposits.
 pub async fn new_user(pool: &PgPool, user: &User) -> Result<()> {
     debug!("Inserting new user: {:?}", user.username);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    usernames::lock_usernames(&mut tx, &[&user.username]).await?;
     let result = sqlx::query(
         r#"
-        INSERT INTO users (userid, name, username, phno, address, balance, password_hash)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        INSERT INTO users
+            (userid, name, username, phno, address, balance, password_hash, email, discoverable)
+        SELECT $1, $2, $3, $4, $5, 0, $6, $7, $8
+        WHERE NOT EXISTS (
+            SELECT 1 FROM username_history WHERE old_username = $3 AND reserved_until > NOW()
+        )
         "#,
     )
-    .bind(&user.userid)
+    .bind(user.userid)
     .bind(&user.name)
     .bind(&user.username)
     .bind(&user.phno)
     .bind(&user.address)
-    .bind(user.balance)
     .bind(&user.password_hash)
-    .execute(pool)
+    .bind(&user.email)
+    .bind(user.discoverable)
+    .execute(&mut *tx)
     .await
-    .map(|_| ())
-    .map_err(ApiError::Database);
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => match db.constraint() {
+            Some("users_phno_key") => {
+                ApiError::Conflict("phone number is already registered".to_string())
+            }
+            Some("users_email_key") => {
+                ApiError::Conflict("email is already registered".to_string())
+            }
+            _ => ApiError::UsernameTaken,
+        },
+        e => ApiError::Database(e),
+    })
+    .and_then(|done| match done.rows_affected() {
+        0 => Err(ApiError::UsernameTaken),
+        _ => Ok(()),
+    });
     debug!("Insert user result: {:?}", result);
-    result
+    result?;
+    tx.commit().await.map_err(ApiError::Database)
 }
 
 pub async fn login(pool: &PgPool, username: &str) -> Result<Option<User>> {
     debug!("Fetching user for login: {:?}", username);
     let rec = sqlx::query(
         r#"
-        SELECT userid, name, username, phno, address, balance, password_hash FROM users WHERE username = $1
-        "#
+        SELECT userid, name, username, phno, address, balance, password_hash, email, discoverable
+        FROM users WHERE username = $1
+        "#,
     )
     .bind(username)
     .fetch_optional(pool)
@@ -50,6 +115,8 @@ pub async fn login(pool: &PgPool, username: &str) -> Result<Option<User>> {
                     address: row.get("address"),
                     balance: row.get("balance"),
                     password_hash: row.get("password_hash"),
+                    email: row.get("email"),
+                    discoverable: row.get("discoverable"),
                 };
                 Ok(Some(user))
             }
@@ -69,8 +136,9 @@ pub async fn fetch_profile(pool: &PgPool, username: &str) -> Result<Option<User>
     debug!("Fetching profile for user: {:?}", username);
     let rec = sqlx::query(
         r#"
-        SELECT userid, name, username, phno, address, balance, password_hash FROM users WHERE username = $1
-        "#
+        SELECT userid, name, username, phno, address, balance, password_hash, email, discoverable
+        FROM users WHERE username = $1
+        "#,
     )
     .bind(username)
     .fetch_optional(pool)
@@ -88,6 +156,8 @@ pub async fn fetch_profile(pool: &PgPool, username: &str) -> Result<Option<User>
                     address: row.get("address"),
                     balance: row.get("balance"),
                     password_hash: row.get("password_hash"),
+                    email: row.get("email"),
+                    discoverable: row.get("discoverable"),
                 };
                 Ok(Some(user))
             }
@@ -106,36 +176,200 @@ pub async fn fetch_profile(pool: &PgPool, username: &str) -> Result<Option<User>
     }
 }
 
-pub async fn fetch_transactions(pool: &PgPool, username: &str) -> Result<Vec<Transaction>> {
-    debug!("Fetching transactions for user: {:?}", username);
-    let rec = sqlx::query(
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// A position in a history: when the entry was posted, its own `time` and
+/// its id.
+type CursorKey = (DateTime<Utc>, DateTime<Utc>, Uuid);
+
+/// Encodes a keyset position as an opaque, URL-safe cursor.
+fn encode_cursor((posted_at, time, txn_id): CursorKey) -> String {
+    let key = format!(
+        "{}|{}|{}",
+        posted_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+        time.to_rfc3339_opts(SecondsFormat::Micros, true),
+        txn_id
+    );
+    URL_SAFE_NO_PAD.encode(key)
+}
+
+fn decode_cursor(cursor: &str) -> Result<CursorKey> {
+    let invalid = || ApiError::Validation("invalid cursor".to_string());
+    let parse_time = |time: &str| {
+        DateTime::parse_from_rfc3339(time)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|_| invalid())
+    };
+    let key = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let key = String::from_utf8(key).map_err(|_| invalid())?;
+    let mut parts = key.split('|');
+    let (Some(posted_at), Some(time), Some(txn_id), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+    let txn_id = Uuid::parse_str(txn_id).map_err(|_| invalid())?;
+    Ok((parse_time(posted_at)?, parse_time(time)?, txn_id))
+}
+
+/// Fetches one page of a user's history, most recently posted first.
+/// Outgoing and incoming transfers are read separately so each side walks
+/// its own `(userid, created_at, time, txn_id)` index, then merged.
+///
+/// Each entry carries the balance recorded right after it was posted. The
+/// history is ordered by that same server-side posting order rather than the
+/// client's `time`, which only breaks ties between rows posted before
+/// `created_at` existed, so consecutive entries reconcile line by line.
+pub async fn fetch_transactions(
+    pool: &PgPool,
+    username: &str,
+    filter: &TransactionFilter,
+) -> Result<TransactionPage> {
+    debug!(
+        "Fetching transactions for user: {:?} with {:?}",
+        username, filter
+    );
+    let limit = filter.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::Validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let cursor = filter.cursor.as_deref().map(decode_cursor).transpose()?;
+    let empty = TransactionPage {
+        transactions: Vec::new(),
+        next_cursor: None,
+    };
+
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let Some(userid) = sqlx::query(r#"SELECT userid FROM users WHERE username = $1"#)
+        .bind(username)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .map(|row| row.get::<Uuid, _>("userid"))
+    else {
+        return Ok(empty);
+    };
+    let counterparty = match &filter.counterparty {
+        Some(name) => match usernames::resolve_username(&mut conn, name).await? {
+            Some(id) => Some(id),
+            None => return Ok(empty),
+        },
+        None => None,
+    };
+    let tag = filter
+        .tag
+        .as_deref()
+        .map(annotations::sanitize_tag)
+        .transpose()?;
+    let category = filter
+        .category
+        .as_deref()
+        .map(annotations::sanitize_category)
+        .transpose()?;
+
+    let rec = sqlx::query_as::<_, TransactionEntry>(
         r#"
-        SELECT txn_id, amount, from_username, to_username, time FROM transactions
-        WHERE from_username = $1 OR to_username = $1
-        ORDER BY time DESC
+        WITH page AS (
+            SELECT * FROM (
+                (SELECT txn_id, amount, from_userid, to_userid, time, created_at, memo, kind, fee,
+                    from_balance_after, to_balance_after
+                FROM transactions
+                WHERE $10 AND from_userid = $1
+                    AND ($4::UUID IS NULL OR to_userid = $4)
+                    AND ($2::TIMESTAMPTZ IS NULL OR time >= $2)
+                    AND ($3::TIMESTAMPTZ IS NULL OR time < $3)
+                    AND ($5::FLOAT8 IS NULL OR amount >= $5)
+                    AND ($6::FLOAT8 IS NULL OR amount <= $6)
+                    AND ($7::TIMESTAMPTZ IS NULL OR (created_at, time, txn_id) < ($7, $14, $8))
+                    AND (($12::TEXT IS NULL AND $13::TEXT IS NULL) OR EXISTS (
+                        SELECT 1 FROM transaction_annotations a
+                        WHERE a.txn_id = transactions.txn_id AND a.userid = $1
+                            AND ($12::TEXT IS NULL OR a.category = $12)
+                            AND ($13::TEXT IS NULL OR $13 = ANY(a.tags))))
+                ORDER BY created_at DESC, time DESC, txn_id DESC
+                LIMIT $9)
+                UNION ALL
+                (SELECT txn_id, amount, from_userid, to_userid, time, created_at, memo, kind, fee,
+                    from_balance_after, to_balance_after
+                FROM transactions
+                WHERE $11 AND to_userid = $1
+                    AND ($4::UUID IS NULL OR from_userid = $4)
+                    AND ($2::TIMESTAMPTZ IS NULL OR time >= $2)
+                    AND ($3::TIMESTAMPTZ IS NULL OR time < $3)
+                    AND ($5::FLOAT8 IS NULL OR amount >= $5)
+                    AND ($6::FLOAT8 IS NULL OR amount <= $6)
+                    AND ($7::TIMESTAMPTZ IS NULL OR (created_at, time, txn_id) < ($7, $14, $8))
+                    AND (($12::TEXT IS NULL AND $13::TEXT IS NULL) OR EXISTS (
+                        SELECT 1 FROM transaction_annotations a
+                        WHERE a.txn_id = transactions.txn_id AND a.userid = $1
+                            AND ($12::TEXT IS NULL OR a.category = $12)
+                            AND ($13::TEXT IS NULL OR $13 = ANY(a.tags))))
+                ORDER BY created_at DESC, time DESC, txn_id DESC
+                LIMIT $9)
+            ) merged
+            ORDER BY created_at DESC, time DESC, txn_id DESC
+            LIMIT $9
+        )
+        SELECT p.txn_id, p.amount, f.username AS from_username, r.username AS to_username, p.time,
+            p.created_at AS posted_at,
+            p.kind, p.fee,
+            CASE WHEN p.from_userid = $1 THEN 'outgoing' ELSE 'incoming' END AS direction,
+            c.username AS counterparty_username,
+            c.name AS counterparty_name,
+            CASE WHEN p.from_userid = $1 THEN -(p.amount + p.fee) ELSE p.amount END AS signed_amount,
+            CASE WHEN p.from_userid = $1 THEN p.from_balance_after ELSE p.to_balance_after END
+                AS balance_after,
+            p.memo,
+            a.category,
+            COALESCE(a.tags, '{}') AS tags
+        FROM page p
+        LEFT JOIN transaction_annotations a ON a.txn_id = p.txn_id AND a.userid = $1
+        JOIN users f ON f.userid = p.from_userid
+        JOIN users r ON r.userid = p.to_userid
+        JOIN users c ON c.userid = CASE WHEN p.from_userid = $1 THEN p.to_userid ELSE p.from_userid END
+        ORDER BY p.created_at DESC, p.time DESC, p.txn_id DESC
         "#,
     )
-    .bind(username)
-    .fetch_all(pool)
+    .bind(userid)
+    .bind(filter.from)
+    .bind(filter.to)
+    .bind(counterparty)
+    .bind(filter.min_amount)
+    .bind(filter.max_amount)
+    .bind(cursor.map(|(posted_at, _, _)| posted_at))
+    .bind(cursor.map(|(_, _, txn_id)| txn_id))
+    .bind(limit + 1)
+    .bind(filter.direction != Some(TransferDirection::Incoming))
+    .bind(filter.direction != Some(TransferDirection::Outgoing))
+    .bind(category)
+    .bind(tag)
+    .bind(cursor.map(|(_, time, _)| time))
+    .fetch_all(&mut *conn)
     .await;
     match rec {
-        Ok(rows) => {
+        Ok(mut transactions) => {
             debug!(
                 "Fetched {} transactions for user: {:?}",
-                rows.len(),
+                transactions.len(),
                 username
             );
-            let transactions = rows
-                .into_iter()
-                .map(|row| Transaction {
-                    txn_id: row.get("txn_id"),
-                    amount: row.get("amount"),
-                    from_username: row.get("from_username"),
-                    to_username: row.get("to_username"),
-                    time: row.get("time"),
-                })
-                .collect();
-            Ok(transactions)
+            // One extra row was fetched to tell whether another page exists.
+            let next_cursor = if transactions.len() as i64 > limit {
+                transactions.truncate(limit as usize);
+                transactions
+                    .last()
+                    .map(|txn| encode_cursor((txn.posted_at, txn.time, txn.txn_id)))
+            } else {
+                None
+            };
+            Ok(TransactionPage {
+                transactions,
+                next_cursor,
+            })
         }
         Err(e) => {
             debug!(
@@ -147,19 +381,26 @@ pub async fn fetch_transactions(pool: &PgPool, username: &str) -> Result<Vec<Tra
     }
 }
 
-pub async fn fetch_balance(pool: &PgPool, username: &str) -> Result<Option<f64>> {
+pub async fn fetch_balance(pool: &PgPool, username: &str) -> Result<Option<Balance>> {
     debug!("Fetching balance for user: {:?}", username);
-    let rec = sqlx::query(
+    let balance = sqlx::query_as::<_, Balance>(
         r#"
-        SELECT balance FROM users WHERE username = $1
+        SELECT u.balance AS ledger_balance,
+            u.balance - COALESCE((
+                SELECT SUM(h.amount) FROM holds h
+                WHERE h.payer_userid = u.userid AND h.status = 'active' AND h.expires_at > NOW()
+            ), 0) - COALESCE((
+                SELECT SUM(d.frozen_amount) FROM disputes d
+                WHERE d.payee_userid = u.userid AND d.status IN ('open', 'under_review')
+            ), 0) AS available_balance
+        FROM users u
+        WHERE u.username = $1
         "#,
     )
     .bind(username)
     .fetch_optional(pool)
     .await
     .map_err(ApiError::Database)?;
-
-    let balance = rec.and_then(|row| row.try_get("balance").ok());
     debug!("Fetched balance for user {:?}: {:?}", username, balance);
     Ok(balance)
 }
@@ -167,66 +408,222 @@ pub async fn fetch_balance(pool: &PgPool, username: &str) -> Result<Option<f64>>
 pub async fn insert_transaction(pool: &PgPool, txn: &Transaction) -> Result<()> {
     debug!("Inserting transaction: {:?}", txn);
     let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    transfer(&mut tx, txn).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(())
+}
 
-    let sender_balance = sqlx::query(r#"SELECT balance FROM users WHERE username = $1"#)
+/// Locks both accounts of a transfer in userid order, so that transfers in
+/// opposite directions cannot deadlock, and returns the balance and status
+/// of `first` and then `second`. A transfer that charges a fee locks the fee
+/// account in the same ordered set, rather than on its own afterwards.
+async fn lock_accounts(
+    conn: &mut PgConnection,
+    first: Uuid,
+    second: Uuid,
+    with_fee_account: bool,
+) -> Result<[(f64, AccountStatus); 2]> {
+    let mut userids = vec![first, second];
+    if with_fee_account {
+        userids.push(fees::FEE_ACCOUNT_ID);
+    }
+    let rows = sqlx::query(
+        r#"SELECT userid, balance, status FROM users WHERE userid = ANY($1) ORDER BY userid FOR UPDATE"#,
+    )
+    .bind(userids)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    let find = |userid: Uuid| {
+        rows.iter()
+            .find(|row| row.get::<Uuid, _>("userid") == userid)
+            .map(|row| (row.get("balance"), row.get("status")))
+            .ok_or(ApiError::UserNotFound)
+    };
+    Ok([find(first)?, find(second)?])
+}
+
+/// Moves money between two accounts inside the caller's database
+/// transaction, so it can be combined atomically with other writes.
+pub async fn transfer(conn: &mut PgConnection, txn: &Transaction) -> Result<()> {
+    if !txn.amount.is_finite() || txn.amount <= 0.0 {
+        return Err(ApiError::Validation(
+            "amount must be greater than zero".to_string(),
+        ));
+    }
+    let sender_id: Uuid = sqlx::query(r#"SELECT userid FROM users WHERE username = $1"#)
         .bind(&txn.from_username)
-        .fetch_optional(&mut *tx)
+        .fetch_optional(&mut *conn)
         .await
-        .map_err(ApiError::Database)?;
-    let sender_balance: f64 = match sender_balance {
-        Some(balance) => balance.get("balance"),
-        None => return Err(ApiError::UserNotFound),
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?
+        .get("userid");
+    let receiver_id = match usernames::resolve_username(&mut *conn, &txn.to_username).await? {
+        Some(userid) => userid,
+        None => {
+            debug!("Receiver not found for transaction: {:?}", txn);
+            return Err(ApiError::UserNotFound);
+        }
+    };
+    if sender_id == receiver_id {
+        return Err(ApiError::Validation(
+            "cannot transfer to the same account".to_string(),
+        ));
+    }
+    // Users only pay into a system account by withdrawing to the funding
+    // account; anything else would throw off the system balances.
+    let system_sender = is_system_account(sender_id);
+    if is_system_account(receiver_id)
+        && !system_sender
+        && !(receiver_id == funding::FUNDING_ACCOUNT_ID && txn.kind == TransferKind::Withdrawal)
+    {
+        debug!("Rejecting payment into a system account: {:?}", txn);
+        return Err(ApiError::UserNotFound);
+    }
+
+    let fee = if system_sender {
+        0.0
+    } else {
+        fees::quote_fee(&mut *conn, sender_id, txn.kind, txn.amount)
+            .await?
+            .fee
     };
+    let [(sender_balance, sender_status), (_, receiver_status)] =
+        lock_accounts(&mut *conn, sender_id, receiver_id, fee > 0.0).await?;
     debug!(
         "Sender balance for {}: {}",
         txn.from_username, sender_balance
     );
+    ensure_active(sender_status)?;
+    ensure_active(receiver_status)?;
 
-    if sender_balance < txn.amount {
-        debug!("Insufficient balance for transaction: {:?}", txn);
-        return Err(ApiError::BalanceLow);
+    if !system_sender {
+        let held = holds::held_amount(&mut *conn, sender_id).await?;
+        if sender_balance - held < txn.amount + fee {
+            debug!(
+                "Insufficient available balance for transaction: {:?} ({} held, {} fee)",
+                txn, held, fee
+            );
+            return Err(ApiError::BalanceLow);
+        }
+        limits::enforce_spending_limits(&mut *conn, sender_id, txn.amount, fee).await?;
     }
+    kyc::enforce_kyc_limits(&mut *conn, sender_id, receiver_id, txn.amount, fee).await?;
 
-    sqlx::query(r#"UPDATE users SET balance = balance - $1 WHERE username = $2"#)
-        .bind(txn.amount)
-        .bind(&txn.from_username)
-        .execute(&mut *tx)
-        .await
-        .map_err(ApiError::Database)?;
+    let sender_balance_after = debit(&mut *conn, sender_id, txn.amount + fee).await?;
+    let receiver_balance_after = credit(&mut *conn, receiver_id, txn.amount).await?;
 
-    sqlx::query(r#"UPDATE users SET balance = balance + $1 WHERE username = $2"#)
-        .bind(txn.amount)
-        .bind(&txn.to_username)
-        .execute(&mut *tx)
-        .await
-        .map_err(ApiError::Database)?;
+    if fee > 0.0 {
+        fees::collect_fee(&mut *conn, fee).await?;
+    }
 
     let insert_result = sqlx::query(
         r#"
-        INSERT INTO transactions (txn_id, amount, from_username, to_username, time)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO transactions (txn_id, amount, from_userid, to_userid, time, memo, kind, fee,
+            from_balance_after, to_balance_after)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
         "#,
     )
-    .bind(&txn.txn_id)
+    .bind(txn.txn_id)
     .bind(txn.amount)
-    .bind(&txn.from_username)
-    .bind(&txn.to_username)
+    .bind(sender_id)
+    .bind(receiver_id)
     .bind(txn.time)
-    .execute(&mut *tx)
+    .bind(txn.memo.as_deref())
+    .bind(txn.kind)
+    .bind(fee)
+    .bind(sender_balance_after)
+    .bind(receiver_balance_after)
+    .execute(&mut *conn)
     .await
     .map_err(ApiError::Database);
 
     debug!("Insert transaction result: {:?}", insert_result);
-
-    tx.commit().await.map_err(ApiError::Database)?;
+    insert_result?;
     Ok(())
 }
 
+/// Moves `amount` of a completed payment back from its payee to its payer
+/// and returns the `reversal` transaction. It is not a payment by the payee,
+/// so it skips their holds and limits and can leave their balance negative,
+/// but the payer must be able to receive it like any other transfer.
+pub async fn reverse_payment(
+    conn: &mut PgConnection,
+    payee: Uuid,
+    payer: Uuid,
+    amount: f64,
+    memo: &str,
+) -> Result<Uuid> {
+    let [_, (_, payer_status)] = lock_accounts(&mut *conn, payee, payer, false).await?;
+    ensure_active(payer_status)?;
+    kyc::enforce_receiver_limits(&mut *conn, payer, amount).await?;
+
+    let payee_balance_after = debit(&mut *conn, payee, amount).await?;
+    let payer_balance_after = credit(&mut *conn, payer, amount).await?;
+    let txn_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (txn_id, amount, from_userid, to_userid, time, memo, kind,
+            from_balance_after, to_balance_after)
+        VALUES ($1, $2, $3, $4, NOW(), $5, 'reversal', $6, $7)
+        "#,
+    )
+    .bind(txn_id)
+    .bind(amount)
+    .bind(payee)
+    .bind(payer)
+    .bind(memo)
+    .bind(payee_balance_after)
+    .bind(payer_balance_after)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(txn_id)
+}
+
+/// Takes `amount` off an account and returns its new balance, which the
+/// caller records on the transaction row.
+pub(crate) async fn debit(conn: &mut PgConnection, userid: Uuid, amount: f64) -> Result<f64> {
+    adjust_balance(conn, userid, -amount).await
+}
+
+/// Adds `amount` to an account and returns its new balance.
+pub(crate) async fn credit(conn: &mut PgConnection, userid: Uuid, amount: f64) -> Result<f64> {
+    adjust_balance(conn, userid, amount).await
+}
+
+async fn adjust_balance(conn: &mut PgConnection, userid: Uuid, delta: f64) -> Result<f64> {
+    let balance: f64 = sqlx::query(
+        r#"UPDATE users SET balance = balance + $1 WHERE userid = $2 RETURNING balance"#,
+    )
+    .bind(delta)
+    .bind(userid)
+    .fetch_optional(conn)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::UserNotFound)?
+    .get("balance");
+    Ok(balance)
+}
+
+fn ensure_active(status: AccountStatus) -> Result<()> {
+    match status {
+        AccountStatus::Active => Ok(()),
+        AccountStatus::Frozen => Err(ApiError::AccountFrozen),
+        AccountStatus::Closed => Err(ApiError::AccountClosed),
+    }
+}
+
 pub async fn fetch_transaction(pool: &PgPool, txn_id: Uuid) -> Result<Option<Transaction>> {
     debug!("Fetching transaction by txn_id: {:?}", txn_id);
     let rec = sqlx::query(
         r#"
-        SELECT txn_id, amount, from_username, to_username, time FROM transactions WHERE txn_id = $1
+        SELECT t.txn_id, t.amount, f.username AS from_username, r.username AS to_username, t.time,
+            t.memo, t.kind, t.fee
+        FROM transactions t
+        JOIN users f ON f.userid = t.from_userid
+        JOIN users r ON r.userid = t.to_userid
+        WHERE t.txn_id = $1
         "#,
     )
     .bind(txn_id)
@@ -241,6 +638,9 @@ pub async fn fetch_transaction(pool: &PgPool, txn_id: Uuid) -> Result<Option<Tra
                 let from_username = row.try_get("from_username")?;
                 let to_username = row.try_get("to_username")?;
                 let time = row.try_get("time")?;
+                let memo = row.try_get("memo")?;
+                let kind = row.try_get("kind")?;
+                let fee = row.try_get("fee")?;
 
                 debug!("Transaction found: {:?}", txn_id);
 
@@ -250,6 +650,9 @@ pub async fn fetch_transaction(pool: &PgPool, txn_id: Uuid) -> Result<Option<Tra
                     from_username,
                     to_username,
                     time,
+                    memo,
+                    kind,
+                    fee,
                 }))
             }
             None => {
@@ -270,7 +673,8 @@ pub async fn fetch_transaction(pool: &PgPool, txn_id: Uuid) -> Result<Option<Tra
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Utc;
+    use crate::http::db::model::TransferKind;
+    use crate::http::db::test_utils::{create_test_user, phone_number, set_balance, transaction};
     use sqlx::{PgPool, postgres::PgPoolOptions};
     use uuid::Uuid;
 
@@ -291,10 +695,12 @@ mod tests {
             userid: Uuid::new_v4(),
             name: "Test User".to_string(),
             username: username.clone(),
-            phno: "1234567890".to_string(),
+            phno: phone_number(),
             address: "Test Address".to_string(),
             balance: 100.0,
             password_hash: "hash".to_string(),
+            email: None,
+            discoverable: false,
         };
         // Insert user
         let res = new_user(&pool, &user).await;
@@ -314,18 +720,25 @@ mod tests {
             userid: Uuid::new_v4(),
             name: "Test User".to_string(),
             username: username.clone(),
-            phno: "1234567890".to_string(),
+            phno: phone_number(),
             address: "Test Address".to_string(),
             balance: 123.45,
             password_hash: "hash".to_string(),
+            email: None,
+            discoverable: false,
         };
         new_user(&pool, &user).await.unwrap();
 
+        // Accounts open empty whatever balance the caller asks for.
         let profile = fetch_profile(&pool, &username).await.unwrap();
         assert!(profile.is_some());
-        assert_eq!(profile.as_ref().unwrap().balance, 123.45);
+        assert_eq!(profile.as_ref().unwrap().balance, 0.0);
 
-        let balance = fetch_balance(&pool, &username).await.unwrap();
+        set_balance(&pool, user.userid, 123.45).await;
+        let balance = fetch_balance(&pool, &username)
+            .await
+            .unwrap()
+            .map(|b| b.ledger_balance);
         assert_eq!(balance, Some(123.45));
     }
 
@@ -336,22 +749,28 @@ mod tests {
             userid: Uuid::new_v4(),
             name: "Sender".to_string(),
             username: format!("sender_{}", Uuid::new_v4()),
-            phno: "1111111111".to_string(),
+            phno: phone_number(),
             address: "Sender Address".to_string(),
             balance: 500.0,
             password_hash: "hash".to_string(),
+            email: None,
+            discoverable: false,
         };
         let user2 = User {
             userid: Uuid::new_v4(),
             name: "Receiver".to_string(),
             username: format!("receiver_{}", Uuid::new_v4()),
-            phno: "2222222222".to_string(),
+            phno: phone_number(),
             address: "Receiver Address".to_string(),
             balance: 100.0,
             password_hash: "hash".to_string(),
+            email: None,
+            discoverable: false,
         };
         new_user(&pool, &user1).await.unwrap();
         new_user(&pool, &user2).await.unwrap();
+        set_balance(&pool, user1.userid, user1.balance).await;
+        set_balance(&pool, user2.userid, user2.balance).await;
 
         let txn_id = Uuid::new_v4();
         let txn = Transaction {
@@ -360,6 +779,9 @@ mod tests {
             from_username: user1.username.clone(),
             to_username: user2.username.clone(),
             time: Utc::now(),
+            memo: None,
+            kind: TransferKind::P2p,
+            fee: 0.0,
         };
         let res = insert_transaction(&pool, &txn).await;
         assert!(res.is_ok());
@@ -371,8 +793,10 @@ mod tests {
         assert_eq!(fetched_txn.from_username, user1.username);
         assert_eq!(fetched_txn.to_username, user2.username);
 
-        let txns = fetch_transactions(&pool, &user1.username).await.unwrap();
-        assert!(txns.iter().any(|t| t.txn_id == txn_id));
+        let page = fetch_transactions(&pool, &user1.username, &TransactionFilter::default())
+            .await
+            .unwrap();
+        assert!(page.transactions.iter().any(|t| t.txn_id == txn_id));
     }
 
     #[tokio::test]
@@ -382,22 +806,28 @@ mod tests {
             userid: Uuid::new_v4(),
             name: "Sender".to_string(),
             username: format!("sender2_{}", Uuid::new_v4()),
-            phno: "1111111111".to_string(),
+            phno: phone_number(),
             address: "Sender Address".to_string(),
             balance: 10.0,
             password_hash: "hash".to_string(),
+            email: None,
+            discoverable: false,
         };
         let user2 = User {
             userid: Uuid::new_v4(),
             name: "Receiver".to_string(),
             username: format!("receiver2_{}", Uuid::new_v4()),
-            phno: "2222222222".to_string(),
+            phno: phone_number(),
             address: "Receiver Address".to_string(),
             balance: 100.0,
             password_hash: "hash".to_string(),
+            email: None,
+            discoverable: false,
         };
         new_user(&pool, &user1).await.unwrap();
         new_user(&pool, &user2).await.unwrap();
+        set_balance(&pool, user1.userid, user1.balance).await;
+        set_balance(&pool, user2.userid, user2.balance).await;
 
         let txn = Transaction {
             txn_id: Uuid::new_v4(),
@@ -405,8 +835,193 @@ mod tests {
             from_username: user1.username.clone(),
             to_username: user2.username.clone(),
             time: Utc::now(),
+            memo: None,
+            kind: TransferKind::P2p,
+            fee: 0.0,
         };
         let res = insert_transaction(&pool, &txn).await;
         assert!(matches!(res, Err(ApiError::BalanceLow)));
     }
+
+    #[tokio::test]
+    async fn test_transaction_to_unknown_user_is_rejected() {
+        let pool = setup_test_db().await;
+        let sender = User {
+            userid: Uuid::new_v4(),
+            name: "Sender".to_string(),
+            username: format!("sender3_{}", Uuid::new_v4()),
+            phno: phone_number(),
+            address: "Sender Address".to_string(),
+            balance: 50.0,
+            password_hash: "hash".to_string(),
+            email: None,
+            discoverable: false,
+        };
+        new_user(&pool, &sender).await.unwrap();
+        set_balance(&pool, sender.userid, sender.balance).await;
+
+        let txn = Transaction {
+            txn_id: Uuid::new_v4(),
+            amount: 20.0,
+            from_username: sender.username.clone(),
+            to_username: format!("ghost_{}", Uuid::new_v4()),
+            time: Utc::now(),
+            memo: None,
+            kind: TransferKind::P2p,
+            fee: 0.0,
+        };
+        let res = insert_transaction(&pool, &txn).await;
+        assert!(matches!(res, Err(ApiError::UserNotFound)));
+
+        let balance = fetch_balance(&pool, &sender.username)
+            .await
+            .unwrap()
+            .map(|b| b.ledger_balance);
+        assert_eq!(balance, Some(50.0));
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let posted_at = Utc::now();
+        let time = posted_at - chrono::Duration::days(1);
+        let txn_id = Uuid::new_v4();
+        let (decoded_posted_at, decoded_time, decoded_id) =
+            decode_cursor(&encode_cursor((posted_at, time, txn_id))).unwrap();
+        assert_eq!(
+            decoded_posted_at.timestamp_micros(),
+            posted_at.timestamp_micros()
+        );
+        assert_eq!(decoded_time.timestamp_micros(), time.timestamp_micros());
+        assert_eq!(decoded_id, txn_id);
+        assert!(matches!(
+            decode_cursor("not a cursor"),
+            Err(ApiError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_transactions_pages_and_filters() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let carol = create_test_user(&pool, "carol", 100.0).await;
+        let base = Utc::now();
+        for (i, (from, to)) in [
+            (&alice, &bob),
+            (&bob, &alice),
+            (&alice, &carol),
+            (&carol, &alice),
+            (&alice, &bob),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut txn = transaction(&from.username, &to.username, (i + 1) as f64);
+            txn.time = base + chrono::Duration::seconds(i as i64);
+            insert_transaction(&pool, &txn).await.unwrap();
+        }
+
+        let mut filter = TransactionFilter {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let mut amounts = Vec::new();
+        loop {
+            let page = fetch_transactions(&pool, &alice.username, &filter)
+                .await
+                .unwrap();
+            assert!(page.transactions.len() <= 2);
+            amounts.extend(page.transactions.iter().map(|t| t.amount));
+            match page.next_cursor {
+                Some(cursor) => filter.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(amounts, vec![5.0, 4.0, 3.0, 2.0, 1.0]);
+
+        let outgoing_to_bob = TransactionFilter {
+            direction: Some(TransferDirection::Outgoing),
+            counterparty: Some(bob.username.clone()),
+            ..Default::default()
+        };
+        let page = fetch_transactions(&pool, &alice.username, &outgoing_to_bob)
+            .await
+            .unwrap();
+        let amounts: Vec<f64> = page.transactions.iter().map(|t| t.amount).collect();
+        assert_eq!(amounts, vec![5.0, 1.0]);
+
+        let incoming_range = TransactionFilter {
+            direction: Some(TransferDirection::Incoming),
+            min_amount: Some(3.0),
+            from: Some(base),
+            ..Default::default()
+        };
+        let page = fetch_transactions(&pool, &alice.username, &incoming_range)
+            .await
+            .unwrap();
+        let amounts: Vec<f64> = page.transactions.iter().map(|t| t.amount).collect();
+        assert_eq!(amounts, vec![4.0]);
+    }
+
+    #[tokio::test]
+    async fn test_history_entries_reconcile_with_balance() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let base = Utc::now();
+        for (i, (from, to, amount)) in [
+            (&alice, &bob, 30.0),
+            (&bob, &alice, 10.0),
+            (&alice, &bob, 5.0),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut txn = transaction(&from.username, &to.username, amount);
+            txn.time = base + chrono::Duration::seconds(i as i64);
+            insert_transaction(&pool, &txn).await.unwrap();
+        }
+
+        let page = fetch_transactions(&pool, &alice.username, &TransactionFilter::default())
+            .await
+            .unwrap();
+        let entries = &page.transactions;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].direction, TransferDirection::Outgoing);
+        assert_eq!(entries[0].counterparty_username, bob.username);
+        assert_eq!(entries[0].counterparty_name, bob.name);
+        assert_eq!(entries[1].signed_amount, 10.0);
+        let balances: Vec<f64> = entries.iter().map(|e| e.balance_after).collect();
+        assert_eq!(balances, vec![75.0, 80.0, 70.0]);
+
+        // Filtering out entries must not change the running balance of the rest.
+        let incoming = TransactionFilter {
+            direction: Some(TransferDirection::Incoming),
+            ..Default::default()
+        };
+        let page = fetch_transactions(&pool, &alice.username, &incoming)
+            .await
+            .unwrap();
+        assert_eq!(page.transactions.len(), 1);
+        assert_eq!(page.transactions[0].balance_after, 80.0);
+
+        // A backdated transfer is listed when it was posted, not by its
+        // client-supplied time, so the chain still reconciles line by line.
+        let mut backdated = transaction(&alice.username, &bob.username, 20.0);
+        backdated.time = base - chrono::Duration::hours(1);
+        insert_transaction(&pool, &backdated).await.unwrap();
+        let page = fetch_transactions(&pool, &alice.username, &TransactionFilter::default())
+            .await
+            .unwrap();
+        let entries = &page.transactions;
+        assert_eq!(entries[0].txn_id, backdated.txn_id);
+        let balances: Vec<f64> = entries.iter().map(|e| e.balance_after).collect();
+        assert_eq!(balances, vec![55.0, 75.0, 80.0, 70.0]);
+        for pair in entries.windows(2) {
+            assert_eq!(
+                pair[0].balance_after,
+                pair[1].balance_after + pair[0].signed_amount
+            );
+        }
+    }
 }
@@ -0,0 +1,242 @@
+use crate::http::db::model::{AccountState, AccountStatus, Transaction, TransferKind};
+use crate::http::db::queries::{holds, transfer};
+use crate::http::errors::{ApiError, Result};
+use chrono::Utc;
+use log::debug;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+pub async fn fetch_account_state(pool: &PgPool, username: &str) -> Result<Option<AccountState>> {
+    debug!("Fetching account state for user: {:?}", username);
+    sqlx::query_as::<_, AccountState>(
+        r#"
+        SELECT status, status_reason, status_changed_at, closed_at FROM users WHERE username = $1
+        "#,
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Returns the account status and admin flag used to authorize a request.
+pub async fn fetch_access(pool: &PgPool, username: &str) -> Result<Option<(AccountStatus, bool)>> {
+    debug!("Fetching access for user: {:?}", username);
+    let rec = sqlx::query(r#"SELECT status, is_admin FROM users WHERE username = $1"#)
+        .bind(username)
+        .fetch_optional(pool)
+        .await
+        .map_err(ApiError::Database)?;
+    Ok(rec.map(|row| (row.get("status"), row.get("is_admin"))))
+}
+
+/// Freezes or reactivates an account. Closed accounts stay closed.
+pub async fn set_account_status(
+    pool: &PgPool,
+    username: &str,
+    status: AccountStatus,
+    reason: Option<&str>,
+) -> Result<()> {
+    debug!("Setting status of {:?} to {:?}", username, status);
+    if status == AccountStatus::Closed {
+        return Err(ApiError::Validation(
+            "accounts are closed through the close endpoint".to_string(),
+        ));
+    }
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let current: AccountStatus =
+        match sqlx::query(r#"SELECT status FROM users WHERE username = $1 FOR UPDATE"#)
+            .bind(username)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(ApiError::Database)?
+        {
+            Some(row) => row.get("status"),
+            None => return Err(ApiError::UserNotFound),
+        };
+    if current == AccountStatus::Closed {
+        return Err(ApiError::AccountClosed);
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE users SET status = $1, status_reason = $2, status_changed_at = NOW()
+        WHERE username = $3
+        "#,
+    )
+    .bind(status)
+    .bind(reason)
+    .bind(username)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(())
+}
+
+/// Closes an account. Any remaining balance must be paid out to `payout_to`
+/// in the same database transaction; otherwise the balance must be zero.
+/// Accounts with active holds or funds frozen by a dispute cannot close
+/// until those are settled.
+pub async fn close_account(
+    pool: &PgPool,
+    username: &str,
+    reason: Option<&str>,
+    payout_to: Option<&str>,
+) -> Result<()> {
+    debug!("Closing account: {:?}", username);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let row =
+        sqlx::query(r#"SELECT userid, balance, status FROM users WHERE username = $1 FOR UPDATE"#)
+            .bind(username)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(ApiError::Database)?
+            .ok_or(ApiError::UserNotFound)?;
+    let balance: f64 = row.get("balance");
+    let status: AccountStatus = row.get("status");
+    if status == AccountStatus::Closed {
+        return Err(ApiError::AccountClosed);
+    }
+    if holds::held_amount(&mut tx, row.get("userid")).await? > 0.0 {
+        return Err(ApiError::Conflict("account has active holds".to_string()));
+    }
+
+    if balance != 0.0 {
+        let Some(payout_to) = payout_to else {
+            return Err(ApiError::Validation(
+                "balance must be zero or paid out before closing".to_string(),
+            ));
+        };
+        let payout = Transaction {
+            txn_id: Uuid::new_v4(),
+            amount: balance,
+            from_username: username.to_string(),
+            to_username: payout_to.to_string(),
+            time: Utc::now(),
+            memo: None,
+            kind: TransferKind::Payout,
+            fee: 0.0,
+        };
+        transfer(&mut tx, &payout).await?;
+        debug!(
+            "Paid out {} from {:?} to {:?}",
+            balance, username, payout_to
+        );
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET status = 'closed', status_reason = $1, status_changed_at = NOW(), closed_at = NOW()
+        WHERE username = $2
+        "#,
+    )
+    .bind(reason)
+    .bind(username)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::{fetch_balance, insert_transaction};
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+
+    #[tokio::test]
+    async fn test_frozen_account_cannot_send_or_receive() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        set_account_status(
+            &pool,
+            &alice.username,
+            AccountStatus::Frozen,
+            Some("review"),
+        )
+        .await
+        .unwrap();
+
+        let outgoing = Transaction {
+            txn_id: Uuid::new_v4(),
+            amount: 10.0,
+            from_username: alice.username.clone(),
+            to_username: bob.username.clone(),
+            time: Utc::now(),
+            memo: None,
+            kind: TransferKind::P2p,
+            fee: 0.0,
+        };
+        let res = insert_transaction(&pool, &outgoing).await;
+        assert!(matches!(res, Err(ApiError::AccountFrozen)));
+
+        let incoming = Transaction {
+            txn_id: Uuid::new_v4(),
+            from_username: bob.username.clone(),
+            to_username: alice.username.clone(),
+            ..outgoing
+        };
+        let res = insert_transaction(&pool, &incoming).await;
+        assert!(matches!(res, Err(ApiError::AccountFrozen)));
+
+        let state = fetch_account_state(&pool, &alice.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.status, AccountStatus::Frozen);
+        assert_eq!(state.status_reason.as_deref(), Some("review"));
+    }
+
+    #[tokio::test]
+    async fn test_close_requires_zero_balance_or_payout() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 40.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+
+        let res = close_account(&pool, &alice.username, None, None).await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+
+        close_account(&pool, &alice.username, Some("moving"), Some(&bob.username))
+            .await
+            .unwrap();
+        assert_eq!(
+            fetch_balance(&pool, &bob.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(40.0)
+        );
+        let state = fetch_account_state(&pool, &alice.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.status, AccountStatus::Closed);
+        assert!(state.closed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_close_refuses_active_holds() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 40.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        let hold = holds::create_hold(&pool, &alice.username, &bob.username, 10.0, None)
+            .await
+            .unwrap();
+
+        let res = close_account(&pool, &alice.username, None, Some(&bob.username)).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+
+        holds::void_hold(&pool, hold.hold_id, &alice.username)
+            .await
+            .unwrap();
+        close_account(&pool, &alice.username, None, Some(&bob.username))
+            .await
+            .unwrap();
+    }
+}
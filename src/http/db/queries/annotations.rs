@@ -0,0 +1,208 @@
+use crate::http::db::model::TransactionAnnotation;
+use crate::http::errors::{ApiError, Result};
+use log::debug;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+pub const MAX_MEMO_LEN: usize = 140;
+pub const MAX_TAG_LEN: usize = 32;
+pub const MAX_TAGS: usize = 10;
+
+/// Trims a memo, drops control characters and collapses runs of whitespace.
+/// An empty memo becomes `None`.
+pub fn sanitize_memo(raw: &str) -> Result<Option<String>> {
+    let memo = raw
+        .split_whitespace()
+        .map(|word| word.chars().filter(|c| !c.is_control()).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if memo.chars().count() > MAX_MEMO_LEN {
+        return Err(ApiError::Validation(format!(
+            "memo must be at most {} characters",
+            MAX_MEMO_LEN
+        )));
+    }
+    Ok(if memo.is_empty() { None } else { Some(memo) })
+}
+
+/// Lowercases a tag and strips a leading '#'. Tags may only contain
+/// letters, digits, '-' and '_'.
+pub fn sanitize_tag(raw: &str) -> Result<String> {
+    let tag = raw.trim();
+    let tag = tag.strip_prefix('#').unwrap_or(tag).to_lowercase();
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN {
+        return Err(ApiError::Validation(format!(
+            "tags must be between 1 and {} characters",
+            MAX_TAG_LEN
+        )));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(ApiError::Validation(
+            "tags may only contain letters, digits, '-' and '_'".to_string(),
+        ));
+    }
+    Ok(tag)
+}
+
+/// Sanitizes every tag, dropping duplicates while keeping the given order.
+pub fn sanitize_tags(raw: &[String]) -> Result<Vec<String>> {
+    let mut tags: Vec<String> = Vec::with_capacity(raw.len());
+    for tag in raw {
+        let tag = sanitize_tag(tag)?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if tags.len() > MAX_TAGS {
+        return Err(ApiError::Validation(format!(
+            "a transaction can have at most {} tags",
+            MAX_TAGS
+        )));
+    }
+    Ok(tags)
+}
+
+/// Categories follow the same rules as tags.
+pub fn sanitize_category(raw: &str) -> Result<String> {
+    sanitize_tag(raw).map_err(|_| {
+        ApiError::Validation(format!(
+            "category must be 1 to {} letters, digits, '-' or '_'",
+            MAX_TAG_LEN
+        ))
+    })
+}
+
+/// Sets `username`'s own tags and category on a transaction, replacing any
+/// previous ones. Transactions the user took no part in look missing.
+pub async fn annotate_transaction(
+    pool: &PgPool,
+    username: &str,
+    txn_id: Uuid,
+    tags: &[String],
+    category: Option<&str>,
+) -> Result<TransactionAnnotation> {
+    debug!(
+        "Annotating transaction {:?} for {:?} with tags {:?} and category {:?}",
+        txn_id, username, tags, category
+    );
+    let tags = sanitize_tags(tags)?;
+    let category = category.map(sanitize_category).transpose()?;
+
+    let userid: Uuid = sqlx::query(
+        r#"
+        SELECT u.userid FROM transactions t
+        JOIN users u ON u.userid IN (t.from_userid, t.to_userid)
+        WHERE t.txn_id = $1 AND u.username = $2
+        "#,
+    )
+    .bind(txn_id)
+    .bind(username)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::NotFound)?
+    .get("userid");
+
+    sqlx::query_as::<_, TransactionAnnotation>(
+        r#"
+        INSERT INTO transaction_annotations (txn_id, userid, category, tags)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (txn_id, userid)
+        DO UPDATE SET category = EXCLUDED.category, tags = EXCLUDED.tags, updated_at = NOW()
+        RETURNING txn_id, category, tags, updated_at
+        "#,
+    )
+    .bind(txn_id)
+    .bind(userid)
+    .bind(category)
+    .bind(tags)
+    .fetch_one(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::model::TransactionFilter;
+    use crate::http::db::queries::{fetch_transactions, insert_transaction};
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+
+    #[test]
+    fn test_sanitize_memo_and_tags() {
+        assert_eq!(
+            sanitize_memo("  lunch\u{7}\n  at   Joe's ").unwrap(),
+            Some("lunch at Joe's".to_string())
+        );
+        assert_eq!(sanitize_memo(" \t ").unwrap(), None);
+        assert!(sanitize_memo(&"x".repeat(MAX_MEMO_LEN + 1)).is_err());
+        assert_eq!(
+            sanitize_tags(&["#Food".to_string(), "food".to_string(), "rent".to_string()]).unwrap(),
+            vec!["food".to_string(), "rent".to_string()]
+        );
+        assert!(sanitize_tag("no spaces").is_err());
+        assert!(sanitize_category("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_annotations_are_per_participant_and_filterable() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let carol = create_test_user(&pool, "carol", 0.0).await;
+        let mut dinner = transaction(&alice.username, &bob.username, 30.0);
+        dinner.memo = Some("dinner".to_string());
+        insert_transaction(&pool, &dinner).await.unwrap();
+        let other = transaction(&alice.username, &bob.username, 5.0);
+        insert_transaction(&pool, &other).await.unwrap();
+
+        annotate_transaction(
+            &pool,
+            &alice.username,
+            dinner.txn_id,
+            &["#Friends".to_string()],
+            Some("food"),
+        )
+        .await
+        .unwrap();
+        annotate_transaction(&pool, &bob.username, dinner.txn_id, &[], Some("income"))
+            .await
+            .unwrap();
+        let res = annotate_transaction(&pool, &carol.username, dinner.txn_id, &[], None).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+
+        let by_tag = TransactionFilter {
+            tag: Some("friends".to_string()),
+            ..Default::default()
+        };
+        let page = fetch_transactions(&pool, &alice.username, &by_tag)
+            .await
+            .unwrap();
+        assert_eq!(page.transactions.len(), 1);
+        let entry = &page.transactions[0];
+        assert_eq!(entry.txn_id, dinner.txn_id);
+        assert_eq!(entry.memo.as_deref(), Some("dinner"));
+        assert_eq!(entry.category.as_deref(), Some("food"));
+        assert_eq!(entry.tags, vec!["friends".to_string()]);
+
+        // Bob sees the shared memo but only his own category.
+        let by_category = TransactionFilter {
+            category: Some("income".to_string()),
+            ..Default::default()
+        };
+        let page = fetch_transactions(&pool, &bob.username, &by_category)
+            .await
+            .unwrap();
+        assert_eq!(page.transactions.len(), 1);
+        assert_eq!(page.transactions[0].memo.as_deref(), Some("dinner"));
+        assert!(page.transactions[0].tags.is_empty());
+        let page = fetch_transactions(&pool, &bob.username, &by_tag)
+            .await
+            .unwrap();
+        assert!(page.transactions.is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::http::db::model::{
+    BatchItem, BatchItemResult, BatchItemStatus, BatchStatus, NewBatch, Transaction, TransferBatch,
+    TransferKind,
+};
+use crate::http::db::queries::{transfer, usernames};
+use crate::http::errors::{ApiError, Result};
+use chrono::Utc;
+use log::debug;
+use sqlx::{Connection, PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+pub const MAX_BATCH_ITEMS: usize = 1000;
+
+/// Checks every item before anything runs and reports all problems at once,
+/// so a payroll file can be fixed in one go.
+async fn validate_items(
+    conn: &mut PgConnection,
+    sender_id: Uuid,
+    items: &[BatchItem],
+) -> Result<()> {
+    let mut problems = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        if !item.amount.is_finite() || item.amount <= 0.0 {
+            problems.push(format!("item {}: amount must be greater than zero", i));
+        }
+        match usernames::resolve_username(&mut *conn, &item.to_username).await? {
+            None => problems.push(format!(
+                "item {}: recipient {} not found",
+                i, item.to_username
+            )),
+            Some(id) if id == sender_id => {
+                problems.push(format!("item {}: cannot transfer to yourself", i))
+            }
+            Some(_) => {}
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::Validation(problems.join("; ")))
+    }
+}
+
+/// Pays every item of a batch from `sender` in one database transaction.
+/// Each item runs in its own savepoint: an atomic batch stops and rolls back
+/// everything at the first failure, a best-effort batch skips failed items.
+/// The outcome is recorded either way and can be fetched with [`fetch_batch`].
+pub async fn run_batch(pool: &PgPool, sender: &str, batch: &NewBatch) -> Result<TransferBatch> {
+    debug!(
+        "Running transfer batch of {} items for {:?}, atomic={}",
+        batch.items.len(),
+        sender,
+        batch.atomic
+    );
+    if batch.items.is_empty() || batch.items.len() > MAX_BATCH_ITEMS {
+        return Err(ApiError::Validation(format!(
+            "a batch must have between 1 and {} items",
+            MAX_BATCH_ITEMS
+        )));
+    }
+    let batch_id = batch.batch_id.unwrap_or_else(Uuid::new_v4);
+
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let sender_id: Uuid = sqlx::query(r#"SELECT userid FROM users WHERE username = $1"#)
+        .bind(sender)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?
+        .get("userid");
+    let exists = sqlx::query(r#"SELECT 1 FROM transfer_batches WHERE batch_id = $1"#)
+        .bind(batch_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    if exists.is_some() {
+        return Err(ApiError::Conflict(
+            "batch has already been submitted".to_string(),
+        ));
+    }
+    validate_items(&mut tx, sender_id, &batch.items).await?;
+
+    let mut items = Vec::with_capacity(batch.items.len());
+    let mut failed = false;
+    for (i, item) in batch.items.iter().enumerate() {
+        let mut result = BatchItemResult {
+            item_index: i as i32,
+            to_username: item.to_username.clone(),
+            amount: item.amount,
+            status: BatchItemStatus::Skipped,
+            txn_id: None,
+            error: None,
+        };
+        if !(failed && batch.atomic) {
+            let txn = Transaction {
+                txn_id: Uuid::new_v4(),
+                amount: item.amount,
+                from_username: sender.to_string(),
+                to_username: item.to_username.clone(),
+                time: Utc::now(),
+                memo: None,
+                kind: TransferKind::Batch,
+                fee: 0.0,
+            };
+            let mut attempt = tx.begin().await.map_err(ApiError::Database)?;
+            match transfer(&mut attempt, &txn).await {
+                Ok(()) => {
+                    attempt.commit().await.map_err(ApiError::Database)?;
+                    result.status = BatchItemStatus::Succeeded;
+                    result.txn_id = Some(txn.txn_id);
+                }
+                Err(e) => {
+                    attempt.rollback().await.map_err(ApiError::Database)?;
+                    debug!("Batch {:?} item {} failed: {}", batch_id, i, e);
+                    result.status = BatchItemStatus::Failed;
+                    result.error = Some(e.to_string());
+                    failed = true;
+                }
+            }
+        }
+        items.push(result);
+    }
+
+    if failed && batch.atomic {
+        tx.rollback().await.map_err(ApiError::Database)?;
+        for item in items.iter_mut() {
+            if item.status == BatchItemStatus::Succeeded {
+                item.status = BatchItemStatus::RolledBack;
+            }
+        }
+        tx = pool.begin().await.map_err(ApiError::Database)?;
+    }
+
+    let succeeded: Vec<&BatchItemResult> = items
+        .iter()
+        .filter(|item| item.status == BatchItemStatus::Succeeded)
+        .collect();
+    let status = match succeeded.len() {
+        n if n == items.len() => BatchStatus::Completed,
+        0 => BatchStatus::Failed,
+        _ => BatchStatus::Partial,
+    };
+    let total_amount: f64 = succeeded.iter().map(|item| item.amount).sum();
+
+    let created_at = sqlx::query(
+        r#"
+        INSERT INTO transfer_batches
+            (batch_id, from_userid, atomic, status, item_count, succeeded_count, total_amount)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING created_at
+        "#,
+    )
+    .bind(batch_id)
+    .bind(sender_id)
+    .bind(batch.atomic)
+    .bind(status)
+    .bind(items.len() as i32)
+    .bind(succeeded.len() as i32)
+    .bind(total_amount)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            ApiError::Conflict("batch has already been submitted".to_string())
+        }
+        e => ApiError::Database(e),
+    })?
+    .get("created_at");
+    let succeeded_count = succeeded.len() as i32;
+    for item in &items {
+        sqlx::query(
+            r#"
+            INSERT INTO transfer_batch_items
+                (batch_id, item_index, to_username, amount, status, txn_id, error)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(batch_id)
+        .bind(item.item_index)
+        .bind(&item.to_username)
+        .bind(item.amount)
+        .bind(item.status)
+        .bind(item.txn_id)
+        .bind(item.error.as_deref())
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    }
+    tx.commit().await.map_err(ApiError::Database)?;
+
+    Ok(TransferBatch {
+        batch_id,
+        from_username: sender.to_string(),
+        atomic: batch.atomic,
+        status,
+        item_count: items.len() as i32,
+        succeeded_count,
+        total_amount,
+        created_at,
+        items,
+    })
+}
+
+/// Fetches a batch with its item results. Other users' batches look missing.
+pub async fn fetch_batch(pool: &PgPool, batch_id: Uuid, username: &str) -> Result<TransferBatch> {
+    debug!("Fetching transfer batch {:?} for {:?}", batch_id, username);
+    let mut batch = sqlx::query_as::<_, TransferBatch>(
+        r#"
+        SELECT b.batch_id, u.username AS from_username, b.atomic, b.status, b.item_count,
+            b.succeeded_count, b.total_amount, b.created_at
+        FROM transfer_batches b
+        JOIN users u ON u.userid = b.from_userid
+        WHERE b.batch_id = $1 AND u.username = $2
+        "#,
+    )
+    .bind(batch_id)
+    .bind(username)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::NotFound)?;
+    batch.items = sqlx::query_as::<_, BatchItemResult>(
+        r#"
+        SELECT item_index, to_username, amount, status, txn_id, error
+        FROM transfer_batch_items
+        WHERE batch_id = $1
+        ORDER BY item_index
+        "#,
+    )
+    .bind(batch_id)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::fetch_balance;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+
+    fn item(to: &str, amount: f64) -> BatchItem {
+        BatchItem {
+            to_username: to.to_string(),
+            amount,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_atomic_batch_rolls_back_and_best_effort_skips() {
+        let pool = setup_test_db().await;
+        let payer = create_test_user(&pool, "payer", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        let carol = create_test_user(&pool, "carol", 0.0).await;
+        let items = vec![
+            item(&bob.username, 40.0),
+            item(&carol.username, 80.0),
+            item(&carol.username, 10.0),
+        ];
+
+        let atomic = NewBatch {
+            batch_id: None,
+            atomic: true,
+            items: items.clone(),
+        };
+        let result = run_batch(&pool, &payer.username, &atomic).await.unwrap();
+        assert_eq!(result.status, BatchStatus::Failed);
+        let statuses: Vec<BatchItemStatus> = result.items.iter().map(|i| i.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                BatchItemStatus::RolledBack,
+                BatchItemStatus::Failed,
+                BatchItemStatus::Skipped
+            ]
+        );
+        assert_eq!(
+            fetch_balance(&pool, &payer.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(100.0)
+        );
+        let stored = fetch_batch(&pool, result.batch_id, &payer.username)
+            .await
+            .unwrap();
+        assert_eq!(stored.items.len(), 3);
+        assert!(matches!(
+            fetch_batch(&pool, result.batch_id, &bob.username).await,
+            Err(ApiError::NotFound)
+        ));
+
+        let best_effort = NewBatch {
+            batch_id: Some(Uuid::new_v4()),
+            atomic: false,
+            items,
+        };
+        let result = run_batch(&pool, &payer.username, &best_effort)
+            .await
+            .unwrap();
+        assert_eq!(result.status, BatchStatus::Partial);
+        assert_eq!(result.succeeded_count, 2);
+        assert_eq!(result.total_amount, 50.0);
+        assert_eq!(
+            fetch_balance(&pool, &payer.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(50.0)
+        );
+
+        let res = run_batch(&pool, &payer.username, &best_effort).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_batch_validation_reports_every_item() {
+        let pool = setup_test_db().await;
+        let payer = create_test_user(&pool, "payer", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        let batch = NewBatch {
+            batch_id: None,
+            atomic: true,
+            items: vec![
+                item(&bob.username, 10.0),
+                item("nobody_at_all", 10.0),
+                item(&bob.username, -5.0),
+            ],
+        };
+        match run_batch(&pool, &payer.username, &batch).await {
+            Err(ApiError::Validation(msg)) => {
+                assert!(msg.contains("item 1") && msg.contains("item 2"));
+                assert!(!msg.contains("item 0"));
+            }
+            other => panic!("expected validation error, got {:?}", other),
+        }
+    }
+}
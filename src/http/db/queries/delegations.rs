@@ -0,0 +1,202 @@
+use crate::http::db::model::ViewerDelegation;
+use crate::http::db::queries::usernames;
+use crate::http::errors::{ApiError, Result};
+use chrono::{DateTime, Utc};
+use log::debug;
+use sqlx::PgPool;
+
+/// Lists `owner`'s viewers whose delegation has not expired.
+pub async fn list_viewers(pool: &PgPool, owner: &str) -> Result<Vec<ViewerDelegation>> {
+    debug!("Listing delegated viewers of {:?}", owner);
+    sqlx::query_as::<_, ViewerDelegation>(
+        r#"
+        SELECT v.username AS viewer_username, v.name AS viewer_name, d.created_at, d.expires_at
+        FROM viewer_delegations d
+        JOIN users o ON o.userid = d.owner_userid
+        JOIN users v ON v.userid = d.viewer_userid
+        WHERE o.username = $1 AND (d.expires_at IS NULL OR d.expires_at > NOW())
+        ORDER BY d.created_at
+        "#,
+    )
+    .bind(owner)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Lets `viewer` read `owner`'s transactions until `expires_at`, or until
+/// revoked when it is `None`. Granting again only replaces the expiry.
+pub async fn grant_viewer(
+    pool: &PgPool,
+    owner: &str,
+    viewer: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    debug!(
+        "Granting {:?} view access to {:?} until {:?}",
+        viewer, owner, expires_at
+    );
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(ApiError::Validation(
+            "expires_at must be in the future".to_string(),
+        ));
+    }
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let owner_id = usernames::resolve_username(&mut conn, owner)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    let viewer_id = usernames::resolve_username(&mut conn, viewer)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    if owner_id == viewer_id {
+        return Err(ApiError::Validation(
+            "cannot delegate to yourself".to_string(),
+        ));
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO viewer_delegations (owner_userid, viewer_userid, expires_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (owner_userid, viewer_userid) DO UPDATE SET expires_at = EXCLUDED.expires_at
+        "#,
+    )
+    .bind(owner_id)
+    .bind(viewer_id)
+    .bind(expires_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(())
+}
+
+pub async fn revoke_viewer(pool: &PgPool, owner: &str, viewer: &str) -> Result<()> {
+    debug!("Revoking {:?} view access to {:?}", viewer, owner);
+    let result = sqlx::query(
+        r#"
+        DELETE FROM viewer_delegations d
+        USING users o, users v
+        WHERE o.userid = d.owner_userid AND v.userid = d.viewer_userid
+            AND o.username = $1 AND v.username = $2
+        "#,
+    )
+    .bind(owner)
+    .bind(viewer)
+    .execute(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound);
+    }
+    Ok(())
+}
+
+/// True when `viewer` holds an unexpired delegation from any of `owners`.
+pub async fn is_delegated_viewer(pool: &PgPool, viewer: &str, owners: &[&str]) -> Result<bool> {
+    let row = sqlx::query(
+        r#"
+        SELECT 1 FROM viewer_delegations d
+        JOIN users o ON o.userid = d.owner_userid
+        JOIN users v ON v.userid = d.viewer_userid
+        WHERE v.username = $1 AND o.username = ANY($2)
+            AND (d.expires_at IS NULL OR d.expires_at > NOW())
+        LIMIT 1
+        "#,
+    )
+    .bind(viewer)
+    .bind(owners)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(row.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_grant_list_and_revoke_viewer() {
+        let pool = setup_test_db().await;
+        let owner = create_test_user(&pool, "owner", 0.0).await;
+        let viewer = create_test_user(&pool, "viewer", 0.0).await;
+
+        let res = grant_viewer(&pool, &owner.username, &owner.username, None).await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+
+        grant_viewer(&pool, &owner.username, &viewer.username, None)
+            .await
+            .unwrap();
+        // Granting again only replaces the expiry.
+        let expires_at = Utc::now() + Duration::days(30);
+        grant_viewer(&pool, &owner.username, &viewer.username, Some(expires_at))
+            .await
+            .unwrap();
+        let viewers = list_viewers(&pool, &owner.username).await.unwrap();
+        assert_eq!(viewers.len(), 1);
+        assert_eq!(viewers[0].viewer_username, viewer.username);
+        assert!(viewers[0].expires_at.is_some());
+        assert!(
+            is_delegated_viewer(&pool, &viewer.username, &[&owner.username])
+                .await
+                .unwrap()
+        );
+        // The delegation only goes one way.
+        assert!(
+            !is_delegated_viewer(&pool, &owner.username, &[&viewer.username])
+                .await
+                .unwrap()
+        );
+
+        revoke_viewer(&pool, &owner.username, &viewer.username)
+            .await
+            .unwrap();
+        assert!(
+            list_viewers(&pool, &owner.username)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let res = revoke_viewer(&pool, &owner.username, &viewer.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_expired_delegation_grants_nothing() {
+        let pool = setup_test_db().await;
+        let owner = create_test_user(&pool, "owner", 0.0).await;
+        let viewer = create_test_user(&pool, "viewer", 0.0).await;
+
+        let past = Utc::now() - Duration::minutes(1);
+        let res = grant_viewer(&pool, &owner.username, &viewer.username, Some(past)).await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+
+        grant_viewer(&pool, &owner.username, &viewer.username, None)
+            .await
+            .unwrap();
+        sqlx::query(
+            "UPDATE viewer_delegations SET expires_at = NOW() - interval '1 minute' WHERE owner_userid = $1",
+        )
+        .bind(owner.userid)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(
+            list_viewers(&pool, &owner.username)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            !is_delegated_viewer(&pool, &viewer.username, &[&owner.username])
+                .await
+                .unwrap()
+        );
+        // The owner can still clean up the expired row.
+        revoke_viewer(&pool, &owner.username, &viewer.username)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+use crate::http::db::model::Recipient;
+use crate::http::db::queries::usernames;
+use crate::http::errors::{ApiError, Result};
+use log::debug;
+use sqlx::{PgPool, Row};
+
+/// Lookups a user may make per hour, counting misses, so phone numbers and
+/// emails cannot be enumerated.
+pub const LOOKUPS_PER_HOUR: i64 = 30;
+
+const MIN_PHONE_DIGITS: usize = 7;
+const MAX_PHONE_DIGITS: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alias {
+    Username(String),
+    Phone(String),
+    Email(String),
+}
+
+/// Strips formatting characters from a phone number, keeping an optional
+/// leading '+'.
+pub fn normalize_phone(raw: &str) -> Result<String> {
+    let trimmed = raw.trim();
+    let (plus, rest) = match trimmed.strip_prefix('+') {
+        Some(rest) => ("+", rest),
+        None => ("", trimmed),
+    };
+    let mut digits = String::with_capacity(rest.len());
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            ' ' | '-' | '.' | '(' | ')' => {}
+            _ => {
+                return Err(ApiError::Validation(
+                    "phone number may only contain digits".to_string(),
+                ));
+            }
+        }
+    }
+    if !(MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits.len()) {
+        return Err(ApiError::Validation(format!(
+            "phone number must have between {} and {} digits",
+            MIN_PHONE_DIGITS, MAX_PHONE_DIGITS
+        )));
+    }
+    Ok(format!("{}{}", plus, digits))
+}
+
+pub fn normalize_email(raw: &str) -> Result<String> {
+    let email = raw.trim().to_lowercase();
+    match email.split_once('@') {
+        Some((local, domain))
+            if !local.is_empty()
+                && domain.contains('.')
+                && !email.contains(char::is_whitespace) =>
+        {
+            Ok(email)
+        }
+        _ => Err(ApiError::Validation("invalid email address".to_string())),
+    }
+}
+
+/// Classifies a recipient alias: `@name` is always a username, anything
+/// else containing '@' is an email, and phone-shaped input is a phone number.
+pub fn parse_alias(raw: &str) -> Result<Alias> {
+    let raw = raw.trim();
+    if let Some(username) = raw.strip_prefix('@') {
+        return Ok(Alias::Username(username.to_string()));
+    }
+    if raw.contains('@') {
+        return normalize_email(raw).map(Alias::Email);
+    }
+    let phone_shaped = raw.starts_with('+')
+        || raw
+            .chars()
+            .all(|c| c.is_ascii_digit() || " -.()".contains(c));
+    if phone_shaped && let Ok(phone) = normalize_phone(raw) {
+        return Ok(Alias::Phone(phone));
+    }
+    if raw.is_empty() {
+        return Err(ApiError::Validation("alias is required".to_string()));
+    }
+    Ok(Alias::Username(raw.to_string()))
+}
+
+/// Resolves an alias to an active account on behalf of `requester`.
+/// Phone numbers and emails only match accounts that opted into discovery.
+/// Every call counts against the requester's hourly lookup budget.
+pub async fn resolve_alias(
+    pool: &PgPool,
+    requester: &str,
+    alias: &Alias,
+) -> Result<Option<Recipient>> {
+    debug!("Resolving alias {:?} for {:?}", alias, requester);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+
+    let requester_id: uuid::Uuid =
+        match sqlx::query(r#"SELECT userid FROM users WHERE username = $1 FOR UPDATE"#)
+            .bind(requester)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(ApiError::Database)?
+        {
+            Some(row) => row.get("userid"),
+            None => return Err(ApiError::UserNotFound),
+        };
+    let recent: i64 = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS count FROM recipient_lookups
+        WHERE userid = $1 AND looked_up_at > NOW() - INTERVAL '1 hour'
+        "#,
+    )
+    .bind(requester_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    .get("count");
+    if recent >= LOOKUPS_PER_HOUR {
+        debug!("Lookup rate limit reached for {:?}", requester);
+        return Err(ApiError::RateLimited);
+    }
+    sqlx::query(r#"INSERT INTO recipient_lookups (userid) VALUES ($1)"#)
+        .bind(requester_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+
+    let recipient = match alias {
+        Alias::Username(username) => match usernames::resolve_username(&mut tx, username).await? {
+            Some(userid) => sqlx::query_as::<_, Recipient>(
+                r#"SELECT username, name FROM users WHERE userid = $1 AND status <> 'closed'"#,
+            )
+            .bind(userid)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(ApiError::Database)?,
+            None => None,
+        },
+        Alias::Phone(phone) => sqlx::query_as::<_, Recipient>(
+            r#"
+            SELECT username, name FROM users
+            WHERE phno = $1 AND discoverable AND status <> 'closed'
+            "#,
+        )
+        .bind(phone)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?,
+        Alias::Email(email) => sqlx::query_as::<_, Recipient>(
+            r#"
+            SELECT username, name FROM users
+            WHERE email = $1 AND discoverable AND status <> 'closed'
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?,
+    };
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(recipient)
+}
+
+pub async fn update_discovery(
+    pool: &PgPool,
+    username: &str,
+    discoverable: bool,
+    email: Option<&str>,
+) -> Result<()> {
+    debug!(
+        "Updating discovery for {:?}: discoverable={}",
+        username, discoverable
+    );
+    let result = sqlx::query(
+        r#"
+        UPDATE users SET discoverable = $1, email = COALESCE($2, email)
+        WHERE username = $3
+        "#,
+    )
+    .bind(discoverable)
+    .bind(email)
+    .bind(username)
+    .execute(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            ApiError::Conflict("email is already registered".to_string())
+        }
+        e => ApiError::Database(e),
+    })?;
+    match result.rows_affected() {
+        0 => Err(ApiError::UserNotFound),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+
+    #[test]
+    fn test_normalize_phone() {
+        assert_eq!(
+            normalize_phone("+91 (999) 999-9999").unwrap(),
+            "+919999999999"
+        );
+        assert_eq!(normalize_phone("999.999.9999").unwrap(), "9999999999");
+        assert!(normalize_phone("12345").is_err());
+        assert!(normalize_phone("99999x9999").is_err());
+    }
+
+    #[test]
+    fn test_parse_alias() {
+        assert_eq!(
+            parse_alias("@12345678").unwrap(),
+            Alias::Username("12345678".to_string())
+        );
+        assert_eq!(
+            parse_alias(" Ayush@Example.com ").unwrap(),
+            Alias::Email("ayush@example.com".to_string())
+        );
+        assert_eq!(
+            parse_alias("+1 555 010 9999").unwrap(),
+            Alias::Phone("+15550109999".to_string())
+        );
+        assert_eq!(
+            parse_alias("ayush2").unwrap(),
+            Alias::Username("ayush2".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_phone_lookup_requires_opt_in_and_is_rate_limited() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 0.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        let by_phone = Alias::Phone(bob.phno.clone());
+
+        let found = resolve_alias(&pool, &alice.username, &by_phone)
+            .await
+            .unwrap();
+        assert!(found.is_none());
+
+        update_discovery(&pool, &bob.username, true, None)
+            .await
+            .unwrap();
+        let found = resolve_alias(&pool, &alice.username, &by_phone)
+            .await
+            .unwrap();
+        assert_eq!(found.unwrap().username, bob.username);
+
+        for _ in 2..LOOKUPS_PER_HOUR {
+            resolve_alias(&pool, &alice.username, &by_phone)
+                .await
+                .unwrap();
+        }
+        let res = resolve_alias(&pool, &alice.username, &by_phone).await;
+        assert!(matches!(res, Err(ApiError::RateLimited)));
+    }
+}
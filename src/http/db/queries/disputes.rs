@@ -0,0 +1,488 @@
+use crate::http::db::model::{
+    Dispute, DisputeEvidence, DisputeReason, DisputeStatus, TransferKind,
+};
+use crate::http::db::queries::reverse_payment;
+use crate::http::errors::{ApiError, Result};
+use chrono::{DateTime, Duration, Utc};
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+/// How long after a transfer its payer can still dispute it.
+pub const DISPUTE_WINDOW_DAYS: i64 = 120;
+pub const MAX_NOTE_LEN: usize = 2000;
+
+const SELECT_DISPUTES: &str = r#"
+    SELECT d.dispute_id, d.txn_id, p.username AS payer_username, e.username AS payee_username,
+        d.amount, d.reason, d.description, d.status, d.frozen_amount, d.resolution_note,
+        r.username AS resolved_by, d.reversal_txn_id, d.created_at, d.updated_at, d.resolved_at
+    FROM disputes d
+    JOIN users p ON p.userid = d.payer_userid
+    JOIN users e ON e.userid = d.payee_userid
+    LEFT JOIN users r ON r.userid = d.resolved_by
+"#;
+
+fn sanitize_note(raw: &str, field: &str) -> Result<String> {
+    let note = raw.trim();
+    if note.is_empty() || note.chars().count() > MAX_NOTE_LEN {
+        return Err(ApiError::Validation(format!(
+            "{} must be between 1 and {} characters",
+            field, MAX_NOTE_LEN
+        )));
+    }
+    Ok(note.to_string())
+}
+
+fn is_unresolved(status: DisputeStatus) -> bool {
+    matches!(status, DisputeStatus::Open | DisputeStatus::UnderReview)
+}
+
+async fn fetch_with_evidence(conn: &mut PgConnection, dispute_id: Uuid) -> Result<Dispute> {
+    let mut dispute =
+        sqlx::query_as::<_, Dispute>(&format!("{} WHERE d.dispute_id = $1", SELECT_DISPUTES))
+            .bind(dispute_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(ApiError::Database)?
+            .ok_or(ApiError::NotFound)?;
+    dispute.evidence = sqlx::query_as::<_, DisputeEvidence>(
+        r#"
+        SELECT v.evidence_id, u.username AS author_username, v.note, v.created_at
+        FROM dispute_evidence v
+        JOIN users u ON u.userid = v.author_userid
+        WHERE v.dispute_id = $1
+        ORDER BY v.created_at, v.evidence_id
+        "#,
+    )
+    .bind(dispute_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(dispute)
+}
+
+/// Fetches a dispute with its evidence. Only its payer, its payee and admins
+/// can see it; anyone else gets `NotFound`.
+pub async fn fetch_dispute(
+    pool: &PgPool,
+    dispute_id: Uuid,
+    actor: &str,
+    is_admin: bool,
+) -> Result<Dispute> {
+    debug!("Fetching dispute {} for {:?}", dispute_id, actor);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let dispute = fetch_with_evidence(&mut conn, dispute_id).await?;
+    if !is_admin && actor != dispute.payer_username && actor != dispute.payee_username {
+        return Err(ApiError::NotFound);
+    }
+    Ok(dispute)
+}
+
+/// Disputes the user opened or that were opened against them, newest first.
+pub async fn list_disputes(pool: &PgPool, username: &str) -> Result<Vec<Dispute>> {
+    debug!("Listing disputes for {:?}", username);
+    sqlx::query_as::<_, Dispute>(&format!(
+        "{} WHERE p.username = $1 OR e.username = $1 ORDER BY d.created_at DESC",
+        SELECT_DISPUTES
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// The support queue: all disputes, or those in one status, oldest first.
+pub async fn list_all_disputes(
+    pool: &PgPool,
+    status: Option<DisputeStatus>,
+) -> Result<Vec<Dispute>> {
+    debug!("Listing disputes with status {:?}", status);
+    sqlx::query_as::<_, Dispute>(&format!(
+        "{} WHERE ($1::TEXT IS NULL OR d.status = $1) ORDER BY d.created_at",
+        SELECT_DISPUTES
+    ))
+    .bind(status)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Opens a dispute on a transfer. Only its payer can, within
+/// [`DISPUTE_WINDOW_DAYS`] of the transfer. Transfers the user took no part
+/// in look missing. Nothing is frozen until support reviews the dispute and
+/// chooses to.
+pub async fn open_dispute(
+    pool: &PgPool,
+    username: &str,
+    txn_id: Uuid,
+    reason: DisputeReason,
+    description: Option<&str>,
+) -> Result<Dispute> {
+    debug!(
+        "Opening dispute on {} for {:?} because {:?}",
+        txn_id, username, reason
+    );
+    let description = description
+        .map(|description| sanitize_note(description, "description"))
+        .transpose()?;
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let txn = sqlx::query(
+        r#"
+        SELECT t.from_userid, t.to_userid, t.amount, t.kind, t.created_at,
+            f.username AS from_username, r.username AS to_username
+        FROM transactions t
+        JOIN users f ON f.userid = t.from_userid
+        JOIN users r ON r.userid = t.to_userid
+        WHERE t.txn_id = $1
+        "#,
+    )
+    .bind(txn_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::TransactionNotFound)?;
+    let from_username: String = txn.get("from_username");
+    let to_username: String = txn.get("to_username");
+    if username != to_username && username != from_username {
+        return Err(ApiError::TransactionNotFound);
+    }
+    if username != from_username {
+        return Err(ApiError::Validation(
+            "only the payer can dispute a transaction".to_string(),
+        ));
+    }
+    let kind: TransferKind = txn.get("kind");
+    if matches!(
+        kind,
+        TransferKind::Interest
+            | TransferKind::Deposit
+            | TransferKind::Withdrawal
+            | TransferKind::Reversal
+    ) {
+        return Err(ApiError::Validation(format!(
+            "{} transactions cannot be disputed",
+            format!("{:?}", kind).to_lowercase()
+        )));
+    }
+    let created_at: DateTime<Utc> = txn.get("created_at");
+    if created_at < Utc::now() - Duration::days(DISPUTE_WINDOW_DAYS) {
+        return Err(ApiError::Validation(format!(
+            "transactions can only be disputed within {} days",
+            DISPUTE_WINDOW_DAYS
+        )));
+    }
+
+    let dispute_id = Uuid::new_v4();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO disputes (dispute_id, txn_id, payer_userid, payee_userid, amount, reason,
+            description)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (txn_id) DO NOTHING
+        "#,
+    )
+    .bind(dispute_id)
+    .bind(txn_id)
+    .bind(txn.get::<Uuid, _>("from_userid"))
+    .bind(txn.get::<Uuid, _>("to_userid"))
+    .bind(txn.get::<f64, _>("amount"))
+    .bind(reason)
+    .bind(description)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    if inserted.rows_affected() == 0 {
+        return Err(ApiError::Conflict(
+            "transaction is already disputed".to_string(),
+        ));
+    }
+    let dispute = fetch_with_evidence(&mut tx, dispute_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(dispute)
+}
+
+/// Locks a dispute, failing with `Conflict` once it has been resolved.
+async fn lock_unresolved(conn: &mut PgConnection, dispute_id: Uuid) -> Result<()> {
+    let status: DisputeStatus =
+        sqlx::query(r#"SELECT status FROM disputes WHERE dispute_id = $1 FOR UPDATE"#)
+            .bind(dispute_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(ApiError::Database)?
+            .ok_or(ApiError::NotFound)?
+            .get("status");
+    if !is_unresolved(status) {
+        return Err(ApiError::Conflict(
+            "dispute is already resolved".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Adds a note to an unresolved dispute. Its payer, its payee and admins can.
+pub async fn add_evidence(
+    pool: &PgPool,
+    dispute_id: Uuid,
+    actor: &str,
+    is_admin: bool,
+    note: &str,
+) -> Result<Dispute> {
+    debug!("Adding evidence to dispute {} by {:?}", dispute_id, actor);
+    let note = sanitize_note(note, "note")?;
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let dispute = fetch_with_evidence(&mut tx, dispute_id).await?;
+    if !is_admin && actor != dispute.payer_username && actor != dispute.payee_username {
+        return Err(ApiError::NotFound);
+    }
+    lock_unresolved(&mut tx, dispute_id).await?;
+    sqlx::query(
+        r#"
+        INSERT INTO dispute_evidence (evidence_id, dispute_id, author_userid, note)
+        SELECT $1, $2, userid, $3 FROM users WHERE username = $4
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(dispute_id)
+    .bind(note)
+    .bind(actor)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    sqlx::query(r#"UPDATE disputes SET updated_at = NOW() WHERE dispute_id = $1"#)
+        .bind(dispute_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    let dispute = fetch_with_evidence(&mut tx, dispute_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(dispute)
+}
+
+/// Moves a dispute under review. `freeze_funds` freezes the disputed amount
+/// of the payee's balance or lifts the freeze; `None` leaves it as it is.
+/// Frozen funds cannot be spent until the dispute is resolved or the freeze
+/// is lifted, even if the payee has already spent the money and their
+/// available balance goes negative.
+pub async fn review_dispute(
+    pool: &PgPool,
+    dispute_id: Uuid,
+    freeze_funds: Option<bool>,
+) -> Result<Dispute> {
+    debug!(
+        "Reviewing dispute {} with freeze_funds {:?}",
+        dispute_id, freeze_funds
+    );
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    lock_unresolved(&mut tx, dispute_id).await?;
+    sqlx::query(
+        r#"
+        UPDATE disputes
+        SET status = 'under_review',
+            frozen_amount = CASE WHEN $2 IS NULL THEN frozen_amount
+                WHEN $2 THEN amount ELSE 0 END,
+            updated_at = NOW()
+        WHERE dispute_id = $1
+        "#,
+    )
+    .bind(dispute_id)
+    .bind(freeze_funds)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    let dispute = fetch_with_evidence(&mut tx, dispute_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(dispute)
+}
+
+/// Decides a dispute, which lifts any freeze. Deciding for the payer moves
+/// the disputed amount back from the payee with a `reversal` transaction;
+/// the original fee is not returned. It fails if the payer's account could
+/// not receive a transfer of that amount.
+pub async fn resolve_dispute(
+    pool: &PgPool,
+    dispute_id: Uuid,
+    admin: &str,
+    for_payer: bool,
+    note: Option<&str>,
+) -> Result<Dispute> {
+    debug!(
+        "Resolving dispute {} for the {} by {:?}",
+        dispute_id,
+        if for_payer { "payer" } else { "payee" },
+        admin
+    );
+    let note = note.map(|note| sanitize_note(note, "note")).transpose()?;
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    lock_unresolved(&mut tx, dispute_id).await?;
+    let dispute = sqlx::query(
+        r#"SELECT txn_id, payer_userid, payee_userid, amount FROM disputes WHERE dispute_id = $1"#,
+    )
+    .bind(dispute_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let reversal_txn_id = if for_payer {
+        let txn_id = reverse_payment(
+            &mut tx,
+            dispute.get("payee_userid"),
+            dispute.get("payer_userid"),
+            dispute.get("amount"),
+            &format!("Reversal of {}", dispute.get::<Uuid, _>("txn_id")),
+        )
+        .await?;
+        Some(txn_id)
+    } else {
+        None
+    };
+
+    sqlx::query(
+        r#"
+        UPDATE disputes
+        SET status = $2, resolution_note = $3, reversal_txn_id = $4, frozen_amount = 0,
+            resolved_by = (SELECT userid FROM users WHERE username = $5),
+            resolved_at = NOW(), updated_at = NOW()
+        WHERE dispute_id = $1
+        "#,
+    )
+    .bind(dispute_id)
+    .bind(if for_payer {
+        DisputeStatus::ResolvedPayer
+    } else {
+        DisputeStatus::ResolvedPayee
+    })
+    .bind(note)
+    .bind(reversal_txn_id)
+    .bind(admin)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    let dispute = fetch_with_evidence(&mut tx, dispute_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(dispute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::{fetch_balance, insert_transaction};
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+
+    #[tokio::test]
+    async fn test_dispute_freezes_funds_and_reverses_for_payer() {
+        let pool = setup_test_db().await;
+        let payer = create_test_user(&pool, "payer", 100.0).await;
+        let payee = create_test_user(&pool, "payee", 0.0).await;
+        let admin = create_test_user(&pool, "support", 0.0).await;
+        let txn = transaction(&payer.username, &payee.username, 30.0);
+        insert_transaction(&pool, &txn).await.unwrap();
+
+        assert!(matches!(
+            open_dispute(
+                &pool,
+                &payee.username,
+                txn.txn_id,
+                DisputeReason::Other,
+                None
+            )
+            .await,
+            Err(ApiError::Validation(_))
+        ));
+        assert!(matches!(
+            open_dispute(
+                &pool,
+                &admin.username,
+                txn.txn_id,
+                DisputeReason::Other,
+                None
+            )
+            .await,
+            Err(ApiError::TransactionNotFound)
+        ));
+        let dispute = open_dispute(
+            &pool,
+            &payer.username,
+            txn.txn_id,
+            DisputeReason::Unauthorized,
+            Some("I did not send this"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(dispute.status, DisputeStatus::Open);
+        // Opening a dispute freezes nothing on its own.
+        assert_eq!(dispute.frozen_amount, 0.0);
+        let balance = fetch_balance(&pool, &payee.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(balance.available_balance, 30.0);
+        assert!(matches!(
+            open_dispute(
+                &pool,
+                &payer.username,
+                txn.txn_id,
+                DisputeReason::Other,
+                None
+            )
+            .await,
+            Err(ApiError::Conflict(_))
+        ));
+
+        add_evidence(
+            &pool,
+            dispute.dispute_id,
+            &payee.username,
+            false,
+            "Invoice #12 attached",
+        )
+        .await
+        .unwrap();
+        let reviewed = review_dispute(&pool, dispute.dispute_id, Some(true))
+            .await
+            .unwrap();
+        assert_eq!(reviewed.status, DisputeStatus::UnderReview);
+        assert_eq!(reviewed.evidence.len(), 1);
+        assert_eq!(reviewed.frozen_amount, 30.0);
+        let balance = fetch_balance(&pool, &payee.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(balance.ledger_balance, 30.0);
+        assert_eq!(balance.available_balance, 0.0);
+        assert!(matches!(
+            insert_transaction(&pool, &transaction(&payee.username, &admin.username, 5.0)).await,
+            Err(ApiError::BalanceLow)
+        ));
+
+        let resolved = resolve_dispute(
+            &pool,
+            dispute.dispute_id,
+            &admin.username,
+            true,
+            Some("Card reported stolen"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolved.status, DisputeStatus::ResolvedPayer);
+        assert!(resolved.reversal_txn_id.is_some());
+        assert_eq!(
+            resolved.resolved_by.as_deref(),
+            Some(admin.username.as_str())
+        );
+        let payee_balance = fetch_balance(&pool, &payee.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payee_balance.ledger_balance, 0.0);
+        assert_eq!(payee_balance.available_balance, 0.0);
+        let payer_balance = fetch_balance(&pool, &payer.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payer_balance.ledger_balance, 100.0);
+        assert!(matches!(
+            add_evidence(&pool, dispute.dispute_id, &payer.username, false, "Thanks").await,
+            Err(ApiError::Conflict(_))
+        ));
+    }
+}
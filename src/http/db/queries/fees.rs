@@ -0,0 +1,452 @@
+use crate::http::db::model::{
+    FeeQuote, FeeRule, FeeTier, FeeType, KycLevel, NewFeeRule, TransferKind,
+};
+use crate::http::errors::{ApiError, Result};
+use log::{debug, error};
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+/// The system account that collects every fee.
+pub const FEE_ACCOUNT_USERNAME: &str = "payfree_fees";
+pub const FEE_ACCOUNT_ID: Uuid = Uuid::from_u128(0xfee5);
+
+const SELECT_RULES: &str = r#"
+    SELECT rule_id, kind, kyc_level, fee_type, flat_amount, percentage, min_fee, max_fee,
+        active, created_at
+    FROM fee_rules
+"#;
+
+/// Works out the fee a rule charges on `amount`, rounded to whole cents.
+/// Tiers must be sorted by `up_to`, with the open-ended tier last.
+pub fn compute_fee(rule: &FeeRule, amount: f64) -> f64 {
+    let (flat, percentage) = match rule.fee_type {
+        FeeType::Flat => (rule.flat_amount, 0.0),
+        FeeType::Percentage => (0.0, rule.percentage),
+        FeeType::Tiered => rule
+            .tiers
+            .iter()
+            .find(|tier| tier.up_to.is_none_or(|up_to| amount <= up_to))
+            .map(|tier| (tier.flat_amount, tier.percentage))
+            .unwrap_or((0.0, 0.0)),
+    };
+    let mut fee = flat + amount * percentage / 100.0;
+    if let Some(min) = rule.min_fee {
+        fee = fee.max(min);
+    }
+    if let Some(max) = rule.max_fee {
+        fee = fee.min(max);
+    }
+    (fee * 100.0).round() / 100.0
+}
+
+fn validate_rule(rule: &NewFeeRule) -> Result<()> {
+    if rule.kind == TransferKind::Payout {
+        return Err(ApiError::Validation(
+            "payouts on closing an account are always free".to_string(),
+        ));
+    }
+    let non_negative = |value: f64| value.is_finite() && value >= 0.0;
+    let amounts = [rule.flat_amount, rule.percentage]
+        .into_iter()
+        .chain(rule.min_fee)
+        .chain(rule.max_fee)
+        .chain(
+            rule.tiers
+                .iter()
+                .flat_map(|t| [t.flat_amount, t.percentage]),
+        );
+    if !amounts.into_iter().all(non_negative) {
+        return Err(ApiError::Validation(
+            "fee amounts and percentages must not be negative".to_string(),
+        ));
+    }
+    if let (Some(min), Some(max)) = (rule.min_fee, rule.max_fee)
+        && min > max
+    {
+        return Err(ApiError::Validation(
+            "min_fee must not be greater than max_fee".to_string(),
+        ));
+    }
+    match rule.fee_type {
+        FeeType::Tiered => {
+            if rule.tiers.is_empty() {
+                return Err(ApiError::Validation(
+                    "a tiered rule needs at least one tier".to_string(),
+                ));
+            }
+            let bounds: Vec<Option<f64>> = rule.tiers.iter().map(|t| t.up_to).collect();
+            let ascending = bounds.windows(2).all(|pair| match (pair[0], pair[1]) {
+                (Some(a), Some(b)) => a < b,
+                (Some(_), None) => true,
+                (None, _) => false,
+            });
+            if !ascending
+                || bounds
+                    .iter()
+                    .flatten()
+                    .any(|up_to| !up_to.is_finite() || *up_to <= 0.0)
+            {
+                return Err(ApiError::Validation(
+                    "tiers must be in ascending order of up_to, with only the last one open-ended"
+                        .to_string(),
+                ));
+            }
+        }
+        FeeType::Flat | FeeType::Percentage => {
+            if !rule.tiers.is_empty() {
+                return Err(ApiError::Validation(
+                    "only tiered rules can have tiers".to_string(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn fetch_tiers(conn: &mut PgConnection, rule: &mut FeeRule) -> Result<()> {
+    rule.tiers = sqlx::query_as::<_, FeeTier>(
+        r#"
+        SELECT up_to, flat_amount, percentage FROM fee_rule_tiers
+        WHERE rule_id = $1
+        ORDER BY tier_index
+        "#,
+    )
+    .bind(rule.rule_id)
+    .fetch_all(conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(())
+}
+
+/// The active rule for a transfer kind, preferring one for the sender's own
+/// KYC level over one for every level.
+async fn find_rule(
+    conn: &mut PgConnection,
+    kind: TransferKind,
+    kyc_level: KycLevel,
+) -> Result<Option<FeeRule>> {
+    let rule = sqlx::query_as::<_, FeeRule>(&format!(
+        r#"{} WHERE active AND kind = $1 AND (kyc_level = $2 OR kyc_level IS NULL)
+        ORDER BY kyc_level IS NULL
+        LIMIT 1"#,
+        SELECT_RULES
+    ))
+    .bind(kind)
+    .bind(kyc_level)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    match rule {
+        Some(mut rule) => {
+            fetch_tiers(conn, &mut rule).await?;
+            Ok(Some(rule))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Prices a transfer for `sender_id`. Transfers no rule covers are free, and
+/// so are payouts, so a closing account can always pay out its whole balance.
+pub async fn quote_fee(
+    conn: &mut PgConnection,
+    sender_id: Uuid,
+    kind: TransferKind,
+    amount: f64,
+) -> Result<FeeQuote> {
+    let kyc_level: KycLevel = sqlx::query(r#"SELECT kyc_level FROM users WHERE userid = $1"#)
+        .bind(sender_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .get("kyc_level");
+    let rule = match kind {
+        TransferKind::Payout => None,
+        _ => find_rule(conn, kind, kyc_level).await?,
+    };
+    let fee = rule.as_ref().map_or(0.0, |rule| compute_fee(rule, amount));
+    Ok(FeeQuote {
+        kind,
+        amount,
+        fee,
+        total: amount + fee,
+        rule_id: rule.map(|rule| rule.rule_id),
+    })
+}
+
+pub async fn quote(
+    pool: &PgPool,
+    username: &str,
+    kind: TransferKind,
+    amount: f64,
+) -> Result<FeeQuote> {
+    debug!(
+        "Quoting {:?} transfer of {} for {:?}",
+        kind, amount, username
+    );
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(ApiError::Validation(
+            "amount must be greater than zero".to_string(),
+        ));
+    }
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let sender_id: Uuid = sqlx::query(r#"SELECT userid FROM users WHERE username = $1"#)
+        .bind(username)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?
+        .get("userid");
+    quote_fee(&mut conn, sender_id, kind, amount).await
+}
+
+/// Credits a fee to the fee account. Runs inside the transfer's transaction,
+/// which has already locked the fee account along with its own two.
+pub async fn collect_fee(conn: &mut PgConnection, fee: f64) -> Result<()> {
+    let result = sqlx::query(r#"UPDATE users SET balance = balance + $1 WHERE userid = $2"#)
+        .bind(fee)
+        .bind(FEE_ACCOUNT_ID)
+        .execute(conn)
+        .await
+        .map_err(ApiError::Database)?;
+    if result.rows_affected() == 0 {
+        error!("Fee account {} is missing", FEE_ACCOUNT_ID);
+        return Err(ApiError::InternalServerError);
+    }
+    Ok(())
+}
+
+/// Adds a rule, deactivating the one it replaces for the same kind and level.
+pub async fn create_rule(pool: &PgPool, new_rule: &NewFeeRule) -> Result<FeeRule> {
+    debug!("Creating fee rule: {:?}", new_rule);
+    validate_rule(new_rule)?;
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    sqlx::query(
+        r#"
+        UPDATE fee_rules SET active = FALSE
+        WHERE active AND kind = $1 AND kyc_level IS NOT DISTINCT FROM $2
+        "#,
+    )
+    .bind(new_rule.kind)
+    .bind(new_rule.kyc_level)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let rule_id = Uuid::new_v4();
+    let mut rule = sqlx::query_as::<_, FeeRule>(
+        r#"
+        INSERT INTO fee_rules
+            (rule_id, kind, kyc_level, fee_type, flat_amount, percentage, min_fee, max_fee)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING rule_id, kind, kyc_level, fee_type, flat_amount, percentage, min_fee, max_fee,
+            active, created_at
+        "#,
+    )
+    .bind(rule_id)
+    .bind(new_rule.kind)
+    .bind(new_rule.kyc_level)
+    .bind(new_rule.fee_type)
+    .bind(new_rule.flat_amount)
+    .bind(new_rule.percentage)
+    .bind(new_rule.min_fee)
+    .bind(new_rule.max_fee)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    for (i, tier) in new_rule.tiers.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO fee_rule_tiers (rule_id, tier_index, up_to, flat_amount, percentage)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(rule_id)
+        .bind(i as i32)
+        .bind(tier.up_to)
+        .bind(tier.flat_amount)
+        .bind(tier.percentage)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    }
+    rule.tiers = new_rule.tiers.clone();
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(rule)
+}
+
+pub async fn list_rules(pool: &PgPool) -> Result<Vec<FeeRule>> {
+    debug!("Listing active fee rules");
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let mut rules = sqlx::query_as::<_, FeeRule>(&format!(
+        "{} WHERE active ORDER BY kind, kyc_level NULLS FIRST",
+        SELECT_RULES
+    ))
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    for rule in rules.iter_mut() {
+        fetch_tiers(&mut conn, rule).await?;
+    }
+    Ok(rules)
+}
+
+/// Stops a rule from applying to new transfers. The rule itself is kept.
+pub async fn deactivate_rule(pool: &PgPool, rule_id: Uuid) -> Result<FeeRule> {
+    debug!("Deactivating fee rule {:?}", rule_id);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let mut rule = sqlx::query_as::<_, FeeRule>(
+        r#"
+        UPDATE fee_rules SET active = FALSE
+        WHERE rule_id = $1
+        RETURNING rule_id, kind, kyc_level, fee_type, flat_amount, percentage, min_fee, max_fee,
+            active, created_at
+        "#,
+    )
+    .bind(rule_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::NotFound)?;
+    fetch_tiers(&mut conn, &mut rule).await?;
+    Ok(rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::{
+        fetch_balance, fetch_transaction, funding, insert_transaction, interest,
+    };
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+    use chrono::Utc;
+
+    fn rule(fee_type: FeeType, tiers: Vec<FeeTier>) -> FeeRule {
+        FeeRule {
+            rule_id: Uuid::new_v4(),
+            kind: TransferKind::P2p,
+            kyc_level: None,
+            fee_type,
+            flat_amount: 0.5,
+            percentage: 2.0,
+            min_fee: Some(1.0),
+            max_fee: Some(10.0),
+            active: true,
+            created_at: Utc::now(),
+            tiers,
+        }
+    }
+
+    async fn ledger_balance(pool: &PgPool, username: &str) -> f64 {
+        fetch_balance(pool, username)
+            .await
+            .unwrap()
+            .unwrap()
+            .ledger_balance
+    }
+
+    #[test]
+    fn test_compute_fee_applies_caps_and_tiers() {
+        assert_eq!(compute_fee(&rule(FeeType::Flat, vec![]), 100.0), 1.0);
+        assert_eq!(
+            compute_fee(&rule(FeeType::Percentage, vec![]), 123.45),
+            2.47
+        );
+        assert_eq!(
+            compute_fee(&rule(FeeType::Percentage, vec![]), 5000.0),
+            10.0
+        );
+        let tiers = vec![
+            FeeTier {
+                up_to: Some(100.0),
+                flat_amount: 1.5,
+                percentage: 0.0,
+            },
+            FeeTier {
+                up_to: None,
+                flat_amount: 0.0,
+                percentage: 1.0,
+            },
+        ];
+        let tiered = rule(FeeType::Tiered, tiers);
+        assert_eq!(compute_fee(&tiered, 100.0), 1.5);
+        assert_eq!(compute_fee(&tiered, 400.0), 4.0);
+    }
+
+    #[tokio::test]
+    async fn test_fee_is_charged_and_credited_in_the_transfer() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        // No other test uses verified senders, so this rule only affects alice.
+        sqlx::query(r#"UPDATE users SET kyc_level = 'verified' WHERE userid = $1"#)
+            .bind(alice.userid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let rule = create_rule(
+            &pool,
+            &NewFeeRule {
+                kind: TransferKind::P2p,
+                kyc_level: Some(KycLevel::Verified),
+                fee_type: FeeType::Percentage,
+                flat_amount: 0.0,
+                percentage: 1.0,
+                min_fee: Some(0.5),
+                max_fee: None,
+                tiers: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        let payout_rule = NewFeeRule {
+            kind: TransferKind::Payout,
+            kyc_level: None,
+            fee_type: FeeType::Flat,
+            flat_amount: 1.0,
+            percentage: 0.0,
+            min_fee: None,
+            max_fee: None,
+            tiers: vec![],
+        };
+        assert!(matches!(
+            create_rule(&pool, &payout_rule).await,
+            Err(ApiError::Validation(_))
+        ));
+
+        let charged = quote(&pool, &alice.username, TransferKind::P2p, 20.0)
+            .await
+            .unwrap();
+        assert_eq!((charged.fee, charged.total), (0.5, 20.5));
+        assert_eq!(charged.rule_id, Some(rule.rule_id));
+        let free = quote(&pool, &bob.username, TransferKind::P2p, 20.0)
+            .await
+            .unwrap();
+        assert_eq!(free.fee, 0.0);
+
+        let fees_before = ledger_balance(&pool, FEE_ACCOUNT_USERNAME).await;
+        let txn = transaction(&alice.username, &bob.username, 20.0);
+        insert_transaction(&pool, &txn).await.unwrap();
+        let too_much = transaction(&alice.username, &bob.username, 79.9);
+        let res = insert_transaction(&pool, &too_much).await;
+        assert!(matches!(res, Err(ApiError::BalanceLow)));
+        deactivate_rule(&pool, rule.rule_id).await.unwrap();
+
+        let stored = fetch_transaction(&pool, txn.txn_id).await.unwrap().unwrap();
+        assert_eq!(stored.fee, 0.5);
+        assert_eq!(ledger_balance(&pool, &alice.username).await, 79.5);
+        assert_eq!(ledger_balance(&pool, &bob.username).await, 20.0);
+        // Other test runs may add fees concurrently.
+        assert!(ledger_balance(&pool, FEE_ACCOUNT_USERNAME).await >= fees_before + 0.5);
+
+        // Users cannot pay straight into a system account.
+        for system in [
+            FEE_ACCOUNT_USERNAME,
+            interest::INTEREST_ACCOUNT_USERNAME,
+            funding::FUNDING_ACCOUNT_USERNAME,
+        ] {
+            let res = insert_transaction(&pool, &transaction(&bob.username, system, 5.0)).await;
+            assert!(matches!(res, Err(ApiError::UserNotFound)));
+        }
+        assert_eq!(ledger_balance(&pool, &bob.username).await, 20.0);
+    }
+}
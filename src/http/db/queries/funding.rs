@@ -0,0 +1,360 @@
+use crate::http::db::model::{
+    FundingDirection, FundingStatus, FundingTransfer, Transaction, TransferKind,
+};
+use crate::http::db::queries::transfer;
+use crate::http::errors::{ApiError, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, error};
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+/// The clearing account deposits are paid from and withdrawals paid into.
+pub const FUNDING_ACCOUNT_USERNAME: &str = "payfree_funding";
+pub const FUNDING_ACCOUNT_ID: Uuid = Uuid::from_u128(0xf0d5);
+
+const SELECT_FUNDING: &str = r#"
+    SELECT f.funding_id, u.username, f.direction, f.amount, f.status, f.provider,
+        f.provider_ref, f.destination, f.failure_reason, f.txn_id, f.return_txn_id,
+        f.created_at, f.updated_at
+    FROM funding_transfers f
+    JOIN users u ON u.userid = f.userid
+"#;
+
+fn validate_amount(amount: f64) -> Result<()> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(ApiError::Validation(
+            "amount must be greater than zero".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn fetch_funding(conn: &mut PgConnection, funding_id: Uuid) -> Result<FundingTransfer> {
+    sqlx::query_as::<_, FundingTransfer>(&format!("{} WHERE f.funding_id = $1", SELECT_FUNDING))
+        .bind(funding_id)
+        .fetch_one(conn)
+        .await
+        .map_err(ApiError::Database)
+}
+
+pub async fn list_funding(pool: &PgPool, username: &str) -> Result<Vec<FundingTransfer>> {
+    debug!("Listing deposits and withdrawals for {:?}", username);
+    sqlx::query_as::<_, FundingTransfer>(&format!(
+        "{} WHERE u.username = $1 ORDER BY f.created_at DESC",
+        SELECT_FUNDING
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Records a pending deposit. No money moves until the provider confirms it.
+pub async fn create_deposit(
+    pool: &PgPool,
+    username: &str,
+    amount: f64,
+    provider: &str,
+) -> Result<FundingTransfer> {
+    debug!(
+        "Creating {} deposit of {} for {:?}",
+        provider, amount, username
+    );
+    validate_amount(amount)?;
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let row = sqlx::query(r#"SELECT userid, status FROM users WHERE username = $1"#)
+        .bind(username)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?;
+    super::ensure_active(row.get("status"))?;
+    let funding_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO funding_transfers (funding_id, userid, direction, amount, provider)
+        VALUES ($1, $2, 'deposit', $3, $4)
+        "#,
+    )
+    .bind(funding_id)
+    .bind(row.get::<Uuid, _>("userid"))
+    .bind(amount)
+    .bind(provider)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    fetch_funding(&mut conn, funding_id).await
+}
+
+/// Records a pending withdrawal and moves the money, fee included, into the
+/// funding account straight away, so it cannot be spent twice while the
+/// provider pays it out.
+pub async fn create_withdrawal(
+    pool: &PgPool,
+    username: &str,
+    amount: f64,
+    provider: &str,
+    destination: &str,
+) -> Result<FundingTransfer> {
+    debug!(
+        "Creating {} withdrawal of {} for {:?} to {}",
+        provider, amount, username, destination
+    );
+    validate_amount(amount)?;
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount,
+        from_username: username.to_string(),
+        to_username: FUNDING_ACCOUNT_USERNAME.to_string(),
+        time: Utc::now(),
+        memo: Some(format!("Withdrawal to {}", destination)),
+        kind: TransferKind::Withdrawal,
+        fee: 0.0,
+    };
+    transfer(&mut tx, &txn).await?;
+    let funding_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO funding_transfers (funding_id, userid, direction, amount, provider,
+            destination, txn_id)
+        SELECT $1, userid, 'withdrawal', $2, $3, $4, $5 FROM users WHERE username = $6
+        "#,
+    )
+    .bind(funding_id)
+    .bind(amount)
+    .bind(provider)
+    .bind(destination)
+    .bind(txn.txn_id)
+    .bind(username)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    let funding = fetch_funding(&mut tx, funding_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(funding)
+}
+
+/// Stores the provider's reference for a transfer, which its callbacks use.
+pub async fn set_provider_ref(
+    pool: &PgPool,
+    funding_id: Uuid,
+    provider_ref: &str,
+) -> Result<FundingTransfer> {
+    debug!(
+        "Setting provider reference {:?} on {}",
+        provider_ref, funding_id
+    );
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    sqlx::query(
+        r#"
+        UPDATE funding_transfers SET provider_ref = $1, updated_at = NOW()
+        WHERE funding_id = $2
+        "#,
+    )
+    .bind(provider_ref)
+    .bind(funding_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    fetch_funding(&mut conn, funding_id).await
+}
+
+/// Pays a completed deposit from the funding account to `userid` with an
+/// ordinary transfer, so the account must be active and able to hold the
+/// amount at its KYC tier.
+async fn credit_deposit(conn: &mut PgConnection, userid: Uuid, amount: f64) -> Result<Uuid> {
+    let username: String = sqlx::query(r#"SELECT username FROM users WHERE userid = $1"#)
+        .bind(userid)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .get("username");
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount,
+        from_username: FUNDING_ACCOUNT_USERNAME.to_string(),
+        to_username: username,
+        time: Utc::now(),
+        memo: Some("Deposit".to_string()),
+        kind: TransferKind::Deposit,
+        fee: 0.0,
+    };
+    transfer(conn, &txn).await?;
+    Ok(txn.txn_id)
+}
+
+/// Gives a failed withdrawal back to `userid`. This is the user's own money
+/// coming back, so unlike a deposit it skips the receiver's checks: a frozen
+/// account, or one at its KYC balance limit, still gets it back.
+async fn return_withdrawal(conn: &mut PgConnection, userid: Uuid, amount: f64) -> Result<Uuid> {
+    let funding_balance_after = match super::debit(&mut *conn, FUNDING_ACCOUNT_ID, amount).await {
+        Err(ApiError::UserNotFound) => {
+            error!("Funding account {} is missing", FUNDING_ACCOUNT_ID);
+            return Err(ApiError::InternalServerError);
+        }
+        result => result?,
+    };
+    let balance_after = super::credit(&mut *conn, userid, amount).await?;
+    let txn_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (txn_id, amount, from_userid, to_userid, time, memo, kind,
+            from_balance_after, to_balance_after)
+        VALUES ($1, $2, $3, $4, NOW(), 'Withdrawal returned', 'withdrawal', $5, $6)
+        "#,
+    )
+    .bind(txn_id)
+    .bind(amount)
+    .bind(FUNDING_ACCOUNT_ID)
+    .bind(userid)
+    .bind(funding_balance_after)
+    .bind(balance_after)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(txn_id)
+}
+
+/// Settles a pending transfer as `completed` or `failed`. A completed
+/// deposit credits the account; a failed withdrawal returns its amount (but
+/// not the fee). A deposit the account cannot receive, because it is frozen
+/// or closed or would go over its KYC balance limit, is failed instead so
+/// the provider sends it back. Transfers that are already settled are
+/// returned unchanged, so a provider may repeat its callbacks.
+async fn settle(
+    conn: &mut PgConnection,
+    funding_id: Uuid,
+    status: FundingStatus,
+    reason: Option<&str>,
+) -> Result<FundingTransfer> {
+    let row = sqlx::query(
+        r#"SELECT userid, direction, amount, status FROM funding_transfers WHERE funding_id = $1 FOR UPDATE"#,
+    )
+    .bind(funding_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    let current: FundingStatus = row.get("status");
+    if current != FundingStatus::Pending {
+        debug!(
+            "Funding transfer {} is already {:?}; ignoring {:?}",
+            funding_id, current, status
+        );
+        return fetch_funding(conn, funding_id).await;
+    }
+    let userid: Uuid = row.get("userid");
+    let amount: f64 = row.get("amount");
+    let direction: FundingDirection = row.get("direction");
+
+    let mut status = status;
+    let mut reason = reason.map(str::to_string);
+    let (txn_id, return_txn_id) = match (direction, status) {
+        (FundingDirection::Deposit, FundingStatus::Completed) => {
+            match credit_deposit(conn, userid, amount).await {
+                Ok(txn_id) => (Some(txn_id), None),
+                Err(
+                    err @ (ApiError::AccountFrozen
+                    | ApiError::AccountClosed
+                    | ApiError::KycLimitExceeded(_)),
+                ) => {
+                    debug!("Refusing deposit {}: {}", funding_id, err);
+                    status = FundingStatus::Failed;
+                    reason = Some(format!("deposit refused: {}", err));
+                    (None, None)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        (FundingDirection::Withdrawal, FundingStatus::Failed) => {
+            let txn_id = return_withdrawal(conn, userid, amount).await?;
+            (None, Some(txn_id))
+        }
+        (_, FundingStatus::Pending) => {
+            return Err(ApiError::Validation(
+                "a transfer can only be settled as completed or failed".to_string(),
+            ));
+        }
+        _ => (None, None),
+    };
+    sqlx::query(
+        r#"
+        UPDATE funding_transfers
+        SET status = $1, failure_reason = $2, txn_id = COALESCE($3, txn_id),
+            return_txn_id = $4, updated_at = NOW()
+        WHERE funding_id = $5
+        "#,
+    )
+    .bind(status)
+    .bind(reason)
+    .bind(txn_id)
+    .bind(return_txn_id)
+    .bind(funding_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    fetch_funding(conn, funding_id).await
+}
+
+/// Settles the transfer a provider's callback refers to.
+pub async fn apply_outcome(
+    pool: &PgPool,
+    provider: &str,
+    provider_ref: &str,
+    status: FundingStatus,
+    reason: Option<&str>,
+) -> Result<FundingTransfer> {
+    debug!(
+        "Applying {:?} from {} to {:?}",
+        status, provider, provider_ref
+    );
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let funding_id: Uuid = sqlx::query(
+        r#"SELECT funding_id FROM funding_transfers WHERE provider = $1 AND provider_ref = $2"#,
+    )
+    .bind(provider)
+    .bind(provider_ref)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::NotFound)?
+    .get("funding_id");
+    let funding = settle(&mut tx, funding_id, status, reason).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(funding)
+}
+
+/// Fails a transfer the provider never accepted.
+pub async fn fail_unstarted(
+    pool: &PgPool,
+    funding_id: Uuid,
+    reason: &str,
+) -> Result<FundingTransfer> {
+    debug!("Failing unstarted funding transfer {}", funding_id);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let funding = settle(&mut tx, funding_id, FundingStatus::Failed, Some(reason)).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(funding)
+}
+
+/// Pending transfers a provider accepted before `before`, oldest first.
+pub async fn pending_before(
+    pool: &PgPool,
+    provider: &str,
+    before: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<FundingTransfer>> {
+    sqlx::query_as::<_, FundingTransfer>(&format!(
+        r#"{} WHERE f.provider = $1 AND f.status = 'pending' AND f.provider_ref IS NOT NULL
+            AND f.created_at < $2
+        ORDER BY f.created_at
+        LIMIT $3"#,
+        SELECT_FUNDING
+    ))
+    .bind(provider)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
@@ -0,0 +1,1168 @@
+use crate::http::db::model::{
+    Debt, ExpenseShare, GroupBalances, GroupExpense, MemberBalance, NewExpense, SplitGroup,
+    SplitType, Transaction, TransferKind,
+};
+use crate::http::db::queries::{transfer, usernames};
+use crate::http::errors::{ApiError, Result};
+use chrono::{DateTime, Utc};
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+pub const MAX_GROUP_MEMBERS: usize = 50;
+
+const SELECT_GROUPS: &str = r#"
+    SELECT g.group_id, g.name, c.username AS created_by, g.created_at,
+        ARRAY(
+            SELECT u.username FROM split_group_members m
+            JOIN users u ON u.userid = m.userid
+            WHERE m.group_id = g.group_id AND m.accepted_at IS NOT NULL
+            ORDER BY m.accepted_at, u.username
+        ) AS members,
+        ARRAY(
+            SELECT u.username FROM split_group_members m
+            JOIN users u ON u.userid = m.userid
+            WHERE m.group_id = g.group_id AND m.accepted_at IS NULL
+            ORDER BY m.joined_at, u.username
+        ) AS invited
+    FROM split_groups g
+    JOIN users c ON c.userid = g.created_by
+"#;
+
+fn to_cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+fn from_cents(cents: i64) -> f64 {
+    cents as f64 / 100.0
+}
+
+/// Splits `amount` between participants, in whole cents. Cents that do not
+/// divide evenly go to the first participants listed.
+pub fn compute_shares(
+    amount: f64,
+    split_type: SplitType,
+    participants: &[(String, Option<f64>)],
+) -> Result<Vec<(String, f64)>> {
+    let total = to_cents(amount);
+    if !amount.is_finite() || total <= 0 {
+        return Err(ApiError::Validation(
+            "amount must be at least 0.01".to_string(),
+        ));
+    }
+    if participants.is_empty() {
+        return Err(ApiError::Validation(
+            "an expense needs at least one participant".to_string(),
+        ));
+    }
+    let mut seen = HashSet::new();
+    if !participants.iter().all(|(name, _)| seen.insert(name)) {
+        return Err(ApiError::Validation(
+            "participants must be unique".to_string(),
+        ));
+    }
+    let value = |v: Option<f64>| match v {
+        Some(v) if v.is_finite() && v >= 0.0 => Ok(v),
+        _ => Err(ApiError::Validation(
+            "every share needs a non-negative value".to_string(),
+        )),
+    };
+
+    let mut cents: Vec<i64> = match split_type {
+        SplitType::Equal => vec![total / participants.len() as i64; participants.len()],
+        SplitType::Percentage => {
+            let percents = participants
+                .iter()
+                .map(|(_, v)| value(*v))
+                .collect::<Result<Vec<f64>>>()?;
+            if (percents.iter().sum::<f64>() - 100.0).abs() > 1e-6 {
+                return Err(ApiError::Validation(
+                    "percentages must add up to 100".to_string(),
+                ));
+            }
+            percents
+                .iter()
+                .map(|p| (total as f64 * p / 100.0).floor() as i64)
+                .collect()
+        }
+        SplitType::Exact => {
+            let cents = participants
+                .iter()
+                .map(|(_, v)| value(*v).map(to_cents))
+                .collect::<Result<Vec<i64>>>()?;
+            if cents.iter().sum::<i64>() != total {
+                return Err(ApiError::Validation(
+                    "exact shares must add up to the amount".to_string(),
+                ));
+            }
+            cents
+        }
+    };
+    let remainder = total - cents.iter().sum::<i64>();
+    for share in cents.iter_mut().take(remainder as usize) {
+        *share += 1;
+    }
+    Ok(participants
+        .iter()
+        .zip(cents)
+        .map(|((name, _), c)| (name.clone(), from_cents(c)))
+        .collect())
+}
+
+/// Settles every balance with at most one fewer transfer than there are
+/// members, by repeatedly paying the largest creditor from the largest debtor.
+pub fn simplify_debts(balances: &[MemberBalance]) -> Vec<Debt> {
+    let mut creditors: Vec<(String, i64)> = Vec::new();
+    let mut debtors: Vec<(String, i64)> = Vec::new();
+    for b in balances {
+        match to_cents(b.net) {
+            c if c > 0 => creditors.push((b.username.clone(), c)),
+            c if c < 0 => debtors.push((b.username.clone(), -c)),
+            _ => {}
+        }
+    }
+    let by_size = |a: &(String, i64), b: &(String, i64)| b.1.cmp(&a.1).then(a.0.cmp(&b.0));
+    creditors.sort_by(by_size);
+    debtors.sort_by(by_size);
+
+    let mut debts = Vec::new();
+    let (mut c, mut d) = (0, 0);
+    while c < creditors.len() && d < debtors.len() {
+        let amount = creditors[c].1.min(debtors[d].1);
+        debts.push(Debt {
+            from_username: debtors[d].0.clone(),
+            to_username: creditors[c].0.clone(),
+            amount: from_cents(amount),
+        });
+        creditors[c].1 -= amount;
+        debtors[d].1 -= amount;
+        if creditors[c].1 == 0 {
+            c += 1;
+        }
+        if debtors[d].1 == 0 {
+            d += 1;
+        }
+    }
+    debts
+}
+
+async fn fetch_group(conn: &mut PgConnection, group_id: Uuid) -> Result<SplitGroup> {
+    sqlx::query_as::<_, SplitGroup>(&format!("{} WHERE g.group_id = $1", SELECT_GROUPS))
+        .bind(group_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)
+}
+
+/// Returns the user's id and whether they accepted, or `None` when they are
+/// neither a member nor invited.
+async fn membership(
+    conn: &mut PgConnection,
+    group_id: Uuid,
+    username: &str,
+) -> Result<Option<(Uuid, bool)>> {
+    Ok(sqlx::query(
+        r#"
+        SELECT u.userid, m.accepted_at IS NOT NULL AS accepted FROM split_group_members m
+        JOIN users u ON u.userid = m.userid
+        WHERE m.group_id = $1 AND u.username = $2
+        "#,
+    )
+    .bind(group_id)
+    .bind(username)
+    .fetch_optional(conn)
+    .await
+    .map_err(ApiError::Database)?
+    .map(|row| (row.get("userid"), row.get("accepted"))))
+}
+
+/// Returns the member's userid. Groups the user is not in, or only invited
+/// to, look missing.
+async fn ensure_member(conn: &mut PgConnection, group_id: Uuid, username: &str) -> Result<Uuid> {
+    match membership(conn, group_id, username).await? {
+        Some((userid, true)) => Ok(userid),
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+/// Adds `username` to the group, as a member when `accepted` and otherwise
+/// as an invitee. Users already in the group are left as they are.
+async fn insert_member(
+    conn: &mut PgConnection,
+    group_id: Uuid,
+    username: &str,
+    accepted: bool,
+) -> Result<()> {
+    let userid = usernames::resolve_username(&mut *conn, username)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    sqlx::query(
+        r#"
+        INSERT INTO split_group_members (group_id, userid, accepted_at) VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(group_id)
+    .bind(userid)
+    .bind(accepted.then(Utc::now))
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(())
+}
+
+async fn ensure_group_size(conn: &mut PgConnection, group_id: Uuid) -> Result<()> {
+    let count: i64 =
+        sqlx::query(r#"SELECT COUNT(*) AS count FROM split_group_members WHERE group_id = $1"#)
+            .bind(group_id)
+            .fetch_one(conn)
+            .await
+            .map_err(ApiError::Database)?
+            .get("count");
+    if count as usize > MAX_GROUP_MEMBERS {
+        return Err(ApiError::Validation(format!(
+            "a group can have at most {} members",
+            MAX_GROUP_MEMBERS
+        )));
+    }
+    Ok(())
+}
+
+/// Creates a group with the creator as its only member, and invites `members`.
+pub async fn create_group(
+    pool: &PgPool,
+    creator: &str,
+    name: &str,
+    members: &[String],
+) -> Result<SplitGroup> {
+    debug!("Creating split group {:?} for {:?}", name, creator);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let creator_id: Uuid = sqlx::query(r#"SELECT userid FROM users WHERE username = $1"#)
+        .bind(creator)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?
+        .get("userid");
+    let group_id = Uuid::new_v4();
+    sqlx::query(r#"INSERT INTO split_groups (group_id, name, created_by) VALUES ($1, $2, $3)"#)
+        .bind(group_id)
+        .bind(name)
+        .bind(creator_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    insert_member(&mut tx, group_id, creator, true).await?;
+    for member in members {
+        insert_member(&mut tx, group_id, member, false).await?;
+    }
+    ensure_group_size(&mut tx, group_id).await?;
+
+    let group = fetch_group(&mut tx, group_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(group)
+}
+
+/// Lists the groups the user is a member of or invited to.
+pub async fn list_groups(pool: &PgPool, username: &str) -> Result<Vec<SplitGroup>> {
+    debug!("Listing split groups for {:?}", username);
+    sqlx::query_as::<_, SplitGroup>(&format!(
+        r#"{} WHERE g.group_id IN (
+            SELECT m.group_id FROM split_group_members m
+            JOIN users u ON u.userid = m.userid
+            WHERE u.username = $1
+        )
+        ORDER BY g.created_at DESC"#,
+        SELECT_GROUPS
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+pub async fn get_group(pool: &PgPool, group_id: Uuid, username: &str) -> Result<SplitGroup> {
+    debug!("Fetching split group {:?} for {:?}", group_id, username);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    // Invitees may look at the group before deciding to join.
+    membership(&mut conn, group_id, username)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    fetch_group(&mut conn, group_id).await
+}
+
+/// Invites a user to a group. Any member may invite others, but they only
+/// join once they accept.
+pub async fn add_member(
+    pool: &PgPool,
+    group_id: Uuid,
+    actor: &str,
+    username: &str,
+) -> Result<SplitGroup> {
+    debug!(
+        "Adding {:?} to split group {:?} by {:?}",
+        username, group_id, actor
+    );
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    ensure_member(&mut tx, group_id, actor).await?;
+    insert_member(&mut tx, group_id, username, false).await?;
+    ensure_group_size(&mut tx, group_id).await?;
+    let group = fetch_group(&mut tx, group_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(group)
+}
+
+/// Accepts the user's invitation, making them a member.
+pub async fn accept_invite(pool: &PgPool, group_id: Uuid, username: &str) -> Result<SplitGroup> {
+    debug!(
+        "{:?} accepting invite to split group {:?}",
+        username, group_id
+    );
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let result = sqlx::query(
+        r#"
+        UPDATE split_group_members m SET accepted_at = NOW()
+        FROM users u
+        WHERE u.userid = m.userid AND m.group_id = $1 AND u.username = $2
+            AND m.accepted_at IS NULL
+        "#,
+    )
+    .bind(group_id)
+    .bind(username)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound);
+    }
+    fetch_group(&mut conn, group_id).await
+}
+
+/// Declines the user's invitation. They can be invited again later.
+pub async fn decline_invite(pool: &PgPool, group_id: Uuid, username: &str) -> Result<()> {
+    debug!(
+        "{:?} declining invite to split group {:?}",
+        username, group_id
+    );
+    let result = sqlx::query(
+        r#"
+        DELETE FROM split_group_members m
+        USING users u
+        WHERE u.userid = m.userid AND m.group_id = $1 AND u.username = $2
+            AND m.accepted_at IS NULL
+        "#,
+    )
+    .bind(group_id)
+    .bind(username)
+    .execute(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound);
+    }
+    Ok(())
+}
+
+/// Records an expense paid by one member and split between members.
+pub async fn add_expense(
+    pool: &PgPool,
+    group_id: Uuid,
+    actor: &str,
+    expense: &NewExpense,
+) -> Result<GroupExpense> {
+    debug!(
+        "Adding expense to split group {:?} by {:?}: {:?}",
+        group_id, actor, expense
+    );
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let actor_id = ensure_member(&mut tx, group_id, actor).await?;
+    let group = fetch_group(&mut tx, group_id).await?;
+
+    let participants: Vec<(String, Option<f64>)> = match &expense.shares {
+        Some(shares) => shares
+            .iter()
+            .map(|s| (s.username.clone(), s.value))
+            .collect(),
+        None if expense.split_type == SplitType::Equal => {
+            group.members.iter().map(|m| (m.clone(), None)).collect()
+        }
+        None => {
+            return Err(ApiError::Validation(
+                "shares are required for percentage and exact splits".to_string(),
+            ));
+        }
+    };
+    let paid_by = expense.paid_by.as_deref().unwrap_or(actor);
+    for name in participants
+        .iter()
+        .map(|(n, _)| n.as_str())
+        .chain([paid_by])
+    {
+        if !group.members.iter().any(|m| m == name) {
+            return Err(ApiError::Validation(format!(
+                "{} is not a member of this group",
+                name
+            )));
+        }
+    }
+    let shares = compute_shares(expense.amount, expense.split_type, &participants)?;
+    let amount = from_cents(to_cents(expense.amount));
+
+    let expense_id = Uuid::new_v4();
+    let created_at: DateTime<Utc> = sqlx::query(
+        r#"
+        INSERT INTO split_expenses
+            (expense_id, group_id, paid_by, amount, description, split_type, created_by)
+        SELECT $1, $2, userid, $4, $5, $6, $7 FROM users WHERE username = $3
+        RETURNING created_at
+        "#,
+    )
+    .bind(expense_id)
+    .bind(group_id)
+    .bind(paid_by)
+    .bind(amount)
+    .bind(&expense.description)
+    .bind(expense.split_type)
+    .bind(actor_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    .get("created_at");
+    for (username, share) in &shares {
+        sqlx::query(
+            r#"
+            INSERT INTO split_expense_shares (expense_id, userid, amount)
+            SELECT $1, userid, $3 FROM users WHERE username = $2
+            "#,
+        )
+        .bind(expense_id)
+        .bind(username)
+        .bind(share)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    }
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(GroupExpense {
+        expense_id,
+        paid_by: paid_by.to_string(),
+        amount,
+        description: expense.description.clone(),
+        split_type: expense.split_type,
+        created_at,
+        shares: shares
+            .into_iter()
+            .map(|(username, amount)| ExpenseShare {
+                username,
+                amount,
+                contested: false,
+                settled: false,
+            })
+            .collect(),
+    })
+}
+
+pub async fn list_expenses(
+    pool: &PgPool,
+    group_id: Uuid,
+    username: &str,
+) -> Result<Vec<GroupExpense>> {
+    debug!("Listing expenses of split group {:?}", group_id);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    ensure_member(&mut conn, group_id, username).await?;
+    load_expenses(&mut conn, group_id, None).await
+}
+
+/// Loads a group's expenses, newest first, or only `expense_id` when given.
+async fn load_expenses(
+    conn: &mut PgConnection,
+    group_id: Uuid,
+    expense_id: Option<Uuid>,
+) -> Result<Vec<GroupExpense>> {
+    let mut shares: HashMap<Uuid, Vec<ExpenseShare>> = HashMap::new();
+    for row in sqlx::query(
+        r#"
+        SELECT s.expense_id, u.username, s.amount, s.contested_at IS NOT NULL AS contested,
+            EXISTS (
+                SELECT 1 FROM split_settlements t
+                WHERE t.expense_id = s.expense_id AND t.from_userid = s.userid
+            ) AS settled
+        FROM split_expense_shares s
+        JOIN split_expenses e ON e.expense_id = s.expense_id
+        JOIN users u ON u.userid = s.userid
+        WHERE e.group_id = $1 AND ($2::uuid IS NULL OR e.expense_id = $2)
+        ORDER BY u.username
+        "#,
+    )
+    .bind(group_id)
+    .bind(expense_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?
+    {
+        shares
+            .entry(row.get("expense_id"))
+            .or_default()
+            .push(ExpenseShare {
+                username: row.get("username"),
+                amount: row.get("amount"),
+                contested: row.get("contested"),
+                settled: row.get("settled"),
+            });
+    }
+
+    let rows = sqlx::query(
+        r#"
+        SELECT e.expense_id, u.username AS paid_by, e.amount, e.description, e.split_type,
+            e.created_at
+        FROM split_expenses e
+        JOIN users u ON u.userid = e.paid_by
+        WHERE e.group_id = $1 AND ($2::uuid IS NULL OR e.expense_id = $2)
+        ORDER BY e.created_at DESC
+        "#,
+    )
+    .bind(group_id)
+    .bind(expense_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let expense_id: Uuid = row.get("expense_id");
+            GroupExpense {
+                expense_id,
+                paid_by: row.get("paid_by"),
+                amount: row.get("amount"),
+                description: row.get("description"),
+                split_type: row.get("split_type"),
+                created_at: row.get("created_at"),
+                shares: shares.remove(&expense_id).unwrap_or_default(),
+            }
+        })
+        .collect())
+}
+
+/// Each member's net position: what they paid and settled out, minus their
+/// shares and the settlements they received. Contested expenses are left out
+/// until every participant withdraws their contest.
+async fn member_balances(conn: &mut PgConnection, group_id: Uuid) -> Result<Vec<MemberBalance>> {
+    let mut balances = sqlx::query_as::<_, MemberBalance>(
+        r#"
+        SELECT u.username,
+            COALESCE((SELECT SUM(e.amount) FROM split_expenses e
+                WHERE e.group_id = m.group_id AND e.paid_by = m.userid
+                    AND NOT EXISTS (SELECT 1 FROM split_expense_shares c
+                        WHERE c.expense_id = e.expense_id AND c.contested_at IS NOT NULL)), 0)
+            - COALESCE((SELECT SUM(s.amount) FROM split_expense_shares s
+                JOIN split_expenses e ON e.expense_id = s.expense_id
+                WHERE e.group_id = m.group_id AND s.userid = m.userid
+                    AND NOT EXISTS (SELECT 1 FROM split_expense_shares c
+                        WHERE c.expense_id = e.expense_id AND c.contested_at IS NOT NULL)), 0)
+            + COALESCE((SELECT SUM(t.amount) FROM split_settlements t
+                WHERE t.group_id = m.group_id AND t.from_userid = m.userid), 0)
+            - COALESCE((SELECT SUM(t.amount) FROM split_settlements t
+                WHERE t.group_id = m.group_id AND t.to_userid = m.userid), 0) AS net
+        FROM split_group_members m
+        JOIN users u ON u.userid = m.userid
+        WHERE m.group_id = $1 AND m.accepted_at IS NOT NULL
+        ORDER BY u.username
+        "#,
+    )
+    .bind(group_id)
+    .fetch_all(conn)
+    .await
+    .map_err(ApiError::Database)?;
+    for b in balances.iter_mut() {
+        b.net = from_cents(to_cents(b.net));
+    }
+    Ok(balances)
+}
+
+pub async fn group_balances(
+    pool: &PgPool,
+    group_id: Uuid,
+    username: &str,
+) -> Result<GroupBalances> {
+    debug!("Computing balances of split group {:?}", group_id);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    ensure_member(&mut conn, group_id, username).await?;
+    let balances = member_balances(&mut conn, group_id).await?;
+    let debts = simplify_debts(&balances);
+    Ok(GroupBalances { balances, debts })
+}
+
+/// Pays the caller's share of the simplified debts with real transfers, all
+/// in one database transaction. Members can only settle what they owe, never
+/// move money out of someone else's account.
+pub async fn settle_up(pool: &PgPool, group_id: Uuid, username: &str) -> Result<Vec<Debt>> {
+    debug!("Settling up split group {:?} for {:?}", group_id, username);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    lock_group(&mut tx, group_id).await?;
+    ensure_member(&mut tx, group_id, username).await?;
+
+    let balances = member_balances(&mut tx, group_id).await?;
+    let owed: Vec<Debt> = simplify_debts(&balances)
+        .into_iter()
+        .filter(|d| d.from_username == username)
+        .collect();
+    for debt in &owed {
+        pay_debt(&mut tx, group_id, None, debt).await?;
+    }
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(owed)
+}
+
+/// Serializes settlements and contests within a group, so balances cannot be
+/// paid twice or change while being paid.
+async fn lock_group(conn: &mut PgConnection, group_id: Uuid) -> Result<()> {
+    sqlx::query(r#"SELECT 1 FROM split_groups WHERE group_id = $1 FOR UPDATE"#)
+        .bind(group_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)?;
+    Ok(())
+}
+
+/// Pays `debt` with a real transfer and records it against the group, and
+/// against `expense_id` when a single share is being settled.
+async fn pay_debt(
+    conn: &mut PgConnection,
+    group_id: Uuid,
+    expense_id: Option<Uuid>,
+    debt: &Debt,
+) -> Result<()> {
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount: debt.amount,
+        from_username: debt.from_username.clone(),
+        to_username: debt.to_username.clone(),
+        time: Utc::now(),
+        memo: None,
+        kind: TransferKind::Settlement,
+        fee: 0.0,
+    };
+    transfer(&mut *conn, &txn).await?;
+    sqlx::query(
+        r#"
+        INSERT INTO split_settlements
+            (settlement_id, group_id, txn_id, from_userid, to_userid, amount, expense_id)
+        SELECT $1, $2, $3, f.userid, t.userid, $6, $7
+        FROM users f, users t
+        WHERE f.username = $4 AND t.username = $5
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(group_id)
+    .bind(txn.txn_id)
+    .bind(&debt.from_username)
+    .bind(&debt.to_username)
+    .bind(debt.amount)
+    .bind(expense_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(())
+}
+
+/// Where a member stands on one expense.
+struct ShareState {
+    paid_by: String,
+    /// `None` when the member has no share in the expense.
+    share: Option<f64>,
+    /// Some participant contests the expense.
+    contested: bool,
+    /// The member already paid their share on its own.
+    settled: bool,
+}
+
+async fn share_state(
+    conn: &mut PgConnection,
+    group_id: Uuid,
+    expense_id: Uuid,
+    userid: Uuid,
+) -> Result<ShareState> {
+    let row = sqlx::query(
+        r#"
+        SELECT p.username AS paid_by,
+            (SELECT s.amount FROM split_expense_shares s
+                WHERE s.expense_id = e.expense_id AND s.userid = $3) AS share,
+            EXISTS (SELECT 1 FROM split_expense_shares s
+                WHERE s.expense_id = e.expense_id AND s.contested_at IS NOT NULL) AS contested,
+            EXISTS (SELECT 1 FROM split_settlements t
+                WHERE t.expense_id = e.expense_id AND t.from_userid = $3) AS settled
+        FROM split_expenses e
+        JOIN users p ON p.userid = e.paid_by
+        WHERE e.group_id = $1 AND e.expense_id = $2
+        "#,
+    )
+    .bind(group_id)
+    .bind(expense_id)
+    .bind(userid)
+    .fetch_optional(conn)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::NotFound)?;
+    Ok(ShareState {
+        paid_by: row.get("paid_by"),
+        share: row.get("share"),
+        contested: row.get("contested"),
+        settled: row.get("settled"),
+    })
+}
+
+/// Pays the caller's share of one expense to the member who paid it, with a
+/// real transfer. Each share can be settled once, and not while anyone
+/// contests the expense.
+pub async fn settle_expense(
+    pool: &PgPool,
+    group_id: Uuid,
+    expense_id: Uuid,
+    username: &str,
+) -> Result<Debt> {
+    debug!(
+        "Settling expense {:?} of split group {:?} for {:?}",
+        expense_id, group_id, username
+    );
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    lock_group(&mut tx, group_id).await?;
+    let userid = ensure_member(&mut tx, group_id, username).await?;
+    let state = share_state(&mut tx, group_id, expense_id, userid).await?;
+    let Some(share) = state.share else {
+        return Err(ApiError::Validation(
+            "you have no share in this expense".to_string(),
+        ));
+    };
+    if state.paid_by == username || to_cents(share) == 0 {
+        return Err(ApiError::Validation(
+            "you owe nothing for this expense".to_string(),
+        ));
+    }
+    if state.contested {
+        return Err(ApiError::Conflict("this expense is contested".to_string()));
+    }
+    if state.settled {
+        return Err(ApiError::Conflict(
+            "you already settled this expense".to_string(),
+        ));
+    }
+
+    let debt = Debt {
+        from_username: username.to_string(),
+        to_username: state.paid_by,
+        amount: share,
+    };
+    pay_debt(&mut tx, group_id, Some(expense_id), &debt).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(debt)
+}
+
+/// Contests the caller's share of an expense, or withdraws their contest.
+/// While contested, the expense counts towards nobody's balance.
+pub async fn contest_expense(
+    pool: &PgPool,
+    group_id: Uuid,
+    expense_id: Uuid,
+    username: &str,
+    contested: bool,
+) -> Result<GroupExpense> {
+    debug!(
+        "Setting contest of expense {:?} in split group {:?} by {:?} to {}",
+        expense_id, group_id, username, contested
+    );
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    lock_group(&mut tx, group_id).await?;
+    let userid = ensure_member(&mut tx, group_id, username).await?;
+    let state = share_state(&mut tx, group_id, expense_id, userid).await?;
+    if state.share.is_none() {
+        return Err(ApiError::Validation(
+            "you have no share in this expense".to_string(),
+        ));
+    }
+    if contested && state.settled {
+        return Err(ApiError::Conflict(
+            "you already settled this expense".to_string(),
+        ));
+    }
+    sqlx::query(
+        r#"
+        UPDATE split_expense_shares
+        SET contested_at = CASE WHEN $3 THEN COALESCE(contested_at, NOW()) END
+        WHERE expense_id = $1 AND userid = $2
+        "#,
+    )
+    .bind(expense_id)
+    .bind(userid)
+    .bind(contested)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let expense = load_expenses(&mut tx, group_id, Some(expense_id))
+        .await?
+        .pop()
+        .ok_or(ApiError::NotFound)?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(expense)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::model::ShareInput;
+    use crate::http::db::queries::fetch_balance;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+
+    fn balance(username: &str, net: f64) -> MemberBalance {
+        MemberBalance {
+            username: username.to_string(),
+            net,
+        }
+    }
+
+    #[test]
+    fn test_compute_shares() {
+        let three: Vec<(String, Option<f64>)> = ["a", "b", "c"]
+            .iter()
+            .map(|n| (n.to_string(), None))
+            .collect();
+        let shares = compute_shares(100.0, SplitType::Equal, &three).unwrap();
+        let amounts: Vec<f64> = shares.iter().map(|(_, a)| *a).collect();
+        assert_eq!(amounts, vec![33.34, 33.33, 33.33]);
+
+        let percents = vec![("a".to_string(), Some(70.0)), ("b".to_string(), Some(30.0))];
+        let shares = compute_shares(10.0, SplitType::Percentage, &percents).unwrap();
+        assert_eq!(shares[0].1, 7.0);
+        let bad = vec![("a".to_string(), Some(70.0)), ("b".to_string(), Some(20.0))];
+        assert!(compute_shares(10.0, SplitType::Percentage, &bad).is_err());
+        let exact = vec![("a".to_string(), Some(4.0)), ("b".to_string(), Some(5.0))];
+        assert!(compute_shares(10.0, SplitType::Exact, &exact).is_err());
+    }
+
+    #[test]
+    fn test_simplify_debts_uses_fewest_transfers() {
+        // a paid 90 for three; b and c each owe a 30, d is even.
+        let debts = simplify_debts(&[
+            balance("a", 60.0),
+            balance("b", -30.0),
+            balance("c", -30.0),
+            balance("d", 0.0),
+        ]);
+        assert_eq!(debts.len(), 2);
+        assert!(
+            debts
+                .iter()
+                .all(|d| d.to_username == "a" && d.amount == 30.0)
+        );
+
+        // A chain a -> b -> c collapses into a single transfer.
+        let debts = simplify_debts(&[balance("a", -10.0), balance("b", 0.0), balance("c", 10.0)]);
+        assert_eq!(
+            debts,
+            vec![Debt {
+                from_username: "a".to_string(),
+                to_username: "c".to_string(),
+                amount: 10.0,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expenses_and_settle_up() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let carol = create_test_user(&pool, "carol", 100.0).await;
+        let outsider = create_test_user(&pool, "outsider", 100.0).await;
+
+        let group = create_group(
+            &pool,
+            &alice.username,
+            "Dinner",
+            &[bob.username.clone(), carol.username.clone()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(group.members, vec![alice.username.clone()]);
+        for member in [&bob, &carol] {
+            accept_invite(&pool, group.group_id, &member.username)
+                .await
+                .unwrap();
+        }
+        let group = get_group(&pool, group.group_id, &bob.username)
+            .await
+            .unwrap();
+        assert_eq!(group.members.len(), 3);
+        assert!(group.invited.is_empty());
+        let res = get_group(&pool, group.group_id, &outsider.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+
+        add_expense(
+            &pool,
+            group.group_id,
+            &alice.username,
+            &NewExpense {
+                amount: 90.0,
+                description: "Dinner".to_string(),
+                paid_by: None,
+                split_type: SplitType::Equal,
+                shares: None,
+            },
+        )
+        .await
+        .unwrap();
+        add_expense(
+            &pool,
+            group.group_id,
+            &bob.username,
+            &NewExpense {
+                amount: 20.0,
+                description: "Taxi".to_string(),
+                paid_by: None,
+                split_type: SplitType::Exact,
+                shares: Some(vec![ShareInput {
+                    username: carol.username.clone(),
+                    value: Some(20.0),
+                }]),
+            },
+        )
+        .await
+        .unwrap();
+
+        // alice +60, bob -30 + 20 = -10, carol -30 - 20 = -50.
+        let balances = group_balances(&pool, group.group_id, &carol.username)
+            .await
+            .unwrap();
+        assert_eq!(balances.debts.len(), 2);
+        let settled = settle_up(&pool, group.group_id, &carol.username)
+            .await
+            .unwrap();
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].amount, 50.0);
+        assert_eq!(
+            fetch_balance(&pool, &carol.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(50.0)
+        );
+
+        let balances = group_balances(&pool, group.group_id, &alice.username)
+            .await
+            .unwrap();
+        assert_eq!(
+            balances.debts,
+            vec![Debt {
+                from_username: bob.username.clone(),
+                to_username: alice.username.clone(),
+                amount: 10.0,
+            }]
+        );
+        // Settling twice pays nothing more.
+        assert!(
+            settle_up(&pool, group.group_id, &carol.username)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    fn dinner(amount: f64) -> NewExpense {
+        NewExpense {
+            amount,
+            description: "Dinner".to_string(),
+            paid_by: None,
+            split_type: SplitType::Equal,
+            shares: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invited_users_join_only_once_they_accept() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let carol = create_test_user(&pool, "carol", 100.0).await;
+
+        let group = create_group(
+            &pool,
+            &alice.username,
+            "Trip",
+            std::slice::from_ref(&bob.username),
+        )
+        .await
+        .unwrap();
+        assert_eq!(group.members, vec![alice.username.clone()]);
+        assert_eq!(group.invited, vec![bob.username.clone()]);
+
+        // An invitee can see the group but takes no part in it yet.
+        get_group(&pool, group.group_id, &bob.username)
+            .await
+            .unwrap();
+        let res = list_expenses(&pool, group.group_id, &bob.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+        let res = add_expense(
+            &pool,
+            group.group_id,
+            &alice.username,
+            &NewExpense {
+                amount: 50.0,
+                description: "Hotel".to_string(),
+                paid_by: None,
+                split_type: SplitType::Exact,
+                shares: Some(vec![ShareInput {
+                    username: bob.username.clone(),
+                    value: Some(50.0),
+                }]),
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+        let expense = add_expense(&pool, group.group_id, &alice.username, &dinner(30.0))
+            .await
+            .unwrap();
+        assert_eq!(expense.shares.len(), 1);
+        let res = settle_up(&pool, group.group_id, &bob.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+
+        // Only invitees can accept or decline, and only once.
+        let res = accept_invite(&pool, group.group_id, &carol.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+        decline_invite(&pool, group.group_id, &bob.username)
+            .await
+            .unwrap();
+        let res = accept_invite(&pool, group.group_id, &bob.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+
+        add_member(&pool, group.group_id, &alice.username, &bob.username)
+            .await
+            .unwrap();
+        let group = accept_invite(&pool, group.group_id, &bob.username)
+            .await
+            .unwrap();
+        assert_eq!(
+            group.members,
+            vec![alice.username.clone(), bob.username.clone()]
+        );
+        let res = decline_invite(&pool, group.group_id, &bob.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_contest_and_settle_single_expenses() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let carol = create_test_user(&pool, "carol", 100.0).await;
+        let group = create_group(
+            &pool,
+            &alice.username,
+            "Flat",
+            &[bob.username.clone(), carol.username.clone()],
+        )
+        .await
+        .unwrap();
+        for member in [&bob, &carol] {
+            accept_invite(&pool, group.group_id, &member.username)
+                .await
+                .unwrap();
+        }
+        let net = |balances: GroupBalances| -> Vec<f64> {
+            balances.balances.iter().map(|b| b.net).collect()
+        };
+        let mut order = [&alice, &bob, &carol].map(|u| u.username.clone());
+        order.sort();
+        let position = |username: &str| order.iter().position(|u| u == username).unwrap();
+
+        let dinner = add_expense(&pool, group.group_id, &alice.username, &dinner(30.0))
+            .await
+            .unwrap();
+        // carol books a taxi entirely on bob.
+        let taxi = add_expense(
+            &pool,
+            group.group_id,
+            &carol.username,
+            &NewExpense {
+                amount: 30.0,
+                description: "Taxi".to_string(),
+                paid_by: None,
+                split_type: SplitType::Exact,
+                shares: Some(vec![ShareInput {
+                    username: bob.username.clone(),
+                    value: Some(30.0),
+                }]),
+            },
+        )
+        .await
+        .unwrap();
+
+        let expense = contest_expense(&pool, group.group_id, taxi.expense_id, &bob.username, true)
+            .await
+            .unwrap();
+        assert!(expense.shares[0].contested);
+        // Only participants can contest.
+        let res = contest_expense(
+            &pool,
+            group.group_id,
+            taxi.expense_id,
+            &alice.username,
+            true,
+        )
+        .await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+        let res = settle_expense(&pool, group.group_id, taxi.expense_id, &bob.username).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+
+        // With the taxi contested, only the dinner counts.
+        let balances = group_balances(&pool, group.group_id, &bob.username)
+            .await
+            .unwrap();
+        let balances = net(balances);
+        assert_eq!(balances[position(&alice.username)], 20.0);
+        assert_eq!(balances[position(&bob.username)], -10.0);
+        assert_eq!(balances[position(&carol.username)], -10.0);
+
+        let paid = settle_expense(&pool, group.group_id, dinner.expense_id, &bob.username)
+            .await
+            .unwrap();
+        assert_eq!(paid.to_username, alice.username);
+        assert_eq!(paid.amount, 10.0);
+        let res = settle_expense(&pool, group.group_id, dinner.expense_id, &bob.username).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+        let res = contest_expense(
+            &pool,
+            group.group_id,
+            dinner.expense_id,
+            &bob.username,
+            true,
+        )
+        .await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+        let res = settle_expense(&pool, group.group_id, dinner.expense_id, &alice.username).await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+        assert_eq!(
+            fetch_balance(&pool, &bob.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(90.0)
+        );
+
+        // Once the contest is withdrawn, the taxi counts again.
+        contest_expense(&pool, group.group_id, taxi.expense_id, &bob.username, false)
+            .await
+            .unwrap();
+        let balances = group_balances(&pool, group.group_id, &alice.username)
+            .await
+            .unwrap();
+        let balances = net(balances);
+        assert_eq!(balances[position(&alice.username)], 10.0);
+        assert_eq!(balances[position(&bob.username)], -30.0);
+        assert_eq!(balances[position(&carol.username)], 20.0);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::http::db::model::{AccountStatus, Hold, HoldStatus, Transaction, TransferKind};
+use crate::http::db::queries::{ensure_active, transfer, usernames};
+use crate::http::errors::{ApiError, Result};
+use chrono::{Duration, Utc};
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+pub const DEFAULT_HOLD_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+pub const MAX_HOLD_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+
+const SELECT_HOLDS: &str = r#"
+    SELECT h.hold_id, p.username AS payer_username, e.username AS payee_username, h.amount,
+        h.captured_amount, h.status, h.txn_id, h.expires_at, h.created_at, h.resolved_at
+    FROM holds h
+    JOIN users p ON p.userid = h.payer_userid
+    JOIN users e ON e.userid = h.payee_userid
+"#;
+
+/// Total of a user's active holds plus funds frozen by unresolved disputes
+/// against them. Holds past their expiry no longer count, even before the
+/// sweeper marks them expired.
+pub async fn held_amount(conn: &mut PgConnection, userid: Uuid) -> Result<f64> {
+    let held: f64 = sqlx::query(
+        r#"
+        SELECT COALESCE((
+            SELECT SUM(amount) FROM holds
+            WHERE payer_userid = $1 AND status = 'active' AND expires_at > NOW()
+        ), 0) + COALESCE((
+            SELECT SUM(frozen_amount) FROM disputes
+            WHERE payee_userid = $1 AND status IN ('open', 'under_review')
+        ), 0) AS held
+        "#,
+    )
+    .bind(userid)
+    .fetch_one(conn)
+    .await
+    .map_err(ApiError::Database)?
+    .get("held");
+    Ok(held)
+}
+
+async fn fetch_hold(conn: &mut PgConnection, hold_id: Uuid) -> Result<Hold> {
+    sqlx::query_as::<_, Hold>(&format!("{} WHERE h.hold_id = $1", SELECT_HOLDS))
+        .bind(hold_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)
+}
+
+/// Reserves `amount` of the payer's available balance for `payee`.
+pub async fn create_hold(
+    pool: &PgPool,
+    payer: &str,
+    payee: &str,
+    amount: f64,
+    ttl_secs: Option<i64>,
+) -> Result<Hold> {
+    debug!(
+        "Creating hold of {} from {:?} for {:?}",
+        amount, payer, payee
+    );
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(ApiError::Validation(
+            "amount must be greater than zero".to_string(),
+        ));
+    }
+    let ttl_secs = ttl_secs.unwrap_or(DEFAULT_HOLD_TTL_SECS);
+    if !(1..=MAX_HOLD_TTL_SECS).contains(&ttl_secs) {
+        return Err(ApiError::Validation(format!(
+            "expires_in_secs must be between 1 and {}",
+            MAX_HOLD_TTL_SECS
+        )));
+    }
+
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    // Locked like a transfer's sender, so holds and transfers cannot both
+    // spend the same available balance.
+    let row =
+        sqlx::query(r#"SELECT userid, balance, status FROM users WHERE username = $1 FOR UPDATE"#)
+            .bind(payer)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(ApiError::Database)?
+            .ok_or(ApiError::UserNotFound)?;
+    let payer_id: Uuid = row.get("userid");
+    let balance: f64 = row.get("balance");
+    ensure_active(row.get("status"))?;
+
+    let payee_id = usernames::resolve_username(&mut tx, payee)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    if payee_id == payer_id {
+        return Err(ApiError::Validation(
+            "cannot place a hold for yourself".to_string(),
+        ));
+    }
+    let payee_status: AccountStatus = sqlx::query(r#"SELECT status FROM users WHERE userid = $1"#)
+        .bind(payee_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .get("status");
+    ensure_active(payee_status)?;
+
+    if balance - held_amount(&mut tx, payer_id).await? < amount {
+        return Err(ApiError::BalanceLow);
+    }
+
+    let hold_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO holds (hold_id, payer_userid, payee_userid, amount, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(hold_id)
+    .bind(payer_id)
+    .bind(payee_id)
+    .bind(amount)
+    .bind(Utc::now() + Duration::seconds(ttl_secs))
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let hold = fetch_hold(&mut tx, hold_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(hold)
+}
+
+pub async fn list_holds(pool: &PgPool, username: &str) -> Result<Vec<Hold>> {
+    debug!("Listing holds for {:?}", username);
+    sqlx::query_as::<_, Hold>(&format!(
+        "{} WHERE p.username = $1 OR e.username = $1 ORDER BY h.created_at DESC",
+        SELECT_HOLDS
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Locks an active hold that `actor` may act on. Holds the actor is not a
+/// party to look missing.
+async fn lock_active(
+    conn: &mut PgConnection,
+    hold_id: Uuid,
+    actor: &str,
+    payee_only: bool,
+) -> Result<Hold> {
+    sqlx::query(r#"SELECT 1 FROM holds WHERE hold_id = $1 FOR UPDATE"#)
+        .bind(hold_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)?;
+    let hold = fetch_hold(conn, hold_id).await?;
+    if hold.payee_username != actor && (payee_only || hold.payer_username != actor) {
+        return Err(ApiError::NotFound);
+    }
+    if hold.status == HoldStatus::Active && hold.expires_at <= Utc::now() {
+        return Err(ApiError::Conflict("hold has expired".to_string()));
+    }
+    if hold.status != HoldStatus::Active {
+        return Err(ApiError::Conflict(
+            format!("hold is already {:?}", hold.status).to_lowercase(),
+        ));
+    }
+    Ok(hold)
+}
+
+/// Moves up to the held amount from payer to payee and releases the rest.
+/// Only the payee can capture.
+pub async fn capture_hold(
+    pool: &PgPool,
+    hold_id: Uuid,
+    payee: &str,
+    amount: Option<f64>,
+) -> Result<Hold> {
+    debug!("Capturing hold {:?} by {:?}: {:?}", hold_id, payee, amount);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let hold = lock_active(&mut tx, hold_id, payee, true).await?;
+    let amount = amount.unwrap_or(hold.amount);
+    if !amount.is_finite() || amount <= 0.0 || amount > hold.amount {
+        return Err(ApiError::Validation(format!(
+            "capture amount must be greater than zero and at most {}",
+            hold.amount
+        )));
+    }
+
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount,
+        from_username: hold.payer_username.clone(),
+        to_username: hold.payee_username.clone(),
+        time: Utc::now(),
+        memo: None,
+        kind: TransferKind::Hold,
+        fee: 0.0,
+    };
+    // The hold stops counting against the payer before the transfer checks
+    // their available balance.
+    sqlx::query(
+        r#"
+        UPDATE holds SET status = 'captured', captured_amount = $1, resolved_at = NOW()
+        WHERE hold_id = $2
+        "#,
+    )
+    .bind(amount)
+    .bind(hold_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    transfer(&mut tx, &txn).await?;
+    sqlx::query(r#"UPDATE holds SET txn_id = $1 WHERE hold_id = $2"#)
+        .bind(txn.txn_id)
+        .bind(hold_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+
+    let hold = fetch_hold(&mut tx, hold_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(hold)
+}
+
+/// Releases a hold without moving money. Either party can void.
+pub async fn void_hold(pool: &PgPool, hold_id: Uuid, actor: &str) -> Result<Hold> {
+    debug!("Voiding hold {:?} by {:?}", hold_id, actor);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    lock_active(&mut tx, hold_id, actor, false).await?;
+    sqlx::query(r#"UPDATE holds SET status = 'voided', resolved_at = NOW() WHERE hold_id = $1"#)
+        .bind(hold_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    let hold = fetch_hold(&mut tx, hold_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(hold)
+}
+
+/// Marks active holds past their expiry as expired and returns how many.
+pub async fn expire_stale_holds(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        UPDATE holds SET status = 'expired', resolved_at = NOW()
+        WHERE status = 'active' AND expires_at <= NOW()
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::model::Balance;
+    use crate::http::db::queries::{fetch_balance, insert_transaction};
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+
+    #[tokio::test]
+    async fn test_hold_reduces_available_balance_and_captures_partially() {
+        let pool = setup_test_db().await;
+        let buyer = create_test_user(&pool, "buyer", 100.0).await;
+        let shop = create_test_user(&pool, "shop", 0.0).await;
+
+        let hold = create_hold(&pool, &buyer.username, &shop.username, 80.0, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            fetch_balance(&pool, &buyer.username).await.unwrap(),
+            Some(Balance {
+                ledger_balance: 100.0,
+                available_balance: 20.0,
+            })
+        );
+        let res =
+            insert_transaction(&pool, &transaction(&buyer.username, &shop.username, 30.0)).await;
+        assert!(matches!(res, Err(ApiError::BalanceLow)));
+
+        // Only the payee captures.
+        let res = capture_hold(&pool, hold.hold_id, &buyer.username, None).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+        let captured = capture_hold(&pool, hold.hold_id, &shop.username, Some(50.0))
+            .await
+            .unwrap();
+        assert_eq!(captured.status, HoldStatus::Captured);
+        assert!(captured.txn_id.is_some());
+        assert_eq!(
+            fetch_balance(&pool, &buyer.username).await.unwrap(),
+            Some(Balance {
+                ledger_balance: 50.0,
+                available_balance: 50.0,
+            })
+        );
+
+        let res = void_hold(&pool, hold.hold_id, &buyer.username).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_void_and_expiry_release_funds() {
+        let pool = setup_test_db().await;
+        let buyer = create_test_user(&pool, "buyer", 100.0).await;
+        let shop = create_test_user(&pool, "shop", 0.0).await;
+
+        let hold = create_hold(&pool, &buyer.username, &shop.username, 60.0, None)
+            .await
+            .unwrap();
+        let res = create_hold(&pool, &buyer.username, &shop.username, 60.0, None).await;
+        assert!(matches!(res, Err(ApiError::BalanceLow)));
+        void_hold(&pool, hold.hold_id, &buyer.username)
+            .await
+            .unwrap();
+
+        let stale = create_hold(&pool, &buyer.username, &shop.username, 60.0, Some(1))
+            .await
+            .unwrap();
+        sqlx::query("UPDATE holds SET expires_at = NOW() - INTERVAL '1 second' WHERE hold_id = $1")
+            .bind(stale.hold_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let available = fetch_balance(&pool, &buyer.username)
+            .await
+            .unwrap()
+            .map(|b| b.available_balance);
+        assert_eq!(available, Some(100.0));
+        let res = capture_hold(&pool, stale.hold_id, &shop.username, None).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+
+        assert!(expire_stale_holds(&pool).await.unwrap() >= 1);
+        let mut conn = pool.acquire().await.unwrap();
+        let expired = fetch_hold(&mut conn, stale.hold_id).await.unwrap();
+        assert_eq!(expired.status, HoldStatus::Expired);
+    }
+}
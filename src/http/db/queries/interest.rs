@@ -0,0 +1,415 @@
+use crate::http::db::model::{
+    InterestAccrual, InterestProduct, InterestSummary, NewInterestProduct, Transaction,
+    TransferKind,
+};
+use crate::http::db::queries::transfer;
+use crate::http::errors::{ApiError, Result};
+use chrono::{Datelike, Days, NaiveDate, NaiveTime, Utc};
+use log::{debug, warn};
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+/// The system account interest is paid from.
+pub const INTEREST_ACCOUNT_USERNAME: &str = "payfree_interest";
+pub const INTEREST_ACCOUNT_ID: Uuid = Uuid::from_u128(0x1e7);
+/// Accruals shown in a user's interest summary.
+const RECENT_ACCRUALS: i64 = 31;
+
+const SELECT_PRODUCTS: &str = r#"
+    SELECT product_id, name, annual_rate, active, created_at, updated_at
+    FROM interest_products
+"#;
+
+pub async fn list_products(pool: &PgPool) -> Result<Vec<InterestProduct>> {
+    debug!("Listing active interest products");
+    sqlx::query_as::<_, InterestProduct>(&format!(
+        "{} WHERE active ORDER BY product_id",
+        SELECT_PRODUCTS
+    ))
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Creates a product or changes its name, rate or availability. A new rate
+/// applies from now on: a day accrued later still earns the rate that was in
+/// force at its end.
+pub async fn save_product(pool: &PgPool, product: &NewInterestProduct) -> Result<InterestProduct> {
+    debug!("Saving interest product: {:?}", product);
+    let product_id = product.product_id.trim();
+    if product_id.is_empty() || product.name.trim().is_empty() {
+        return Err(ApiError::Validation(
+            "product_id and name are required".to_string(),
+        ));
+    }
+    if !product.annual_rate.is_finite() || !(0.0..=100.0).contains(&product.annual_rate) {
+        return Err(ApiError::Validation(
+            "annual_rate must be between 0 and 100".to_string(),
+        ));
+    }
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let saved = sqlx::query_as::<_, InterestProduct>(
+        r#"
+        INSERT INTO interest_products (product_id, name, annual_rate, active)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (product_id) DO UPDATE SET
+            name = EXCLUDED.name,
+            annual_rate = EXCLUDED.annual_rate,
+            active = EXCLUDED.active,
+            updated_at = NOW()
+        RETURNING product_id, name, annual_rate, active, created_at, updated_at
+        "#,
+    )
+    .bind(product_id)
+    .bind(product.name.trim())
+    .bind(product.annual_rate)
+    .bind(product.active)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    sqlx::query(
+        r#"
+        INSERT INTO interest_rates (product_id, annual_rate)
+        SELECT $1, $2
+        WHERE $2 IS DISTINCT FROM (
+            SELECT annual_rate FROM interest_rates WHERE product_id = $1
+            ORDER BY effective_from DESC
+            LIMIT 1
+        )
+        "#,
+    )
+    .bind(&saved.product_id)
+    .bind(saved.annual_rate)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(saved)
+}
+
+/// Puts a user on a product, replacing any product they were on before.
+pub async fn enroll(pool: &PgPool, username: &str, product_id: &str) -> Result<InterestSummary> {
+    debug!(
+        "Enrolling {:?} in interest product {:?}",
+        username, product_id
+    );
+    let product = sqlx::query(&format!(
+        "{} WHERE product_id = $1 AND active",
+        SELECT_PRODUCTS
+    ))
+    .bind(product_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    if product.is_none() {
+        return Err(ApiError::Validation(format!(
+            "no active interest product {}",
+            product_id
+        )));
+    }
+    let result = sqlx::query(
+        r#"
+        INSERT INTO interest_enrollments (userid, product_id)
+        SELECT userid, $2 FROM users WHERE username = $1
+        ON CONFLICT (userid) DO UPDATE SET product_id = EXCLUDED.product_id, enrolled_at = NOW()
+        "#,
+    )
+    .bind(username)
+    .bind(product_id)
+    .execute(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::UserNotFound);
+    }
+    fetch_summary(pool, username).await
+}
+
+/// Stops future accruals. Interest already accrued is still paid out.
+pub async fn unenroll(pool: &PgPool, username: &str) -> Result<InterestSummary> {
+    debug!("Removing {:?} from interest", username);
+    sqlx::query(
+        r#"
+        DELETE FROM interest_enrollments
+        WHERE userid = (SELECT userid FROM users WHERE username = $1)
+        "#,
+    )
+    .bind(username)
+    .execute(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    fetch_summary(pool, username).await
+}
+
+pub async fn fetch_summary(pool: &PgPool, username: &str) -> Result<InterestSummary> {
+    debug!("Fetching interest summary for {:?}", username);
+    let row = sqlx::query(
+        r#"
+        SELECT u.userid, e.product_id, p.annual_rate, e.enrolled_at,
+            (SELECT COALESCE(SUM(amount), 0) FROM interest_accruals a
+            WHERE a.userid = u.userid AND a.payout_txn_id IS NULL) AS unpaid_interest
+        FROM users u
+        LEFT JOIN interest_enrollments e ON e.userid = u.userid
+        LEFT JOIN interest_products p ON p.product_id = e.product_id
+        WHERE u.username = $1
+        "#,
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::UserNotFound)?;
+    let accruals = sqlx::query_as::<_, InterestAccrual>(
+        r#"
+        SELECT accrual_date, product_id, annual_rate, balance, amount, payout_txn_id
+        FROM interest_accruals
+        WHERE userid = $1
+        ORDER BY accrual_date DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(row.get::<Uuid, _>("userid"))
+    .bind(RECENT_ACCRUALS)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(InterestSummary {
+        product_id: row.get("product_id"),
+        annual_rate: row.get("annual_rate"),
+        enrolled_at: row.get("enrolled_at"),
+        unpaid_interest: row.get("unpaid_interest"),
+        accruals,
+    })
+}
+
+/// Accrues one UTC day of interest for every enrolled user, on their balance
+/// and at their product's rate at the end of that day. Users enrolled after
+/// the day ended are skipped.
+/// Days already accrued for a user are left alone, so this is safe to rerun.
+/// Returns the number of accruals added.
+pub async fn accrue_day(pool: &PgPool, day: NaiveDate) -> Result<u64> {
+    debug!("Accruing interest for {}", day);
+    let end_of_day = day
+        .checked_add_days(Days::new(1))
+        .ok_or_else(|| ApiError::Validation("day is out of range".to_string()))?
+        .and_time(NaiveTime::MIN)
+        .and_utc();
+    let result = sqlx::query(
+        r#"
+        INSERT INTO interest_accruals (userid, accrual_date, product_id, annual_rate, balance, amount)
+        SELECT e.userid, $1, p.product_id, rate.annual_rate, eod.balance,
+            eod.balance * rate.annual_rate / 100 / 365
+        FROM interest_enrollments e
+        JOIN interest_products p ON p.product_id = e.product_id
+        JOIN users u ON u.userid = e.userid
+        CROSS JOIN LATERAL (
+            SELECT r.annual_rate FROM interest_rates r
+            WHERE r.product_id = p.product_id AND r.effective_from < $2
+            ORDER BY r.effective_from DESC
+            LIMIT 1
+        ) rate
+        CROSS JOIN LATERAL (
+            SELECT u.balance - COALESCE(SUM(
+                CASE WHEN t.to_userid = u.userid THEN t.amount ELSE -(t.amount + t.fee) END
+            ), 0) AS balance
+            FROM transactions t
+            WHERE (t.from_userid = u.userid OR t.to_userid = u.userid) AND t.created_at >= $2
+        ) eod
+        WHERE p.active AND e.enrolled_at < $2 AND u.status <> 'closed' AND eod.balance > 0
+        ON CONFLICT (userid, accrual_date) DO NOTHING
+        "#,
+    )
+    .bind(day)
+    .bind(end_of_day)
+    .execute(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(result.rows_affected())
+}
+
+/// Pays one user's unpaid interest accrued before `before`, rounded to whole
+/// cents, with a transfer from the interest account. The accruals are locked
+/// and marked paid in the same transaction as the posting, so a crash can
+/// never pay twice.
+async fn pay_out_user(conn: &mut PgConnection, userid: Uuid, before: NaiveDate) -> Result<bool> {
+    let amount: f64 = sqlx::query(
+        r#"
+        SELECT COALESCE(SUM(amount), 0) AS amount FROM (
+            SELECT amount FROM interest_accruals
+            WHERE userid = $1 AND payout_txn_id IS NULL AND accrual_date < $2
+            FOR UPDATE
+        ) unpaid
+        "#,
+    )
+    .bind(userid)
+    .bind(before)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?
+    .get("amount");
+    let amount = (amount * 100.0).round() / 100.0;
+    if amount < 0.01 {
+        return Ok(false);
+    }
+
+    let username: String = sqlx::query(r#"SELECT username FROM users WHERE userid = $1"#)
+        .bind(userid)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .get("username");
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount,
+        from_username: INTEREST_ACCOUNT_USERNAME.to_string(),
+        to_username: username,
+        time: Utc::now(),
+        memo: Some(format!(
+            "Interest up to {}",
+            before.pred_opt().unwrap_or(before)
+        )),
+        kind: TransferKind::Interest,
+        fee: 0.0,
+    };
+    transfer(&mut *conn, &txn).await?;
+    sqlx::query(
+        r#"
+        UPDATE interest_accruals SET payout_txn_id = $1
+        WHERE userid = $2 AND payout_txn_id IS NULL AND accrual_date < $3
+        "#,
+    )
+    .bind(txn.txn_id)
+    .bind(userid)
+    .bind(before)
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(true)
+}
+
+/// Pays out interest accrued before the first day of `month`'s month to every
+/// active user who has some. A user whose account cannot receive the payout,
+/// e.g. because it would go over their KYC balance limit, is skipped and
+/// tried again on the next run. Returns the number of payouts made.
+pub async fn pay_out_month(pool: &PgPool, month: NaiveDate) -> Result<usize> {
+    let before = month.with_day(1).unwrap_or(month);
+    debug!("Paying out interest accrued before {}", before);
+    let userids: Vec<Uuid> = sqlx::query(
+        r#"
+        SELECT DISTINCT a.userid FROM interest_accruals a
+        JOIN users u ON u.userid = a.userid
+        WHERE a.payout_txn_id IS NULL AND a.accrual_date < $1 AND u.status = 'active'
+        "#,
+    )
+    .bind(before)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)?
+    .iter()
+    .map(|row| row.get("userid"))
+    .collect();
+
+    let mut paid = 0;
+    for userid in userids {
+        let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+        match pay_out_user(&mut tx, userid, before).await {
+            Ok(true) => paid += 1,
+            Ok(false) => {}
+            Err(
+                err @ (ApiError::AccountFrozen
+                | ApiError::AccountClosed
+                | ApiError::KycLimitExceeded(_)),
+            ) => {
+                warn!("Skipping interest payout to {}: {}", userid, err);
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+        tx.commit().await.map_err(ApiError::Database)?;
+    }
+    Ok(paid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::{fetch_balance, insert_transaction};
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_accrual_is_idempotent_and_paid_out_once() {
+        let pool = setup_test_db().await;
+        let saver = create_test_user(&pool, "saver", 1000.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        let product_request = NewInterestProduct {
+            product_id: format!("savings_{}", Uuid::new_v4()),
+            name: "Savings".to_string(),
+            annual_rate: 36.5,
+            active: true,
+        };
+        let product = save_product(&pool, &product_request).await.unwrap();
+        enroll(&pool, &saver.username, &product.product_id)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"UPDATE interest_enrollments SET enrolled_at = NOW() - INTERVAL '90 days'
+            WHERE userid = $1"#,
+        )
+        .bind(saver.userid)
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"UPDATE interest_rates SET effective_from = NOW() - INTERVAL '90 days'
+            WHERE product_id = $1"#,
+        )
+        .bind(&product.product_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        // Sent today, so the balance at the end of last month was still 1000.
+        insert_transaction(&pool, &transaction(&saver.username, &bob.username, 100.0))
+            .await
+            .unwrap();
+
+        let month_start = Utc::now().date_naive().with_day(1).unwrap();
+        let last_day = month_start.pred_opt().unwrap();
+        let day_before = last_day.pred_opt().unwrap();
+        accrue_day(&pool, day_before).await.unwrap();
+        accrue_day(&pool, last_day).await.unwrap();
+        accrue_day(&pool, last_day).await.unwrap();
+        let summary = fetch_summary(&pool, &saver.username).await.unwrap();
+        assert_eq!(summary.accruals.len(), 2);
+        assert_eq!(summary.accruals[0].balance, 1000.0);
+        assert!((summary.unpaid_interest - 2.0).abs() < 1e-9);
+
+        // A day caught up after a rate change still earns the old rate.
+        save_product(
+            &pool,
+            &NewInterestProduct {
+                annual_rate: 73.0,
+                ..product_request
+            },
+        )
+        .await
+        .unwrap();
+        accrue_day(&pool, day_before.pred_opt().unwrap())
+            .await
+            .unwrap();
+        let summary = fetch_summary(&pool, &saver.username).await.unwrap();
+        assert_eq!(summary.accruals[2].annual_rate, 36.5);
+        assert!((summary.unpaid_interest - 3.0).abs() < 1e-9);
+
+        pay_out_month(&pool, Utc::now().date_naive()).await.unwrap();
+        pay_out_month(&pool, Utc::now().date_naive()).await.unwrap();
+        let balance = fetch_balance(&pool, &saver.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(balance.ledger_balance, 903.0);
+        let summary = fetch_summary(&pool, &saver.username).await.unwrap();
+        assert_eq!(summary.unpaid_interest, 0.0);
+        assert!(summary.accruals.iter().all(|a| a.payout_txn_id.is_some()));
+    }
+}
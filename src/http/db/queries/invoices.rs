@@ -0,0 +1,467 @@
+use crate::http::db::model::{
+    Invoice, InvoiceItem, InvoiceStatus, NewInvoiceItem, Transaction, TransferKind,
+};
+use crate::http::db::queries::{annotations, transfer};
+use crate::http::errors::{ApiError, Result};
+use chrono::{NaiveDate, Utc};
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+pub const MAX_INVOICE_ITEMS: usize = 100;
+pub const MAX_ITEM_DESCRIPTION_LEN: usize = 200;
+
+/// An open invoice is overdue once its due date has passed in UTC.
+const SELECT_INVOICES: &str = r#"
+    SELECT * FROM (
+        SELECT i.invoice_id, 'INV-' || LPAD(i.invoice_no::TEXT, 6, '0') AS number,
+            mu.username AS merchant_username, m.display_name AS merchant_display_name,
+            cu.username AS customer_username, i.memo, i.subtotal, i.tax, i.total,
+            CASE WHEN i.status = 'open' AND i.due_date < (NOW() AT TIME ZONE 'UTC')::DATE
+                THEN 'overdue' ELSE i.status END AS status,
+            i.due_date, i.txn_id, i.created_at, i.paid_at, i.voided_at
+        FROM invoices i
+        JOIN merchants m ON m.userid = i.merchant_userid
+        JOIN users mu ON mu.userid = i.merchant_userid
+        JOIN users cu ON cu.userid = i.customer_userid
+    ) invoices
+"#;
+
+fn cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Prices the line items, rounding each line's amount and tax to whole
+/// cents before they are added up.
+fn price_items(items: &[NewInvoiceItem]) -> Result<Vec<InvoiceItem>> {
+    if items.is_empty() || items.len() > MAX_INVOICE_ITEMS {
+        return Err(ApiError::Validation(format!(
+            "an invoice needs between 1 and {} items",
+            MAX_INVOICE_ITEMS
+        )));
+    }
+    items
+        .iter()
+        .zip(1..)
+        .map(|(item, line_no)| {
+            let description = item.description.trim();
+            if description.is_empty() || description.chars().count() > MAX_ITEM_DESCRIPTION_LEN {
+                return Err(ApiError::Validation(format!(
+                    "item descriptions must be between 1 and {} characters",
+                    MAX_ITEM_DESCRIPTION_LEN
+                )));
+            }
+            if !item.quantity.is_finite() || item.quantity <= 0.0 {
+                return Err(ApiError::Validation(
+                    "item quantity must be greater than zero".to_string(),
+                ));
+            }
+            if !item.unit_price.is_finite() || item.unit_price < 0.0 {
+                return Err(ApiError::Validation(
+                    "item unit_price must not be negative".to_string(),
+                ));
+            }
+            if !(0.0..=100.0).contains(&item.tax_rate) {
+                return Err(ApiError::Validation(
+                    "item tax_rate must be between 0 and 100".to_string(),
+                ));
+            }
+            let amount = cents(item.quantity * item.unit_price);
+            Ok(InvoiceItem {
+                line_no,
+                description: description.to_string(),
+                quantity: item.quantity,
+                unit_price: item.unit_price,
+                tax_rate: item.tax_rate,
+                amount,
+                tax: cents(amount * item.tax_rate / 100.0),
+            })
+        })
+        .collect()
+}
+
+async fn fetch_invoice_with(conn: &mut PgConnection, invoice_id: Uuid) -> Result<Invoice> {
+    let mut invoice =
+        sqlx::query_as::<_, Invoice>(&format!("{} WHERE invoice_id = $1", SELECT_INVOICES))
+            .bind(invoice_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(ApiError::Database)?
+            .ok_or(ApiError::NotFound)?;
+    invoice.items = sqlx::query_as::<_, InvoiceItem>(
+        r#"
+        SELECT line_no, description, quantity, unit_price, tax_rate, amount, tax
+        FROM invoice_items WHERE invoice_id = $1 ORDER BY line_no
+        "#,
+    )
+    .bind(invoice_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(invoice)
+}
+
+/// Fetches an invoice with its items. Only its merchant and customer can
+/// see it; anyone else gets `NotFound`.
+pub async fn fetch_invoice(pool: &PgPool, invoice_id: Uuid, actor: &str) -> Result<Invoice> {
+    debug!("Fetching invoice {} for {:?}", invoice_id, actor);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let invoice = fetch_invoice_with(&mut conn, invoice_id).await?;
+    if actor != invoice.merchant_username && actor != invoice.customer_username {
+        return Err(ApiError::NotFound);
+    }
+    Ok(invoice)
+}
+
+/// Invoices the user sent or was sent, newest first, optionally in one
+/// status.
+pub async fn list_invoices(
+    pool: &PgPool,
+    username: &str,
+    status: Option<InvoiceStatus>,
+) -> Result<Vec<Invoice>> {
+    debug!("Listing {:?} invoices of {:?}", status, username);
+    sqlx::query_as::<_, Invoice>(&format!(
+        r#"{} WHERE (merchant_username = $1 OR customer_username = $1)
+            AND ($2::TEXT IS NULL OR status = $2)
+        ORDER BY created_at DESC"#,
+        SELECT_INVOICES
+    ))
+    .bind(username)
+    .bind(status)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Bills `customer` for the items. The merchant's next invoice number is
+/// taken under a lock on their profile, so numbers have no gaps or repeats.
+pub async fn create_invoice(
+    pool: &PgPool,
+    merchant: &str,
+    customer: &str,
+    due_date: NaiveDate,
+    memo: Option<&str>,
+    items: &[NewInvoiceItem],
+) -> Result<Invoice> {
+    debug!(
+        "Creating invoice from {:?} to {:?} due {}",
+        merchant, customer, due_date
+    );
+    let memo = match memo {
+        Some(memo) => annotations::sanitize_memo(memo)?,
+        None => None,
+    };
+    if due_date < Utc::now().date_naive() {
+        return Err(ApiError::Validation(
+            "due_date must not be in the past".to_string(),
+        ));
+    }
+    let items = price_items(items)?;
+    let subtotal = cents(items.iter().map(|item| item.amount).sum());
+    let tax = cents(items.iter().map(|item| item.tax).sum());
+    if subtotal + tax <= 0.0 {
+        return Err(ApiError::Validation(
+            "an invoice total must be greater than zero".to_string(),
+        ));
+    }
+
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let merchant_row = sqlx::query(
+        r#"
+        SELECT m.userid, m.next_invoice_no, u.status
+        FROM merchants m
+        JOIN users u ON u.userid = m.userid
+        WHERE u.username = $1
+        FOR UPDATE OF m
+        "#,
+    )
+    .bind(merchant)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or_else(|| ApiError::Validation("only merchants can send invoices".to_string()))?;
+    super::ensure_active(merchant_row.get("status"))?;
+    let merchant_id: Uuid = merchant_row.get("userid");
+    let customer_id: Uuid =
+        sqlx::query(r#"SELECT userid FROM users WHERE username = $1 AND status = 'active'"#)
+            .bind(customer)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(ApiError::Database)?
+            .ok_or(ApiError::UserNotFound)?
+            .get("userid");
+    if customer_id == merchant_id {
+        return Err(ApiError::Validation(
+            "merchants cannot invoice themselves".to_string(),
+        ));
+    }
+    if super::is_system_account(customer_id) {
+        return Err(ApiError::UserNotFound);
+    }
+
+    let invoice_id = Uuid::new_v4();
+    let invoice_no: i32 = merchant_row.get("next_invoice_no");
+    sqlx::query(
+        r#"
+        INSERT INTO invoices (invoice_id, merchant_userid, invoice_no, customer_userid, memo,
+            subtotal, tax, total, due_date)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(merchant_id)
+    .bind(invoice_no)
+    .bind(customer_id)
+    .bind(memo)
+    .bind(subtotal)
+    .bind(tax)
+    .bind(cents(subtotal + tax))
+    .bind(due_date)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    for item in &items {
+        sqlx::query(
+            r#"
+            INSERT INTO invoice_items (invoice_id, line_no, description, quantity, unit_price,
+                tax_rate, amount, tax)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(invoice_id)
+        .bind(item.line_no)
+        .bind(&item.description)
+        .bind(item.quantity)
+        .bind(item.unit_price)
+        .bind(item.tax_rate)
+        .bind(item.amount)
+        .bind(item.tax)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    }
+    sqlx::query(r#"UPDATE merchants SET next_invoice_no = next_invoice_no + 1 WHERE userid = $1"#)
+        .bind(merchant_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    let invoice = fetch_invoice_with(&mut tx, invoice_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(invoice)
+}
+
+/// Locks an invoice that can still be paid or voided.
+async fn lock_payable(conn: &mut PgConnection, invoice_id: Uuid) -> Result<Invoice> {
+    sqlx::query(r#"SELECT 1 FROM invoices WHERE invoice_id = $1 FOR UPDATE"#)
+        .bind(invoice_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)?;
+    let invoice = fetch_invoice_with(conn, invoice_id).await?;
+    if !matches!(invoice.status, InvoiceStatus::Open | InvoiceStatus::Overdue) {
+        return Err(ApiError::Conflict(
+            format!("invoice is already {:?}", invoice.status).to_lowercase(),
+        ));
+    }
+    Ok(invoice)
+}
+
+/// Pays an invoice in full from its customer's balance. The payment goes
+/// through `transfer` like any other, with kind `invoice`, in the same
+/// database transaction that marks the invoice paid, so it is paid at most
+/// once. Overdue invoices can still be paid.
+pub async fn pay_invoice(pool: &PgPool, invoice_id: Uuid, payer: &str) -> Result<Invoice> {
+    debug!("Paying invoice {} by {:?}", invoice_id, payer);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let invoice = fetch_invoice_with(&mut tx, invoice_id).await?;
+    if invoice.customer_username != payer {
+        return Err(ApiError::NotFound);
+    }
+    let invoice = lock_payable(&mut tx, invoice_id).await?;
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount: invoice.total,
+        from_username: payer.to_string(),
+        to_username: invoice.merchant_username.clone(),
+        time: Utc::now(),
+        memo: Some(format!("Invoice {}", invoice.number)),
+        kind: TransferKind::Invoice,
+        fee: 0.0,
+    };
+    transfer(&mut tx, &txn).await?;
+    sqlx::query(
+        r#"UPDATE invoices SET status = 'paid', txn_id = $2, paid_at = NOW() WHERE invoice_id = $1"#,
+    )
+    .bind(invoice_id)
+    .bind(txn.txn_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    let invoice = fetch_invoice_with(&mut tx, invoice_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(invoice)
+}
+
+/// Cancels an unpaid invoice. Only its merchant can void it.
+pub async fn void_invoice(pool: &PgPool, invoice_id: Uuid, merchant: &str) -> Result<Invoice> {
+    debug!("Voiding invoice {} by {:?}", invoice_id, merchant);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let invoice = fetch_invoice_with(&mut tx, invoice_id).await?;
+    if invoice.merchant_username != merchant {
+        return Err(ApiError::NotFound);
+    }
+    lock_payable(&mut tx, invoice_id).await?;
+    sqlx::query(r#"UPDATE invoices SET status = 'void', voided_at = NOW() WHERE invoice_id = $1"#)
+        .bind(invoice_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    let invoice = fetch_invoice_with(&mut tx, invoice_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(invoice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::model::SettlementSchedule;
+    use crate::http::db::queries::{fees, fetch_balance, merchants};
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+    use chrono::Days;
+
+    fn item(description: &str, quantity: f64, unit_price: f64, tax_rate: f64) -> NewInvoiceItem {
+        NewInvoiceItem {
+            description: description.to_string(),
+            quantity,
+            unit_price,
+            tax_rate,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invoice_is_totalled_numbered_and_paid_once() {
+        let pool = setup_test_db().await;
+        let shop = create_test_user(&pool, "shop", 0.0).await;
+        let customer = create_test_user(&pool, "customer", 100.0).await;
+        let due = Utc::now().date_naive() + Days::new(14);
+        let items = [
+            item("Widget", 3.0, 9.99, 20.0),
+            item("Delivery", 1.0, 5.0, 0.0),
+        ];
+
+        let res =
+            create_invoice(&pool, &shop.username, &customer.username, due, None, &items).await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+        merchants::save_merchant(
+            &pool,
+            &shop.username,
+            "Widget Shop",
+            SettlementSchedule::Manual,
+            0.0,
+            None,
+        )
+        .await
+        .unwrap();
+        let res = create_invoice(
+            &pool,
+            &shop.username,
+            fees::FEE_ACCOUNT_USERNAME,
+            due,
+            None,
+            &items,
+        )
+        .await;
+        assert!(matches!(res, Err(ApiError::UserNotFound)));
+
+        let invoice = create_invoice(
+            &pool,
+            &shop.username,
+            &customer.username,
+            due,
+            Some("Order 7"),
+            &items,
+        )
+        .await
+        .unwrap();
+        assert_eq!(invoice.number, "INV-000001");
+        assert_eq!(invoice.merchant_display_name, "Widget Shop");
+        assert_eq!(invoice.status, InvoiceStatus::Open);
+        assert_eq!(invoice.items.len(), 2);
+        assert_eq!(invoice.subtotal, 34.97);
+        assert_eq!(invoice.tax, 5.99);
+        assert_eq!(invoice.total, 40.96);
+
+        let res = pay_invoice(&pool, invoice.invoice_id, &shop.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+        let paid = pay_invoice(&pool, invoice.invoice_id, &customer.username)
+            .await
+            .unwrap();
+        assert_eq!(paid.status, InvoiceStatus::Paid);
+        assert!(paid.txn_id.is_some());
+        let res = pay_invoice(&pool, invoice.invoice_id, &customer.username).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+        let balance = fetch_balance(&pool, &shop.username).await.unwrap().unwrap();
+        assert_eq!(balance.ledger_balance, 40.96);
+
+        let second = create_invoice(
+            &pool,
+            &shop.username,
+            &customer.username,
+            due,
+            None,
+            &items[1..],
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.number, "INV-000002");
+        let voided = void_invoice(&pool, second.invoice_id, &shop.username)
+            .await
+            .unwrap();
+        assert_eq!(voided.status, InvoiceStatus::Void);
+        let res = pay_invoice(&pool, second.invoice_id, &customer.username).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_open_invoice_past_its_due_date_is_overdue() {
+        let pool = setup_test_db().await;
+        let shop = create_test_user(&pool, "lateshop", 0.0).await;
+        let customer = create_test_user(&pool, "latecust", 50.0).await;
+        merchants::save_merchant(
+            &pool,
+            &shop.username,
+            "Late Shop",
+            SettlementSchedule::Manual,
+            0.0,
+            None,
+        )
+        .await
+        .unwrap();
+        let invoice = create_invoice(
+            &pool,
+            &shop.username,
+            &customer.username,
+            Utc::now().date_naive(),
+            None,
+            &[item("Consulting", 2.0, 10.0, 0.0)],
+        )
+        .await
+        .unwrap();
+        sqlx::query(r#"UPDATE invoices SET due_date = due_date - 1 WHERE invoice_id = $1"#)
+            .bind(invoice.invoice_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let overdue = list_invoices(&pool, &customer.username, Some(InvoiceStatus::Overdue))
+            .await
+            .unwrap();
+        assert_eq!(overdue.len(), 1);
+        let paid = pay_invoice(&pool, invoice.invoice_id, &customer.username)
+            .await
+            .unwrap();
+        assert_eq!(paid.status, InvoiceStatus::Paid);
+    }
+}
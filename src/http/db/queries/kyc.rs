@@ -0,0 +1,341 @@
+use crate::http::db::model::{KycLevel, KycLimits, KycVerification, VerificationStatus};
+use crate::http::errors::{ApiError, Result};
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+pub async fn fetch_kyc_limits(conn: &mut PgConnection, userid: Uuid) -> Result<KycLimits> {
+    sqlx::query_as::<_, KycLimits>(
+        r#"
+        SELECT l.kyc_level, l.max_single_transfer, l.max_daily_outflow, l.max_balance
+        FROM users u
+        JOIN kyc_limits l ON l.kyc_level = u.kyc_level
+        WHERE u.userid = $1
+        "#,
+    )
+    .bind(userid)
+    .fetch_one(conn)
+    .await
+    .map_err(ApiError::Database)
+}
+
+pub async fn fetch_user_kyc_limits(pool: &PgPool, username: &str) -> Result<Option<KycLimits>> {
+    debug!("Fetching KYC limits for user: {:?}", username);
+    sqlx::query_as::<_, KycLimits>(
+        r#"
+        SELECT l.kyc_level, l.max_single_transfer, l.max_daily_outflow, l.max_balance
+        FROM users u
+        JOIN kyc_limits l ON l.kyc_level = u.kyc_level
+        WHERE u.username = $1
+        "#,
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+async fn enforce_sender_limits(
+    conn: &mut PgConnection,
+    sender_id: Uuid,
+    amount: f64,
+    fee: f64,
+) -> Result<()> {
+    let sender_limits = fetch_kyc_limits(&mut *conn, sender_id).await?;
+    if let Some(max) = sender_limits.max_single_transfer
+        && amount > max
+    {
+        debug!(
+            "Transfer of {} exceeds single transfer limit {}",
+            amount, max
+        );
+        return Err(ApiError::KycLimitExceeded(format!(
+            "single transfer limit of {} for your verification level",
+            max
+        )));
+    }
+
+    if let Some(max) = sender_limits.max_daily_outflow {
+        let sent_today: f64 = sqlx::query(
+            r#"
+            SELECT COALESCE(SUM(amount + fee), 0) AS total FROM transactions
+            WHERE from_userid = $1 AND kind <> 'reversal'
+                AND created_at >= date_trunc('day', NOW())
+            "#,
+        )
+        .bind(sender_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .get("total");
+        if sent_today + amount + fee > max {
+            debug!(
+                "Transfer of {} exceeds daily outflow limit {} (sent {})",
+                amount, max, sent_today
+            );
+            return Err(ApiError::KycLimitExceeded(format!(
+                "daily outflow limit of {} for your verification level",
+                max
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks a transfer against the sender's and receiver's KYC tier limits.
+/// Runs inside the transfer's database transaction, after the sender row is
+/// locked, so concurrent transfers cannot both slip under the daily limit.
+/// Fees count toward the daily outflow. System accounts have no limits on
+/// either side.
+pub async fn enforce_kyc_limits(
+    conn: &mut PgConnection,
+    sender_id: Uuid,
+    receiver_id: Uuid,
+    amount: f64,
+    fee: f64,
+) -> Result<()> {
+    if !super::is_system_account(sender_id) {
+        enforce_sender_limits(&mut *conn, sender_id, amount, fee).await?;
+    }
+    enforce_receiver_limits(conn, receiver_id, amount).await
+}
+
+/// Checks that the receiver can hold `amount` more at their KYC tier.
+pub async fn enforce_receiver_limits(
+    conn: &mut PgConnection,
+    receiver_id: Uuid,
+    amount: f64,
+) -> Result<()> {
+    if super::is_system_account(receiver_id) {
+        return Ok(());
+    }
+    let receiver_limits = fetch_kyc_limits(&mut *conn, receiver_id).await?;
+    if let Some(max) = receiver_limits.max_balance {
+        let receiver_balance: f64 = sqlx::query(r#"SELECT balance FROM users WHERE userid = $1"#)
+            .bind(receiver_id)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(ApiError::Database)?
+            .get("balance");
+        if receiver_balance + amount > max {
+            debug!("Transfer of {} exceeds recipient balance limit", amount);
+            return Err(ApiError::KycLimitExceeded(
+                "recipient cannot hold this amount at their verification level".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub async fn submit_verification(
+    pool: &PgPool,
+    username: &str,
+    requested_level: KycLevel,
+    document_type: &str,
+    document_number: &str,
+    notes: Option<&str>,
+    submitted_by: &str,
+) -> Result<KycVerification> {
+    debug!(
+        "Submitting KYC verification for {:?} at level {:?}",
+        username, requested_level
+    );
+    if requested_level == KycLevel::Unverified {
+        return Err(ApiError::Validation(
+            "requested level must be basic or verified".to_string(),
+        ));
+    }
+    sqlx::query_as::<_, KycVerification>(
+        r#"
+        INSERT INTO kyc_verifications
+            (verification_id, userid, requested_level, document_type, document_number, notes, submitted_by)
+        SELECT $1, userid, $3, $4, $5, $6, $7 FROM users WHERE username = $2
+        RETURNING *
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(username)
+    .bind(requested_level)
+    .bind(document_type)
+    .bind(document_number)
+    .bind(notes)
+    .bind(submitted_by)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::UserNotFound)
+}
+
+pub async fn fetch_verifications(pool: &PgPool, username: &str) -> Result<Vec<KycVerification>> {
+    debug!("Fetching KYC verifications for user: {:?}", username);
+    sqlx::query_as::<_, KycVerification>(
+        r#"
+        SELECT k.* FROM kyc_verifications k
+        JOIN users u ON u.userid = k.userid
+        WHERE u.username = $1
+        ORDER BY k.submitted_at DESC
+        "#,
+    )
+    .bind(username)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Approves or rejects a pending verification. Approval moves the user up to
+/// the requested tier in the same database transaction; approving a request
+/// below the user's current tier leaves the tier as it is.
+pub async fn review_verification(
+    pool: &PgPool,
+    verification_id: Uuid,
+    approve: bool,
+    reviewed_by: &str,
+    notes: Option<&str>,
+) -> Result<KycVerification> {
+    debug!(
+        "Reviewing KYC verification {:?}: approve={}",
+        verification_id, approve
+    );
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let status: VerificationStatus = sqlx::query(
+        r#"SELECT status FROM kyc_verifications WHERE verification_id = $1 FOR UPDATE"#,
+    )
+    .bind(verification_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::NotFound)?
+    .get("status");
+    if status != VerificationStatus::Pending {
+        return Err(ApiError::Validation(
+            "verification has already been reviewed".to_string(),
+        ));
+    }
+
+    let new_status = if approve {
+        VerificationStatus::Approved
+    } else {
+        VerificationStatus::Rejected
+    };
+    let verification = sqlx::query_as::<_, KycVerification>(
+        r#"
+        UPDATE kyc_verifications
+        SET status = $1, reviewed_by = $2, reviewed_at = NOW(), notes = COALESCE($3, notes)
+        WHERE verification_id = $4
+        RETURNING *
+        "#,
+    )
+    .bind(new_status)
+    .bind(reviewed_by)
+    .bind(notes)
+    .bind(verification_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    if approve {
+        let current: KycLevel =
+            sqlx::query(r#"SELECT kyc_level FROM users WHERE userid = $1 FOR UPDATE"#)
+                .bind(verification.userid)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(ApiError::Database)?
+                .get("kyc_level");
+        if verification.requested_level > current {
+            sqlx::query(r#"UPDATE users SET kyc_level = $1 WHERE userid = $2"#)
+                .bind(verification.requested_level)
+                .bind(verification.userid)
+                .execute(&mut *tx)
+                .await
+                .map_err(ApiError::Database)?;
+        }
+    }
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::insert_transaction;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction as txn};
+
+    #[tokio::test]
+    async fn test_unverified_limits_and_approval() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 5000.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+
+        let res = insert_transaction(&pool, &txn(&alice.username, &bob.username, 1500.0)).await;
+        assert!(matches!(res, Err(ApiError::KycLimitExceeded(_))));
+
+        insert_transaction(&pool, &txn(&alice.username, &bob.username, 1000.0))
+            .await
+            .unwrap();
+        insert_transaction(&pool, &txn(&alice.username, &bob.username, 1000.0))
+            .await
+            .unwrap();
+        // 2000 sent today, so another 1000 breaks the 2500 daily outflow.
+        let res = insert_transaction(&pool, &txn(&alice.username, &bob.username, 1000.0)).await;
+        assert!(matches!(res, Err(ApiError::KycLimitExceeded(_))));
+
+        let verification = submit_verification(
+            &pool,
+            &alice.username,
+            KycLevel::Basic,
+            "passport",
+            "P1234567",
+            None,
+            "admin",
+        )
+        .await
+        .unwrap();
+        assert_eq!(verification.status, VerificationStatus::Pending);
+        let reviewed =
+            review_verification(&pool, verification.verification_id, true, "admin", None)
+                .await
+                .unwrap();
+        assert_eq!(reviewed.status, VerificationStatus::Approved);
+
+        insert_transaction(&pool, &txn(&alice.username, &bob.username, 1500.0))
+            .await
+            .unwrap();
+        let limits = fetch_user_kyc_limits(&pool, &alice.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(limits.kyc_level, KycLevel::Basic);
+
+        let res =
+            review_verification(&pool, verification.verification_id, false, "admin", None).await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+
+        // An older, lower request approved later must not downgrade the user.
+        let mut requests = Vec::new();
+        for level in [KycLevel::Basic, KycLevel::Verified] {
+            let request = submit_verification(
+                &pool,
+                &bob.username,
+                level,
+                "passport",
+                "P7654321",
+                None,
+                "admin",
+            )
+            .await
+            .unwrap();
+            requests.push(request.verification_id);
+        }
+        for verification_id in [requests[1], requests[0]] {
+            review_verification(&pool, verification_id, true, "admin", None)
+                .await
+                .unwrap();
+        }
+        let limits = fetch_user_kyc_limits(&pool, &bob.username)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(limits.kyc_level, KycLevel::Verified);
+    }
+}
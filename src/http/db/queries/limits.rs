@@ -0,0 +1,322 @@
+use crate::http::db::model::{NewSpendingLimits, SpendingLimits};
+use crate::http::errors::{ApiError, Result};
+use chrono::{Duration, Utc};
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+/// How long a loosened limit waits before it takes effect, so a compromised
+/// session cannot raise the limits and drain the account straight away.
+pub const LIMIT_INCREASE_DELAY_HOURS: i64 = 24;
+
+const SELECT_LIMITS: &str = r#"
+    SELECT l.max_per_transaction, l.max_daily, l.max_monthly, l.pending_max_per_transaction,
+        l.pending_max_daily, l.pending_max_monthly, l.pending_effective_at
+    FROM users u
+    LEFT JOIN spending_limits l ON l.userid = u.userid
+"#;
+
+/// True when `new` is at least as strict as `current`. `None` is no limit.
+fn is_tighter(new: Option<f64>, current: Option<f64>) -> bool {
+    match (new, current) {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some(new), Some(current)) => new <= current,
+    }
+}
+
+fn validate(limits: &NewSpendingLimits) -> Result<()> {
+    for (field, value) in [
+        ("max_per_transaction", limits.max_per_transaction),
+        ("max_daily", limits.max_daily),
+        ("max_monthly", limits.max_monthly),
+    ] {
+        if let Some(value) = value
+            && (!value.is_finite() || value <= 0.0)
+        {
+            return Err(ApiError::Validation(format!(
+                "{} must be greater than zero",
+                field
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Moves a pending loosening into place once its cooling-off period is over.
+async fn apply_due_change(conn: &mut PgConnection, userid: Uuid) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE spending_limits SET
+            max_per_transaction = pending_max_per_transaction,
+            max_daily = pending_max_daily,
+            max_monthly = pending_max_monthly,
+            pending_max_per_transaction = NULL,
+            pending_max_daily = NULL,
+            pending_max_monthly = NULL,
+            pending_effective_at = NULL,
+            updated_at = NOW()
+        WHERE userid = $1 AND pending_effective_at <= NOW()
+        "#,
+    )
+    .bind(userid)
+    .execute(conn)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(())
+}
+
+async fn fetch_limits(conn: &mut PgConnection, userid: Uuid) -> Result<SpendingLimits> {
+    apply_due_change(&mut *conn, userid).await?;
+    sqlx::query_as::<_, SpendingLimits>(&format!("{} WHERE u.userid = $1", SELECT_LIMITS))
+        .bind(userid)
+        .fetch_one(conn)
+        .await
+        .map_err(ApiError::Database)
+}
+
+pub async fn fetch_spending_limits(pool: &PgPool, username: &str) -> Result<SpendingLimits> {
+    debug!("Fetching spending limits for {:?}", username);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let userid: Uuid = sqlx::query(r#"SELECT userid FROM users WHERE username = $1"#)
+        .bind(username)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?
+        .get("userid");
+    fetch_limits(&mut conn, userid).await
+}
+
+/// Sets all three limits at once. Limits that get stricter apply now; if any
+/// gets looser the full new set is staged and replaces the current limits
+/// after [`LIMIT_INCREASE_DELAY_HOURS`]. A new call replaces a staged change.
+pub async fn set_spending_limits(
+    pool: &PgPool,
+    username: &str,
+    limits: &NewSpendingLimits,
+) -> Result<SpendingLimits> {
+    debug!("Setting spending limits for {:?}: {:?}", username, limits);
+    validate(limits)?;
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let userid: Uuid = sqlx::query(r#"SELECT userid FROM users WHERE username = $1 FOR UPDATE"#)
+        .bind(username)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?
+        .get("userid");
+    let current = fetch_limits(&mut tx, userid).await?;
+
+    let pairs = [
+        (limits.max_per_transaction, current.max_per_transaction),
+        (limits.max_daily, current.max_daily),
+        (limits.max_monthly, current.max_monthly),
+    ];
+    let now: Vec<Option<f64>> = pairs
+        .iter()
+        .map(|&(new, current)| {
+            if is_tighter(new, current) {
+                new
+            } else {
+                current
+            }
+        })
+        .collect();
+    let loosened = pairs
+        .iter()
+        .any(|&(new, current)| !is_tighter(new, current));
+    let effective_at = loosened.then(|| Utc::now() + Duration::hours(LIMIT_INCREASE_DELAY_HOURS));
+    let pending = |value: Option<f64>| if loosened { value } else { None };
+
+    sqlx::query(
+        r#"
+        INSERT INTO spending_limits
+            (userid, max_per_transaction, max_daily, max_monthly, pending_max_per_transaction,
+            pending_max_daily, pending_max_monthly, pending_effective_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (userid) DO UPDATE SET
+            max_per_transaction = EXCLUDED.max_per_transaction,
+            max_daily = EXCLUDED.max_daily,
+            max_monthly = EXCLUDED.max_monthly,
+            pending_max_per_transaction = EXCLUDED.pending_max_per_transaction,
+            pending_max_daily = EXCLUDED.pending_max_daily,
+            pending_max_monthly = EXCLUDED.pending_max_monthly,
+            pending_effective_at = EXCLUDED.pending_effective_at,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(userid)
+    .bind(now[0])
+    .bind(now[1])
+    .bind(now[2])
+    .bind(pending(limits.max_per_transaction))
+    .bind(pending(limits.max_daily))
+    .bind(pending(limits.max_monthly))
+    .bind(effective_at)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let limits = fetch_limits(&mut tx, userid).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(limits)
+}
+
+/// Checks a transfer against the sender's own limits. Daily and monthly
+/// totals are rolling: the last 24 hours and the last 30 days, and include
+/// fees. Like the KYC check this runs after the sender row is locked.
+pub async fn enforce_spending_limits(
+    conn: &mut PgConnection,
+    sender_id: Uuid,
+    amount: f64,
+    fee: f64,
+) -> Result<()> {
+    let limits = fetch_limits(&mut *conn, sender_id).await?;
+    if let Some(max) = limits.max_per_transaction
+        && amount > max
+    {
+        debug!(
+            "Transfer of {} exceeds per-transaction limit {}",
+            amount, max
+        );
+        return Err(ApiError::SpendingLimitExceeded(format!(
+            "per-transaction limit of {}",
+            max
+        )));
+    }
+    if limits.max_daily.is_none() && limits.max_monthly.is_none() {
+        return Ok(());
+    }
+
+    let row = sqlx::query(
+        r#"
+        SELECT
+            COALESCE(SUM(amount + fee) FILTER (
+                WHERE created_at > NOW() - INTERVAL '24 hours'), 0) AS daily,
+            COALESCE(SUM(amount + fee), 0) AS monthly
+        FROM transactions
+        WHERE from_userid = $1 AND kind <> 'reversal'
+            AND created_at > NOW() - INTERVAL '30 days'
+        "#,
+    )
+    .bind(sender_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    for (period, max, spent) in [
+        ("daily", limits.max_daily, row.get::<f64, _>("daily")),
+        ("monthly", limits.max_monthly, row.get::<f64, _>("monthly")),
+    ] {
+        if let Some(max) = max
+            && spent + amount + fee > max
+        {
+            debug!(
+                "Transfer of {} exceeds {} limit {} (sent {})",
+                amount, period, max, spent
+            );
+            return Err(ApiError::SpendingLimitExceeded(format!(
+                "{} limit of {}",
+                period, max
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::insert_transaction;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+
+    fn limits(
+        per_transaction: Option<f64>,
+        daily: Option<f64>,
+        monthly: Option<f64>,
+    ) -> NewSpendingLimits {
+        NewSpendingLimits {
+            max_per_transaction: per_transaction,
+            max_daily: daily,
+            max_monthly: monthly,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spending_limits_are_enforced_on_rolling_totals() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 500.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        set_spending_limits(
+            &pool,
+            &alice.username,
+            &limits(Some(50.0), Some(80.0), None),
+        )
+        .await
+        .unwrap();
+
+        let res =
+            insert_transaction(&pool, &transaction(&alice.username, &bob.username, 60.0)).await;
+        assert!(
+            matches!(res, Err(ApiError::SpendingLimitExceeded(msg)) if msg.contains("per-transaction"))
+        );
+        insert_transaction(&pool, &transaction(&alice.username, &bob.username, 50.0))
+            .await
+            .unwrap();
+        let res =
+            insert_transaction(&pool, &transaction(&alice.username, &bob.username, 40.0)).await;
+        assert!(matches!(res, Err(ApiError::SpendingLimitExceeded(msg)) if msg.contains("daily")));
+        insert_transaction(&pool, &transaction(&alice.username, &bob.username, 30.0))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_limit_increases_wait_for_cooling_off() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 500.0).await;
+        set_spending_limits(
+            &pool,
+            &alice.username,
+            &limits(None, Some(100.0), Some(1000.0)),
+        )
+        .await
+        .unwrap();
+
+        // Loosening the daily limit is staged while tightening the monthly one applies now.
+        let current = set_spending_limits(
+            &pool,
+            &alice.username,
+            &limits(None, Some(200.0), Some(500.0)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(current.max_daily, Some(100.0));
+        assert_eq!(current.max_monthly, Some(500.0));
+        assert_eq!(current.pending_max_daily, Some(200.0));
+        assert!(current.pending_effective_at.is_some());
+
+        sqlx::query(
+            r#"UPDATE spending_limits SET pending_effective_at = NOW() - INTERVAL '1 second'
+            WHERE userid = $1"#,
+        )
+        .bind(alice.userid)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let current = fetch_spending_limits(&pool, &alice.username).await.unwrap();
+        assert_eq!(current.max_daily, Some(200.0));
+        assert_eq!(current.max_monthly, Some(500.0));
+        assert!(current.pending_effective_at.is_none());
+
+        let current = set_spending_limits(
+            &pool,
+            &alice.username,
+            &limits(Some(10.0), Some(50.0), Some(500.0)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(current.max_daily, Some(50.0));
+        assert!(current.pending_effective_at.is_none());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::http::db::model::{PaymentLink, PaymentLinkStatus, Transaction, TransferKind};
+use crate::http::db::queries::transfer;
+use crate::http::errors::{ApiError, Result};
+use chrono::{Duration, Utc};
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+pub const DEFAULT_LINK_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+pub const MAX_LINK_TTL_SECS: i64 = 365 * 24 * 60 * 60;
+
+const SELECT_LINKS: &str = r#"
+    SELECT l.link_id, u.username AS recipient_username, u.name AS recipient_name, l.amount,
+        l.memo, l.multi_use, l.status, l.use_count, l.expires_at, l.created_at
+    FROM payment_links l
+    JOIN users u ON u.userid = l.recipient_userid
+"#;
+
+async fn fetch_link_with(conn: &mut PgConnection, link_id: Uuid) -> Result<PaymentLink> {
+    sqlx::query_as::<_, PaymentLink>(&format!("{} WHERE l.link_id = $1", SELECT_LINKS))
+        .bind(link_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)
+}
+
+pub async fn fetch_link(pool: &PgPool, link_id: Uuid) -> Result<PaymentLink> {
+    debug!("Fetching payment link {:?}", link_id);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    fetch_link_with(&mut conn, link_id).await
+}
+
+/// Creates a link that pays `recipient`. The memo must already be sanitized.
+pub async fn create_link(
+    pool: &PgPool,
+    recipient: &str,
+    amount: Option<f64>,
+    memo: Option<&str>,
+    ttl_secs: Option<i64>,
+    multi_use: bool,
+) -> Result<PaymentLink> {
+    debug!(
+        "Creating payment link for {:?}: {:?}, multi_use={}",
+        recipient, amount, multi_use
+    );
+    if let Some(amount) = amount
+        && (!amount.is_finite() || amount <= 0.0)
+    {
+        return Err(ApiError::Validation(
+            "amount must be greater than zero".to_string(),
+        ));
+    }
+    let ttl_secs = ttl_secs.unwrap_or(DEFAULT_LINK_TTL_SECS);
+    if !(1..=MAX_LINK_TTL_SECS).contains(&ttl_secs) {
+        return Err(ApiError::Validation(format!(
+            "ttl_secs must be between 1 and {}",
+            MAX_LINK_TTL_SECS
+        )));
+    }
+
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let link_id = Uuid::new_v4();
+    let result = sqlx::query(
+        r#"
+        INSERT INTO payment_links (link_id, recipient_userid, amount, memo, multi_use, expires_at)
+        SELECT $1, userid, $3, $4, $5, $6 FROM users WHERE username = $2 AND status = 'active'
+        "#,
+    )
+    .bind(link_id)
+    .bind(recipient)
+    .bind(amount)
+    .bind(memo)
+    .bind(multi_use)
+    .bind(Utc::now() + Duration::seconds(ttl_secs))
+    .execute(&mut *conn)
+    .await
+    .map_err(ApiError::Database)?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::UserNotFound);
+    }
+    fetch_link_with(&mut conn, link_id).await
+}
+
+pub async fn list_links(pool: &PgPool, recipient: &str) -> Result<Vec<PaymentLink>> {
+    debug!("Listing payment links of {:?}", recipient);
+    sqlx::query_as::<_, PaymentLink>(&format!(
+        "{} WHERE u.username = $1 ORDER BY l.created_at DESC",
+        SELECT_LINKS
+    ))
+    .bind(recipient)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Locks a link that can still be paid.
+async fn lock_usable(conn: &mut PgConnection, link_id: Uuid) -> Result<PaymentLink> {
+    sqlx::query(r#"SELECT 1 FROM payment_links WHERE link_id = $1 FOR UPDATE"#)
+        .bind(link_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)?;
+    let link = fetch_link_with(conn, link_id).await?;
+    if link.status != PaymentLinkStatus::Active {
+        return Err(ApiError::Conflict(
+            format!("payment link is already {:?}", link.status).to_lowercase(),
+        ));
+    }
+    if link.expires_at <= Utc::now() {
+        return Err(ApiError::Conflict("payment link has expired".to_string()));
+    }
+    Ok(link)
+}
+
+/// Stops a link from being paid. Only its recipient can revoke it.
+pub async fn revoke_link(pool: &PgPool, link_id: Uuid, recipient: &str) -> Result<PaymentLink> {
+    debug!("Revoking payment link {:?} by {:?}", link_id, recipient);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let link = fetch_link_with(&mut tx, link_id).await?;
+    if link.recipient_username != recipient {
+        return Err(ApiError::NotFound);
+    }
+    lock_usable(&mut tx, link_id).await?;
+    sqlx::query(r#"UPDATE payment_links SET status = 'revoked' WHERE link_id = $1"#)
+        .bind(link_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    let link = fetch_link_with(&mut tx, link_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(link)
+}
+
+/// Pays a link from `payer`. Links with a fixed amount take exactly that
+/// amount; open links need the payer to name one. A single-use link is
+/// marked used in the same transaction as the transfer, so it can never be
+/// paid twice.
+pub async fn redeem_link(
+    pool: &PgPool,
+    link_id: Uuid,
+    payer: &str,
+    amount: Option<f64>,
+) -> Result<Transaction> {
+    debug!(
+        "Redeeming payment link {:?} by {:?}: {:?}",
+        link_id, payer, amount
+    );
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let link = lock_usable(&mut tx, link_id).await?;
+    let amount = match (link.amount, amount) {
+        (Some(fixed), Some(given)) if given != fixed => {
+            return Err(ApiError::Validation(format!(
+                "this link is for exactly {}",
+                fixed
+            )));
+        }
+        (Some(fixed), _) => fixed,
+        (None, Some(given)) => given,
+        (None, None) => {
+            return Err(ApiError::Validation(
+                "amount is required for this link".to_string(),
+            ));
+        }
+    };
+
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount,
+        from_username: payer.to_string(),
+        to_username: link.recipient_username.clone(),
+        time: Utc::now(),
+        memo: link.memo.clone(),
+        kind: TransferKind::Link,
+        fee: 0.0,
+    };
+    transfer(&mut tx, &txn).await?;
+    sqlx::query(
+        r#"
+        INSERT INTO payment_link_redemptions (link_id, txn_id, payer_userid)
+        SELECT $1, $2, from_userid FROM transactions WHERE txn_id = $2
+        "#,
+    )
+    .bind(link_id)
+    .bind(txn.txn_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    sqlx::query(
+        r#"
+        UPDATE payment_links
+        SET use_count = use_count + 1,
+            status = CASE WHEN multi_use THEN status ELSE 'used' END
+        WHERE link_id = $1
+        "#,
+    )
+    .bind(link_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let fee: f64 = sqlx::query(r#"SELECT fee FROM transactions WHERE txn_id = $1"#)
+        .bind(txn.txn_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .get("fee");
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(Transaction { fee, ..txn })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+
+    #[tokio::test]
+    async fn test_single_use_links_pay_once_and_multi_use_links_repeat() {
+        let pool = setup_test_db().await;
+        let stall = create_test_user(&pool, "stall", 0.0).await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+
+        let once = create_link(
+            &pool,
+            &stall.username,
+            Some(4.5),
+            Some("Coffee"),
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        let res = redeem_link(&pool, once.link_id, &alice.username, Some(5.0)).await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+        let txn = redeem_link(&pool, once.link_id, &alice.username, None)
+            .await
+            .unwrap();
+        assert_eq!(txn.amount, 4.5);
+        assert_eq!(txn.memo.as_deref(), Some("Coffee"));
+        let res = redeem_link(&pool, once.link_id, &bob.username, None).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+
+        let tips = create_link(&pool, &stall.username, None, None, None, true)
+            .await
+            .unwrap();
+        let res = redeem_link(&pool, tips.link_id, &alice.username, None).await;
+        assert!(matches!(res, Err(ApiError::Validation(_))));
+        redeem_link(&pool, tips.link_id, &alice.username, Some(2.0))
+            .await
+            .unwrap();
+        redeem_link(&pool, tips.link_id, &bob.username, Some(3.0))
+            .await
+            .unwrap();
+        let tips = fetch_link(&pool, tips.link_id).await.unwrap();
+        assert_eq!(tips.use_count, 2);
+        assert_eq!(tips.status, PaymentLinkStatus::Active);
+
+        let res = revoke_link(&pool, tips.link_id, &alice.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+        revoke_link(&pool, tips.link_id, &stall.username)
+            .await
+            .unwrap();
+        let res = redeem_link(&pool, tips.link_id, &bob.username, Some(1.0)).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+    }
+}
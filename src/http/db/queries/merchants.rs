@@ -0,0 +1,176 @@
+use crate::http::db::model::{Merchant, SettlementSchedule};
+use crate::http::errors::{ApiError, Result};
+use crate::http::funding::BankAccount;
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+pub const MAX_DISPLAY_NAME_LEN: usize = 80;
+
+const SELECT_MERCHANTS: &str = r#"
+    SELECT u.username, m.display_name, m.settlement_schedule, m.settlement_reserve,
+        '****' || RIGHT(m.account_number, 4) AS settlement_destination, m.last_settled_at,
+        m.created_at, m.updated_at
+    FROM merchants m
+    JOIN users u ON u.userid = m.userid
+"#;
+
+/// A merchant whose settlement period has passed.
+#[derive(Debug, Clone)]
+pub struct SettlementDue {
+    pub username: String,
+    pub reserve: f64,
+    pub account: BankAccount,
+}
+
+async fn fetch_merchant_with(conn: &mut PgConnection, username: &str) -> Result<Merchant> {
+    sqlx::query_as::<_, Merchant>(&format!("{} WHERE u.username = $1", SELECT_MERCHANTS))
+        .bind(username)
+        .fetch_optional(conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)
+}
+
+/// Fetches a merchant profile; users who are not merchants get `NotFound`.
+pub async fn fetch_merchant(pool: &PgPool, username: &str) -> Result<Merchant> {
+    debug!("Fetching merchant profile of {:?}", username);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    fetch_merchant_with(&mut conn, username).await
+}
+
+/// Turns the user into a merchant, or updates their profile if they already
+/// are one. Leaving out `account` keeps the bank account already on file;
+/// scheduled settlement needs one.
+pub async fn save_merchant(
+    pool: &PgPool,
+    username: &str,
+    display_name: &str,
+    schedule: SettlementSchedule,
+    reserve: f64,
+    account: Option<&BankAccount>,
+) -> Result<Merchant> {
+    debug!(
+        "Saving merchant profile of {:?}: {:?}, {:?}",
+        username, display_name, schedule
+    );
+    let display_name = display_name.trim();
+    if display_name.is_empty() || display_name.chars().count() > MAX_DISPLAY_NAME_LEN {
+        return Err(ApiError::Validation(format!(
+            "display_name must be between 1 and {} characters",
+            MAX_DISPLAY_NAME_LEN
+        )));
+    }
+    if !reserve.is_finite() || reserve < 0.0 {
+        return Err(ApiError::Validation(
+            "settlement_reserve must not be negative".to_string(),
+        ));
+    }
+    if let Some(account) = account {
+        account.validate()?;
+    }
+
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let row = sqlx::query(r#"SELECT userid, status FROM users WHERE username = $1 FOR UPDATE"#)
+        .bind(username)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?;
+    super::ensure_active(row.get("status"))?;
+    let userid: Uuid = row.get("userid");
+    let has_account = account.is_some()
+        || sqlx::query(
+            r#"SELECT 1 FROM merchants WHERE userid = $1 AND account_number IS NOT NULL"#,
+        )
+        .bind(userid)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .is_some();
+    if schedule != SettlementSchedule::Manual && !has_account {
+        return Err(ApiError::Validation(
+            "a settlement account is required unless settlement is manual".to_string(),
+        ));
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO merchants (userid, display_name, settlement_schedule, settlement_reserve,
+            account_number, bank_code, holder_name)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (userid) DO UPDATE
+        SET display_name = EXCLUDED.display_name,
+            settlement_schedule = EXCLUDED.settlement_schedule,
+            settlement_reserve = EXCLUDED.settlement_reserve,
+            account_number = COALESCE(EXCLUDED.account_number, merchants.account_number),
+            bank_code = COALESCE(EXCLUDED.bank_code, merchants.bank_code),
+            holder_name = COALESCE(EXCLUDED.holder_name, merchants.holder_name),
+            updated_at = NOW()
+        "#,
+    )
+    .bind(userid)
+    .bind(display_name)
+    .bind(schedule)
+    .bind(reserve)
+    .bind(account.map(|account| account.account_number.trim()))
+    .bind(account.map(|account| account.bank_code.trim()))
+    .bind(account.map(|account| account.holder_name.trim()))
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    let merchant = fetch_merchant_with(&mut tx, username).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(merchant)
+}
+
+/// Active merchants on a daily or weekly schedule whose last settlement is
+/// at least a period ago, longest waiting first.
+pub async fn due_for_settlement(pool: &PgPool, limit: i64) -> Result<Vec<SettlementDue>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT u.username, m.settlement_reserve, m.account_number, m.bank_code, m.holder_name
+        FROM merchants m
+        JOIN users u ON u.userid = m.userid
+        WHERE u.status = 'active' AND m.account_number IS NOT NULL
+            AND m.settlement_schedule <> 'manual'
+            AND (m.last_settled_at IS NULL OR m.last_settled_at <= NOW() - CASE
+                WHEN m.settlement_schedule = 'daily' THEN INTERVAL '1 day'
+                ELSE INTERVAL '7 days' END)
+        ORDER BY m.last_settled_at NULLS FIRST
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(rows
+        .into_iter()
+        .map(|row| SettlementDue {
+            username: row.get("username"),
+            reserve: row.get("settlement_reserve"),
+            account: BankAccount {
+                account_number: row.get("account_number"),
+                bank_code: row.get("bank_code"),
+                holder_name: row.get("holder_name"),
+            },
+        })
+        .collect())
+}
+
+/// Starts the merchant's next settlement period.
+pub async fn mark_settled(pool: &PgPool, username: &str) -> Result<()> {
+    debug!("Marking merchant {:?} settled", username);
+    sqlx::query(
+        r#"
+        UPDATE merchants SET last_settled_at = NOW()
+        WHERE userid = (SELECT userid FROM users WHERE username = $1)
+        "#,
+    )
+    .bind(username)
+    .execute(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use crate::http::db::model::{Payee, TransactionFilter};
+use crate::http::db::queries::{MAX_PAGE_SIZE, fetch_transactions, usernames};
+use crate::http::errors::{ApiError, Result};
+use chrono::{DateTime, Utc};
+use log::debug;
+use sqlx::{PgPool, Row};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// How many unsaved recent counterparties are suggested alongside saved payees.
+pub const RECENT_COUNTERPARTIES_LIMIT: usize = 10;
+
+/// Lists saved payees followed by recent counterparties that have not been
+/// saved, derived from the most recent page of the user's history.
+pub async fn list_payees(pool: &PgPool, username: &str) -> Result<Vec<Payee>> {
+    debug!("Listing payees for user: {:?}", username);
+    let mut payees = sqlx::query_as::<_, Payee>(
+        r#"
+        SELECT p.payee_id, u.username, u.name, p.nickname, p.favorite,
+            NULL::TIMESTAMPTZ AS last_transacted_at
+        FROM payees p
+        JOIN users o ON o.userid = p.owner_userid
+        JOIN users u ON u.userid = p.payee_userid
+        WHERE o.username = $1
+        "#,
+    )
+    .bind(username)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let mut last_seen: HashMap<String, DateTime<Utc>> = HashMap::new();
+    let recent_history = TransactionFilter {
+        limit: Some(MAX_PAGE_SIZE),
+        ..Default::default()
+    };
+    for txn in fetch_transactions(pool, username, &recent_history)
+        .await?
+        .transactions
+    {
+        let seen = last_seen
+            .entry(txn.counterparty_username)
+            .or_insert(txn.time);
+        *seen = (*seen).max(txn.time);
+    }
+
+    for payee in payees.iter_mut() {
+        payee.last_transacted_at = last_seen.remove(&payee.username);
+    }
+
+    let mut recent: Vec<(String, DateTime<Utc>)> = last_seen.into_iter().collect();
+    recent.sort_by_key(|(_, time)| Reverse(*time));
+    recent.truncate(RECENT_COUNTERPARTIES_LIMIT);
+    let recent_usernames: Vec<String> = recent.iter().map(|(name, _)| name.clone()).collect();
+    let names: HashMap<String, String> =
+        sqlx::query(r#"SELECT username, name FROM users WHERE username = ANY($1)"#)
+            .bind(&recent_usernames)
+            .fetch_all(pool)
+            .await
+            .map_err(ApiError::Database)?
+            .into_iter()
+            .map(|row| (row.get("username"), row.get("name")))
+            .collect();
+    payees.extend(recent.into_iter().map(|(username, time)| Payee {
+        payee_id: None,
+        name: names.get(&username).cloned().unwrap_or_default(),
+        username,
+        nickname: None,
+        favorite: false,
+        last_transacted_at: Some(time),
+    }));
+
+    sort_payees(&mut payees);
+    Ok(payees)
+}
+
+/// Favorites first, then saved payees, then most recently paid, then by name.
+fn sort_payees(payees: &mut [Payee]) {
+    payees.sort_by(|a, b| {
+        b.favorite
+            .cmp(&a.favorite)
+            .then(b.payee_id.is_some().cmp(&a.payee_id.is_some()))
+            .then(b.last_transacted_at.cmp(&a.last_transacted_at))
+            .then_with(|| {
+                let a_label = a.nickname.as_deref().unwrap_or(&a.username);
+                let b_label = b.nickname.as_deref().unwrap_or(&b.username);
+                a_label.cmp(b_label)
+            })
+    });
+}
+
+/// Saves a payee, or updates the nickname and favorite flag if the
+/// recipient is already saved.
+pub async fn save_payee(
+    pool: &PgPool,
+    owner: &str,
+    payee_username: &str,
+    nickname: Option<&str>,
+    favorite: bool,
+) -> Result<Payee> {
+    debug!("Saving payee {:?} for {:?}", payee_username, owner);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let owner_id = usernames::resolve_username(&mut tx, owner)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    let payee_userid = usernames::resolve_username(&mut tx, payee_username)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    if owner_id == payee_userid {
+        return Err(ApiError::Validation(
+            "cannot save yourself as a payee".to_string(),
+        ));
+    }
+
+    let payee_id: Uuid = sqlx::query(
+        r#"
+        INSERT INTO payees (payee_id, owner_userid, payee_userid, nickname, favorite)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (owner_userid, payee_userid)
+        DO UPDATE SET nickname = EXCLUDED.nickname, favorite = EXCLUDED.favorite
+        RETURNING payee_id
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(owner_id)
+    .bind(payee_userid)
+    .bind(nickname)
+    .bind(favorite)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    .get("payee_id");
+
+    let payee = sqlx::query_as::<_, Payee>(
+        r#"
+        SELECT p.payee_id, u.username, u.name, p.nickname, p.favorite,
+            NULL::TIMESTAMPTZ AS last_transacted_at
+        FROM payees p
+        JOIN users u ON u.userid = p.payee_userid
+        WHERE p.payee_id = $1
+        "#,
+    )
+    .bind(payee_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(payee)
+}
+
+pub async fn delete_payee(pool: &PgPool, owner: &str, payee_id: Uuid) -> Result<()> {
+    debug!("Deleting payee {:?} for {:?}", payee_id, owner);
+    let result = sqlx::query(
+        r#"
+        DELETE FROM payees p USING users o
+        WHERE p.payee_id = $1 AND o.userid = p.owner_userid AND o.username = $2
+        "#,
+    )
+    .bind(payee_id)
+    .bind(owner)
+    .execute(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    match result.rows_affected() {
+        0 => Err(ApiError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the current username of one of `owner`'s saved payees.
+pub async fn fetch_payee_username(
+    pool: &PgPool,
+    owner: &str,
+    payee_id: Uuid,
+) -> Result<Option<String>> {
+    debug!("Fetching payee {:?} for {:?}", payee_id, owner);
+    let rec = sqlx::query(
+        r#"
+        SELECT u.username FROM payees p
+        JOIN users o ON o.userid = p.owner_userid
+        JOIN users u ON u.userid = p.payee_userid
+        WHERE p.payee_id = $1 AND o.username = $2
+        "#,
+    )
+    .bind(payee_id)
+    .bind(owner)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(rec.map(|row| row.get("username")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::insert_transaction;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+
+    #[tokio::test]
+    async fn test_payees_merge_saved_and_recent() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let carol = create_test_user(&pool, "carol", 100.0).await;
+        let dave = create_test_user(&pool, "dave", 100.0).await;
+
+        insert_transaction(&pool, &transaction(&alice.username, &carol.username, 5.0))
+            .await
+            .unwrap();
+        save_payee(&pool, &alice.username, &bob.username, Some("Bobby"), false)
+            .await
+            .unwrap();
+        let favorite = save_payee(&pool, &alice.username, &dave.username, None, true)
+            .await
+            .unwrap();
+
+        let payees = list_payees(&pool, &alice.username).await.unwrap();
+        let order: Vec<&str> = payees.iter().map(|p| p.username.as_str()).collect();
+        assert_eq!(order, vec![&dave.username, &bob.username, &carol.username]);
+        assert!(payees[2].payee_id.is_none());
+        assert!(payees[2].last_transacted_at.is_some());
+
+        let username = fetch_payee_username(&pool, &alice.username, favorite.payee_id.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(username, Some(dave.username.clone()));
+        // Payee ids are private to their owner.
+        let username = fetch_payee_username(&pool, &bob.username, favorite.payee_id.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(username, None);
+
+        delete_payee(&pool, &alice.username, favorite.payee_id.unwrap())
+            .await
+            .unwrap();
+        let res = delete_payee(&pool, &alice.username, favorite.payee_id.unwrap()).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+    }
+}
@@ -0,0 +1,302 @@
+use crate::http::db::model::{
+    AccountStatus, PaymentRequest, RequestStatus, Transaction, TransferKind,
+};
+use crate::http::db::queries::{ensure_active, transfer, usernames};
+use crate::http::errors::{ApiError, Result};
+use chrono::Utc;
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+const SELECT_REQUESTS: &str = r#"
+    SELECT r.request_id, q.username AS requester_username, p.username AS payer_username,
+        r.amount, r.memo, r.status, r.txn_id, r.created_at, r.resolved_at
+    FROM payment_requests r
+    JOIN users q ON q.userid = r.requester_userid
+    JOIN users p ON p.userid = r.payer_userid
+"#;
+
+async fn fetch_request(conn: &mut PgConnection, request_id: Uuid) -> Result<PaymentRequest> {
+    sqlx::query_as::<_, PaymentRequest>(&format!("{} WHERE r.request_id = $1", SELECT_REQUESTS))
+        .bind(request_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)
+}
+
+/// Asks `payer` to pay `requester`. Nothing moves until the payer approves.
+pub async fn create_request(
+    pool: &PgPool,
+    requester: &str,
+    payer: &str,
+    amount: f64,
+    memo: Option<&str>,
+) -> Result<PaymentRequest> {
+    debug!(
+        "Creating payment request from {:?} to {:?} for {}",
+        requester, payer, amount
+    );
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(ApiError::Validation(
+            "amount must be greater than zero".to_string(),
+        ));
+    }
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let row = sqlx::query(r#"SELECT userid, status FROM users WHERE username = $1"#)
+        .bind(requester)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?;
+    let requester_id: Uuid = row.get("userid");
+    ensure_active(row.get("status"))?;
+
+    let payer_id = usernames::resolve_username(&mut tx, payer)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    if payer_id == requester_id {
+        return Err(ApiError::Validation(
+            "cannot request money from yourself".to_string(),
+        ));
+    }
+    let payer_status: AccountStatus = sqlx::query(r#"SELECT status FROM users WHERE userid = $1"#)
+        .bind(payer_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .get("status");
+    if payer_status == AccountStatus::Closed {
+        return Err(ApiError::AccountClosed);
+    }
+
+    let request_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO payment_requests (request_id, requester_userid, payer_userid, amount, memo)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(request_id)
+    .bind(requester_id)
+    .bind(payer_id)
+    .bind(amount)
+    .bind(memo)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let request = fetch_request(&mut tx, request_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(request)
+}
+
+/// Lists requests addressed to `username` (`incoming`) or sent by them,
+/// newest first.
+pub async fn list_requests(
+    pool: &PgPool,
+    username: &str,
+    incoming: bool,
+    status: Option<RequestStatus>,
+) -> Result<Vec<PaymentRequest>> {
+    debug!(
+        "Listing payment requests for {:?}: incoming={} status={:?}",
+        username, incoming, status
+    );
+    let side = if incoming { "p" } else { "q" };
+    sqlx::query_as::<_, PaymentRequest>(&format!(
+        "{} WHERE {}.username = $1 AND ($2::TEXT IS NULL OR r.status = $2) ORDER BY r.created_at DESC",
+        SELECT_REQUESTS, side
+    ))
+    .bind(username)
+    .bind(status)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Locks a pending request that `actor` is allowed to act on. Requests the
+/// actor is not party to in the given role look the same as missing ones.
+async fn lock_pending(
+    conn: &mut PgConnection,
+    request_id: Uuid,
+    actor: &str,
+    as_payer: bool,
+) -> Result<PaymentRequest> {
+    sqlx::query(r#"SELECT 1 FROM payment_requests WHERE request_id = $1 FOR UPDATE"#)
+        .bind(request_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)?;
+    let request = fetch_request(conn, request_id).await?;
+    let party = if as_payer {
+        &request.payer_username
+    } else {
+        &request.requester_username
+    };
+    if party != actor {
+        return Err(ApiError::NotFound);
+    }
+    if request.status != RequestStatus::Pending {
+        return Err(ApiError::Conflict(
+            format!("request is already {:?}", request.status).to_lowercase(),
+        ));
+    }
+    Ok(request)
+}
+
+/// Pays a request. The transfer and the status change commit together, so a
+/// failed transfer leaves the request pending.
+pub async fn approve_request(
+    pool: &PgPool,
+    request_id: Uuid,
+    payer: &str,
+) -> Result<PaymentRequest> {
+    debug!("Approving payment request {:?} by {:?}", request_id, payer);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let request = lock_pending(&mut tx, request_id, payer, true).await?;
+
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount: request.amount,
+        from_username: request.payer_username.clone(),
+        to_username: request.requester_username.clone(),
+        time: Utc::now(),
+        memo: request.memo.clone(),
+        kind: TransferKind::Request,
+        fee: 0.0,
+    };
+    transfer(&mut tx, &txn).await?;
+
+    sqlx::query(
+        r#"
+        UPDATE payment_requests SET status = 'approved', txn_id = $1, resolved_at = NOW()
+        WHERE request_id = $2
+        "#,
+    )
+    .bind(txn.txn_id)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let request = fetch_request(&mut tx, request_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(request)
+}
+
+/// Declines (as the payer) or cancels (as the requester) a pending request.
+pub async fn reject_request(
+    pool: &PgPool,
+    request_id: Uuid,
+    actor: &str,
+    outcome: RequestStatus,
+) -> Result<PaymentRequest> {
+    debug!(
+        "Resolving payment request {:?} as {:?} by {:?}",
+        request_id, outcome, actor
+    );
+    let as_payer = match outcome {
+        RequestStatus::Declined => true,
+        RequestStatus::Cancelled => false,
+        _ => {
+            return Err(ApiError::Validation(
+                "outcome must be declined or cancelled".to_string(),
+            ));
+        }
+    };
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    lock_pending(&mut tx, request_id, actor, as_payer).await?;
+    sqlx::query(
+        r#"UPDATE payment_requests SET status = $1, resolved_at = NOW() WHERE request_id = $2"#,
+    )
+    .bind(outcome)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let request = fetch_request(&mut tx, request_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::{fetch_balance, fetch_transaction};
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+
+    #[tokio::test]
+    async fn test_approve_request_pays_and_links_transaction() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 0.0).await;
+        let bob = create_test_user(&pool, "bob", 50.0).await;
+
+        let request = create_request(&pool, &alice.username, &bob.username, 30.0, Some("Dinner"))
+            .await
+            .unwrap();
+        assert_eq!(request.status, RequestStatus::Pending);
+        let inbox = list_requests(&pool, &bob.username, true, Some(RequestStatus::Pending))
+            .await
+            .unwrap();
+        assert_eq!(inbox.len(), 1);
+
+        // Only the payer can approve.
+        let res = approve_request(&pool, request.request_id, &alice.username).await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+
+        let approved = approve_request(&pool, request.request_id, &bob.username)
+            .await
+            .unwrap();
+        assert_eq!(approved.status, RequestStatus::Approved);
+        let txn = fetch_transaction(&pool, approved.txn_id.unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(txn.to_username, alice.username);
+        assert_eq!(
+            fetch_balance(&pool, &bob.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(20.0)
+        );
+
+        let res = approve_request(&pool, request.request_id, &bob.username).await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_failed_approval_leaves_request_pending() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 0.0).await;
+        let bob = create_test_user(&pool, "bob", 10.0).await;
+
+        let request = create_request(&pool, &alice.username, &bob.username, 30.0, None)
+            .await
+            .unwrap();
+        let res = approve_request(&pool, request.request_id, &bob.username).await;
+        assert!(matches!(res, Err(ApiError::BalanceLow)));
+
+        let res = reject_request(
+            &pool,
+            request.request_id,
+            &bob.username,
+            RequestStatus::Cancelled,
+        )
+        .await;
+        assert!(matches!(res, Err(ApiError::NotFound)));
+        let cancelled = reject_request(
+            &pool,
+            request.request_id,
+            &alice.username,
+            RequestStatus::Cancelled,
+        )
+        .await
+        .unwrap();
+        assert_eq!(cancelled.status, RequestStatus::Cancelled);
+        assert!(cancelled.txn_id.is_none());
+    }
+}
@@ -0,0 +1,649 @@
+use crate::http::db::model::{
+    NewSchedule, ScheduleFrequency, ScheduleStatus, ScheduledRun, ScheduledTransfer, Transaction,
+    TransferKind,
+};
+use crate::http::db::queries::{transfer, usernames};
+use crate::http::errors::{ApiError, Result};
+use chrono::{DateTime, Datelike, Duration, Months, Utc};
+use log::{debug, warn};
+use sqlx::{Connection, PgConnection, PgPool, Row};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Retries are spaced RETRY_BASE_DELAY_SECS * 2^(failures - 1) apart.
+pub const RETRY_BASE_DELAY_SECS: i64 = 300;
+pub const DEFAULT_MAX_RETRIES: i32 = 3;
+pub const MAX_RETRIES_LIMIT: i32 = 10;
+
+const SELECT_SCHEDULES: &str = r#"
+    SELECT s.schedule_id, f.username AS from_username, t.username AS to_username, s.amount,
+        s.frequency, s.cron_expr, s.start_at, s.end_at, s.next_run_at, s.status,
+        s.failure_count, s.max_retries, s.last_error, s.last_run_at, s.created_at
+    FROM scheduled_transfers s
+    JOIN users f ON f.userid = s.from_userid
+    JOIN users t ON t.userid = s.to_userid
+"#;
+
+/// Parses a cron expression with a leading seconds field, e.g.
+/// `0 0 9 1 * *` for 09:00 on the first of every month.
+pub fn parse_cron(expr: &str) -> Result<cron::Schedule> {
+    cron::Schedule::from_str(expr)
+        .map_err(|e| ApiError::Validation(format!("invalid cron expression: {}", e)))
+}
+
+/// The first run of a schedule strictly after `after`, or `None` when there
+/// are no more. Daily, weekly and monthly runs are counted from `start_at`,
+/// so a schedule starting on the 31st runs on the last day of shorter months
+/// and returns to the 31st afterwards.
+pub fn occurrence_after(
+    frequency: ScheduleFrequency,
+    cron: Option<&cron::Schedule>,
+    start_at: DateTime<Utc>,
+    end_at: Option<DateTime<Utc>>,
+    after: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let next = if after < start_at && frequency != ScheduleFrequency::Cron {
+        Some(start_at)
+    } else {
+        match frequency {
+            ScheduleFrequency::Once => None,
+            ScheduleFrequency::Daily | ScheduleFrequency::Weekly => {
+                let period = if frequency == ScheduleFrequency::Daily {
+                    Duration::days(1)
+                } else {
+                    Duration::weeks(1)
+                };
+                let elapsed = (after - start_at).num_seconds() / period.num_seconds();
+                Some(start_at + period * (elapsed as i32 + 1))
+            }
+            ScheduleFrequency::Monthly => {
+                let months = (after.year() - start_at.year()) * 12 + after.month0() as i32
+                    - start_at.month0() as i32;
+                let mut k = months.max(0) as u32;
+                loop {
+                    let candidate = start_at.checked_add_months(Months::new(k))?;
+                    if candidate > after {
+                        break Some(candidate);
+                    }
+                    k += 1;
+                }
+            }
+            ScheduleFrequency::Cron => {
+                let from = after.max(start_at - Duration::seconds(1));
+                cron.and_then(|c| c.after(&from).next())
+            }
+        }
+    };
+    next.filter(|t| end_at.is_none_or(|end| *t <= end))
+}
+
+pub async fn create_schedule(
+    pool: &PgPool,
+    owner: &str,
+    req: &NewSchedule,
+) -> Result<ScheduledTransfer> {
+    debug!("Creating scheduled transfer for {:?}: {:?}", owner, req);
+    if !req.amount.is_finite() || req.amount <= 0.0 {
+        return Err(ApiError::Validation(
+            "amount must be greater than zero".to_string(),
+        ));
+    }
+    let cron = match (req.frequency, req.cron_expr.as_deref()) {
+        (ScheduleFrequency::Cron, Some(expr)) => Some(parse_cron(expr)?),
+        (ScheduleFrequency::Cron, None) => {
+            return Err(ApiError::Validation(
+                "cron_expr is required for cron schedules".to_string(),
+            ));
+        }
+        (_, Some(_)) => {
+            return Err(ApiError::Validation(
+                "cron_expr is only allowed for cron schedules".to_string(),
+            ));
+        }
+        (_, None) => None,
+    };
+    let max_retries = req.max_retries.unwrap_or(DEFAULT_MAX_RETRIES);
+    if !(0..=MAX_RETRIES_LIMIT).contains(&max_retries) {
+        return Err(ApiError::Validation(format!(
+            "max_retries must be between 0 and {}",
+            MAX_RETRIES_LIMIT
+        )));
+    }
+    let start_at = req.start_at.unwrap_or_else(Utc::now);
+    let first_run = occurrence_after(
+        req.frequency,
+        cron.as_ref(),
+        start_at,
+        req.end_at,
+        start_at - Duration::microseconds(1),
+    )
+    .ok_or_else(|| ApiError::Validation("schedule has no runs before end_at".to_string()))?;
+
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let from_userid: Uuid = sqlx::query(r#"SELECT userid FROM users WHERE username = $1"#)
+        .bind(owner)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::UserNotFound)?
+        .get("userid");
+    let to_userid = usernames::resolve_username(&mut tx, &req.to_username)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    if from_userid == to_userid {
+        return Err(ApiError::Validation(
+            "cannot schedule a transfer to the same account".to_string(),
+        ));
+    }
+
+    let schedule_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO scheduled_transfers
+            (schedule_id, from_userid, to_userid, amount, frequency, cron_expr,
+             start_at, end_at, next_run_at, max_retries)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#,
+    )
+    .bind(schedule_id)
+    .bind(from_userid)
+    .bind(to_userid)
+    .bind(req.amount)
+    .bind(req.frequency)
+    .bind(req.cron_expr.as_deref())
+    .bind(start_at)
+    .bind(req.end_at)
+    .bind(first_run)
+    .bind(max_retries)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let schedule = fetch_schedule(&mut tx, schedule_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(schedule)
+}
+
+async fn fetch_schedule(conn: &mut PgConnection, schedule_id: Uuid) -> Result<ScheduledTransfer> {
+    sqlx::query_as::<_, ScheduledTransfer>(&format!(
+        "{} WHERE s.schedule_id = $1",
+        SELECT_SCHEDULES
+    ))
+    .bind(schedule_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::NotFound)
+}
+
+pub async fn list_schedules(pool: &PgPool, owner: &str) -> Result<Vec<ScheduledTransfer>> {
+    debug!("Listing scheduled transfers for {:?}", owner);
+    sqlx::query_as::<_, ScheduledTransfer>(&format!(
+        "{} WHERE f.username = $1 ORDER BY s.created_at DESC",
+        SELECT_SCHEDULES
+    ))
+    .bind(owner)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+pub async fn list_runs(pool: &PgPool, owner: &str, schedule_id: Uuid) -> Result<Vec<ScheduledRun>> {
+    debug!("Listing runs of schedule {:?} for {:?}", schedule_id, owner);
+    let mut conn = pool.acquire().await.map_err(ApiError::Database)?;
+    let schedule = fetch_schedule(&mut conn, schedule_id).await?;
+    if schedule.from_username != owner {
+        return Err(ApiError::NotFound);
+    }
+    sqlx::query_as::<_, ScheduledRun>(
+        r#"
+        SELECT run_id, scheduled_for, ran_at, txn_id, error FROM scheduled_transfer_runs
+        WHERE schedule_id = $1
+        ORDER BY ran_at DESC
+        "#,
+    )
+    .bind(schedule_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Pauses, resumes or cancels one of `owner`'s schedules. Resuming clears
+/// the failure count and runs a missed run right away. A schedule paused
+/// with no next run, because it could not be worked out after a run had
+/// paid, resumes at its next occurrence from now, or not at all while that
+/// still cannot be computed.
+pub async fn set_schedule_status(
+    pool: &PgPool,
+    owner: &str,
+    schedule_id: Uuid,
+    status: ScheduleStatus,
+) -> Result<ScheduledTransfer> {
+    debug!(
+        "Setting schedule {:?} of {:?} to {:?}",
+        schedule_id, owner, status
+    );
+    let allowed_from: &[ScheduleStatus] = match status {
+        ScheduleStatus::Paused => &[ScheduleStatus::Active],
+        ScheduleStatus::Active => &[ScheduleStatus::Paused],
+        ScheduleStatus::Cancelled => &[ScheduleStatus::Active, ScheduleStatus::Paused],
+        ScheduleStatus::Completed => {
+            return Err(ApiError::Validation(
+                "schedules complete on their own".to_string(),
+            ));
+        }
+    };
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    sqlx::query(r#"SELECT 1 FROM scheduled_transfers WHERE schedule_id = $1 FOR UPDATE"#)
+        .bind(schedule_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?
+        .ok_or(ApiError::NotFound)?;
+    let schedule = fetch_schedule(&mut tx, schedule_id).await?;
+    if schedule.from_username != owner {
+        return Err(ApiError::NotFound);
+    }
+    if !allowed_from.contains(&schedule.status) {
+        return Err(ApiError::Conflict(
+            format!("schedule is {:?}", schedule.status).to_lowercase(),
+        ));
+    }
+    let next_run_at = match schedule.next_run_at {
+        None if status == ScheduleStatus::Active => {
+            let cron = schedule
+                .cron_expr
+                .as_deref()
+                .map(parse_cron)
+                .transpose()
+                .map_err(|_| {
+                    ApiError::Conflict("schedule's next run cannot be worked out".to_string())
+                })?;
+            let next = occurrence_after(
+                schedule.frequency,
+                cron.as_ref(),
+                schedule.start_at,
+                schedule.end_at,
+                Utc::now(),
+            )
+            .ok_or_else(|| ApiError::Conflict("schedule has no more runs".to_string()))?;
+            Some(next)
+        }
+        _ => None,
+    };
+
+    sqlx::query(
+        r#"
+        UPDATE scheduled_transfers
+        SET status = $1,
+            failure_count = CASE WHEN $1 = 'active' THEN 0 ELSE failure_count END,
+            next_run_at = CASE
+                WHEN $1 = 'active' THEN COALESCE($3, GREATEST(next_run_at, NOW()))
+                ELSE next_run_at
+            END
+        WHERE schedule_id = $2
+        "#,
+    )
+    .bind(status)
+    .bind(schedule_id)
+    .bind(next_run_at)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let schedule = fetch_schedule(&mut tx, schedule_id).await?;
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(schedule)
+}
+
+/// Failures that may clear up on their own, such as a low balance. Anything
+/// else pauses the schedule straight away.
+fn is_retryable(err: &ApiError) -> bool {
+    matches!(
+        err,
+        ApiError::BalanceLow
+            | ApiError::AccountFrozen
+            | ApiError::KycLimitExceeded(_)
+            | ApiError::SpendingLimitExceeded(_)
+            | ApiError::Database(_)
+    )
+}
+
+/// Claims one due schedule and runs it. The row stays locked until the run
+/// is recorded, and `SKIP LOCKED` lets several workers share the queue.
+/// Returns false when nothing is due.
+async fn run_next_due(pool: &PgPool) -> Result<bool> {
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    let Some(schedule) = sqlx::query_as::<_, ScheduledTransfer>(&format!(
+        r#"{} WHERE s.status = 'active' AND s.next_run_at <= NOW()
+        ORDER BY s.next_run_at
+        LIMIT 1
+        FOR UPDATE OF s SKIP LOCKED"#,
+        SELECT_SCHEDULES
+    ))
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    else {
+        return Ok(false);
+    };
+    let Some(scheduled_for) = schedule.next_run_at else {
+        return Ok(false);
+    };
+    debug!("Running scheduled transfer {:?}", schedule.schedule_id);
+
+    let txn = Transaction {
+        txn_id: Uuid::new_v4(),
+        amount: schedule.amount,
+        from_username: schedule.from_username.clone(),
+        to_username: schedule.to_username.clone(),
+        time: Utc::now(),
+        memo: None,
+        kind: TransferKind::Scheduled,
+        fee: 0.0,
+    };
+    // A savepoint, so a failed transfer can be rolled back and still recorded.
+    let mut attempt = tx.begin().await.map_err(ApiError::Database)?;
+    let outcome = transfer(&mut attempt, &txn).await;
+    match &outcome {
+        Ok(()) => attempt.commit().await.map_err(ApiError::Database)?,
+        Err(_) => attempt.rollback().await.map_err(ApiError::Database)?,
+    }
+
+    let now = Utc::now();
+    let (status, next_run_at, failure_count, error) = match &outcome {
+        Ok(()) => match schedule.cron_expr.as_deref().map(parse_cron).transpose() {
+            Ok(cron) => {
+                // Runs missed while the worker was down are skipped, not replayed.
+                let next = occurrence_after(
+                    schedule.frequency,
+                    cron.as_ref(),
+                    schedule.start_at,
+                    schedule.end_at,
+                    now.max(scheduled_for),
+                );
+                match next {
+                    Some(next) => (ScheduleStatus::Active, Some(next), 0, None),
+                    None => (ScheduleStatus::Completed, None, 0, None),
+                }
+            }
+            // The transfer has already gone through, but with no next run to
+            // work out the schedule cannot stay in the queue.
+            Err(e) => {
+                warn!(
+                    "Pausing scheduled transfer {:?}, its next run could not be computed: {}",
+                    schedule.schedule_id, e
+                );
+                (
+                    ScheduleStatus::Paused,
+                    None,
+                    schedule.failure_count + 1,
+                    Some(e.to_string()),
+                )
+            }
+        },
+        Err(e) => {
+            let failures = schedule.failure_count + 1;
+            if is_retryable(e) && failures <= schedule.max_retries {
+                let delay = Duration::seconds(RETRY_BASE_DELAY_SECS << (failures - 1).min(16));
+                (
+                    ScheduleStatus::Active,
+                    Some(now + delay),
+                    failures,
+                    Some(e.to_string()),
+                )
+            } else {
+                warn!(
+                    "Pausing scheduled transfer {:?} after {} failures: {}",
+                    schedule.schedule_id, failures, e
+                );
+                (
+                    ScheduleStatus::Paused,
+                    Some(scheduled_for),
+                    failures,
+                    Some(e.to_string()),
+                )
+            }
+        }
+    };
+    let txn_id = outcome.is_ok().then_some(txn.txn_id);
+
+    sqlx::query(
+        r#"
+        INSERT INTO scheduled_transfer_runs (run_id, schedule_id, scheduled_for, txn_id, error)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(schedule.schedule_id)
+    .bind(scheduled_for)
+    .bind(txn_id)
+    .bind(error.as_deref())
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    sqlx::query(
+        r#"
+        UPDATE scheduled_transfers
+        SET status = $1, next_run_at = $2, failure_count = $3, last_error = $4, last_run_at = NOW()
+        WHERE schedule_id = $5
+        "#,
+    )
+    .bind(status)
+    .bind(next_run_at)
+    .bind(failure_count)
+    .bind(error.as_deref())
+    .bind(schedule.schedule_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    Ok(true)
+}
+
+/// Runs up to `max` due schedules and returns how many were run.
+pub async fn run_due_transfers(pool: &PgPool, max: usize) -> Result<usize> {
+    let mut ran = 0;
+    while ran < max && run_next_due(pool).await? {
+        ran += 1;
+    }
+    Ok(ran)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::fetch_balance;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_monthly_occurrences_keep_their_day() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 31, 9, 0, 0).unwrap();
+        let feb = occurrence_after(ScheduleFrequency::Monthly, None, start, None, start).unwrap();
+        assert_eq!(feb, Utc.with_ymd_and_hms(2024, 2, 29, 9, 0, 0).unwrap());
+        let mar = occurrence_after(ScheduleFrequency::Monthly, None, start, None, feb).unwrap();
+        assert_eq!(mar, Utc.with_ymd_and_hms(2024, 3, 31, 9, 0, 0).unwrap());
+
+        let weekly = occurrence_after(ScheduleFrequency::Weekly, None, start, None, mar).unwrap();
+        assert_eq!(weekly, Utc.with_ymd_and_hms(2024, 4, 3, 9, 0, 0).unwrap());
+        assert_eq!(
+            occurrence_after(ScheduleFrequency::Once, None, start, None, start),
+            None
+        );
+
+        let cron = parse_cron("0 0 9 1 * *").unwrap();
+        let next = occurrence_after(ScheduleFrequency::Cron, Some(&cron), start, None, start);
+        assert_eq!(
+            next,
+            Some(Utc.with_ymd_and_hms(2024, 2, 1, 9, 0, 0).unwrap())
+        );
+        let end = Some(Utc.with_ymd_and_hms(2024, 1, 31, 23, 0, 0).unwrap());
+        assert_eq!(
+            occurrence_after(ScheduleFrequency::Daily, None, start, end, start),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_worker_runs_due_schedules_and_pauses_after_retries() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+
+        let rent = create_schedule(
+            &pool,
+            &alice.username,
+            &NewSchedule {
+                to_username: bob.username.clone(),
+                amount: 60.0,
+                frequency: ScheduleFrequency::Monthly,
+                cron_expr: None,
+                start_at: Some(Utc::now() - Duration::minutes(1)),
+                end_at: None,
+                max_retries: Some(1),
+            },
+        )
+        .await
+        .unwrap();
+        run_due_transfers(&pool, 100).await.unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        let ran = fetch_schedule(&mut conn, rent.schedule_id).await.unwrap();
+        assert_eq!(ran.status, ScheduleStatus::Active);
+        assert!(ran.next_run_at.unwrap() > Utc::now() + Duration::days(27));
+        assert_eq!(
+            fetch_balance(&pool, &bob.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(60.0)
+        );
+
+        // 40 left, so the next run fails, retries once and then pauses.
+        sqlx::query("UPDATE scheduled_transfers SET next_run_at = NOW() WHERE schedule_id = $1")
+            .bind(rent.schedule_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        run_due_transfers(&pool, 100).await.unwrap();
+        let failed = fetch_schedule(&mut conn, rent.schedule_id).await.unwrap();
+        assert_eq!(failed.failure_count, 1);
+        assert_eq!(failed.status, ScheduleStatus::Active);
+        assert!(failed.next_run_at.unwrap() > Utc::now());
+
+        sqlx::query("UPDATE scheduled_transfers SET next_run_at = NOW() WHERE schedule_id = $1")
+            .bind(rent.schedule_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        run_due_transfers(&pool, 100).await.unwrap();
+        let paused = fetch_schedule(&mut conn, rent.schedule_id).await.unwrap();
+        assert_eq!(paused.status, ScheduleStatus::Paused);
+
+        let runs = list_runs(&pool, &alice.username, rent.schedule_id)
+            .await
+            .unwrap();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs.iter().filter(|r| r.txn_id.is_some()).count(), 1);
+        assert_eq!(
+            fetch_balance(&pool, &alice.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(40.0)
+        );
+
+        let resumed = set_schedule_status(
+            &pool,
+            &alice.username,
+            rent.schedule_id,
+            ScheduleStatus::Active,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resumed.failure_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_without_next_run_pauses_and_is_not_paid_twice() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        let rent = create_schedule(
+            &pool,
+            &alice.username,
+            &NewSchedule {
+                to_username: bob.username.clone(),
+                amount: 30.0,
+                frequency: ScheduleFrequency::Cron,
+                cron_expr: Some("0 0 9 1 * *".to_string()),
+                start_at: None,
+                end_at: None,
+                max_retries: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        // The cron stops parsing after the schedule was created, so the run
+        // pays but no next run can be worked out.
+        sqlx::query(
+            "UPDATE scheduled_transfers SET next_run_at = NOW(), cron_expr = 'not a cron' \
+             WHERE schedule_id = $1",
+        )
+        .bind(rent.schedule_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        run_due_transfers(&pool, 100).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        let broken = fetch_schedule(&mut conn, rent.schedule_id).await.unwrap();
+        assert_eq!(broken.status, ScheduleStatus::Paused);
+        assert_eq!(broken.next_run_at, None);
+        assert!(broken.last_error.is_some());
+
+        // Resuming cannot queue a run it cannot compute.
+        let res = set_schedule_status(
+            &pool,
+            &alice.username,
+            rent.schedule_id,
+            ScheduleStatus::Active,
+        )
+        .await;
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+
+        // Once it can, the next run is queued for later and the run that
+        // already paid is not repeated.
+        sqlx::query("UPDATE scheduled_transfers SET cron_expr = $1 WHERE schedule_id = $2")
+            .bind("0 0 9 1 * *")
+            .bind(rent.schedule_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let resumed = set_schedule_status(
+            &pool,
+            &alice.username,
+            rent.schedule_id,
+            ScheduleStatus::Active,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resumed.status, ScheduleStatus::Active);
+        assert!(resumed.next_run_at.unwrap() > Utc::now());
+        run_due_transfers(&pool, 100).await.unwrap();
+
+        let runs = list_runs(&pool, &alice.username, rent.schedule_id)
+            .await
+            .unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(
+            fetch_balance(&pool, &bob.username)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(30.0)
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use crate::http::db::model::{Statement, StatementEntry};
+use crate::http::db::queries::{fees, funding, interest};
+use crate::http::errors::{ApiError, Result};
+use chrono::{Datelike, Months, NaiveDate, NaiveTime, Utc};
+use log::{debug, error};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+const SELECT_STATEMENTS: &str = r#"
+    SELECT s.statement_id, s.username, s.account_name, s.period_start, s.period_end,
+        s.opening_balance, s.closing_balance, s.total_in, s.total_out, s.generated_at
+    FROM statements s
+    JOIN users u ON u.userid = s.userid
+"#;
+
+/// Parses a `YYYY-MM` month into its first day.
+pub fn parse_month(month: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d")
+        .map_err(|_| ApiError::Validation("month must be in YYYY-MM format".to_string()))
+}
+
+/// The first day of the month before the one `day` falls in.
+pub fn previous_month(day: NaiveDate) -> Option<NaiveDate> {
+    day.with_day(1)?.checked_sub_months(Months::new(1))
+}
+
+/// Lists a user's statements, newest first, without their entries.
+pub async fn list_statements(pool: &PgPool, username: &str) -> Result<Vec<Statement>> {
+    debug!("Listing statements for {:?}", username);
+    sqlx::query_as::<_, Statement>(&format!(
+        "{} WHERE u.username = $1 ORDER BY s.period_start DESC",
+        SELECT_STATEMENTS
+    ))
+    .bind(username)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)
+}
+
+/// Fetches a stored statement with its entries.
+pub async fn fetch_statement(
+    pool: &PgPool,
+    username: &str,
+    period_start: NaiveDate,
+) -> Result<Option<Statement>> {
+    debug!(
+        "Fetching statement for {:?} starting {}",
+        username, period_start
+    );
+    let Some(mut statement) = sqlx::query_as::<_, Statement>(&format!(
+        "{} WHERE u.username = $1 AND s.period_start = $2",
+        SELECT_STATEMENTS
+    ))
+    .bind(username)
+    .bind(period_start)
+    .fetch_optional(pool)
+    .await
+    .map_err(ApiError::Database)?
+    else {
+        return Ok(None);
+    };
+    statement.entries = sqlx::query_as::<_, StatementEntry>(
+        r#"
+        SELECT txn_id, posted_at, kind, direction, counterparty_username, memo, amount, fee,
+            balance_after
+        FROM statement_entries
+        WHERE statement_id = $1
+        ORDER BY line_no
+        "#,
+    )
+    .bind(statement.statement_id)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)?;
+    Ok(Some(statement))
+}
+
+/// Returns the user's statement for the month starting `period_start`,
+/// generating and storing it the first time it is asked for. Only finished
+/// months can be generated. A stored statement is never regenerated, so
+/// transactions posted into a past period later do not change it.
+///
+/// Entries are placed by when the server posted them, not the
+/// client-supplied transaction time, and each carries the balance recorded
+/// when it was posted, the same one the transaction history shows. The
+/// opening balance is that of the last entry before the period.
+pub async fn generate_statement(
+    pool: &PgPool,
+    username: &str,
+    period_start: NaiveDate,
+) -> Result<Statement> {
+    debug!(
+        "Generating statement for {:?} starting {}",
+        username, period_start
+    );
+    if period_start.day() != 1 {
+        return Err(ApiError::Validation(
+            "statements start on the first day of a month".to_string(),
+        ));
+    }
+    let period_end = period_start
+        .checked_add_months(Months::new(1))
+        .ok_or_else(|| ApiError::Validation("month is out of range".to_string()))?;
+    if period_end > Utc::now().date_naive() {
+        return Err(ApiError::Validation(
+            "statements are only available for finished months".to_string(),
+        ));
+    }
+    if let Some(statement) = fetch_statement(pool, username, period_start).await? {
+        return Ok(statement);
+    }
+    let start = period_start.and_time(NaiveTime::MIN).and_utc();
+    let end = period_end.and_time(NaiveTime::MIN).and_utc();
+
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    // The balance and the transactions must come from the same snapshot.
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut *tx)
+        .await
+        .map_err(ApiError::Database)?;
+    let user = sqlx::query(
+        r#"
+        SELECT u.userid, u.username, u.name,
+            COALESCE((
+                SELECT CASE WHEN t.from_userid = u.userid THEN t.from_balance_after
+                    ELSE t.to_balance_after END
+                FROM transactions t
+                WHERE (t.from_userid = u.userid OR t.to_userid = u.userid) AND t.created_at < $2
+                ORDER BY t.created_at DESC, t.time DESC, t.txn_id DESC
+                LIMIT 1
+            ),
+            -- Before its first transaction, an account held what it opened with.
+            u.balance - COALESCE((
+                SELECT SUM(CASE WHEN t.to_userid = u.userid THEN t.amount ELSE -(t.amount + t.fee) END)
+                FROM transactions t
+                WHERE t.from_userid = u.userid OR t.to_userid = u.userid
+            ), 0)) AS opening_balance
+        FROM users u
+        WHERE u.username = $1
+        "#,
+    )
+    .bind(username)
+    .bind(start)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?
+    .ok_or(ApiError::UserNotFound)?;
+    let userid: Uuid = user.get("userid");
+    let opening_balance: f64 = user.get("opening_balance");
+
+    let entries = sqlx::query_as::<_, StatementEntry>(
+        r#"
+        SELECT t.txn_id, t.created_at AS posted_at, t.kind,
+            CASE WHEN t.from_userid = $1 THEN 'outgoing' ELSE 'incoming' END AS direction,
+            c.username AS counterparty_username,
+            t.memo,
+            CASE WHEN t.from_userid = $1 THEN -(t.amount + t.fee) ELSE t.amount END AS amount,
+            CASE WHEN t.from_userid = $1 THEN t.fee ELSE 0 END AS fee,
+            CASE WHEN t.from_userid = $1 THEN t.from_balance_after ELSE t.to_balance_after END
+                AS balance_after
+        FROM transactions t
+        JOIN users c ON c.userid = CASE WHEN t.from_userid = $1 THEN t.to_userid ELSE t.from_userid END
+        WHERE (t.from_userid = $1 OR t.to_userid = $1) AND t.created_at >= $2 AND t.created_at < $3
+        ORDER BY t.created_at, t.time, t.txn_id
+        "#,
+    )
+    .bind(userid)
+    .bind(start)
+    .bind(end)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    let total_in: f64 = entries.iter().map(|e| e.amount.max(0.0)).sum();
+    let total_out: f64 = entries.iter().map(|e| (-e.amount).max(0.0)).sum();
+    let closing_balance = entries
+        .last()
+        .map_or(opening_balance, |entry| entry.balance_after);
+
+    let statement_id = Uuid::new_v4();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO statements (statement_id, userid, username, account_name, period_start,
+            period_end, opening_balance, closing_balance, total_in, total_out)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (userid, period_start) DO NOTHING
+        "#,
+    )
+    .bind(statement_id)
+    .bind(userid)
+    .bind(user.get::<String, _>("username"))
+    .bind(user.get::<String, _>("name"))
+    .bind(period_start)
+    .bind(period_end)
+    .bind(opening_balance)
+    .bind(closing_balance)
+    .bind(total_in)
+    .bind(total_out)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    if inserted.rows_affected() == 1 {
+        for (line_no, entry) in entries.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO statement_entries (statement_id, line_no, txn_id, posted_at, kind,
+                    direction, counterparty_username, memo, amount, fee, balance_after)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                "#,
+            )
+            .bind(statement_id)
+            .bind(line_no as i32 + 1)
+            .bind(entry.txn_id)
+            .bind(entry.posted_at)
+            .bind(entry.kind)
+            .bind(entry.direction)
+            .bind(&entry.counterparty_username)
+            .bind(&entry.memo)
+            .bind(entry.amount)
+            .bind(entry.fee)
+            .bind(entry.balance_after)
+            .execute(&mut *tx)
+            .await
+            .map_err(ApiError::Database)?;
+        }
+    } else {
+        debug!("Statement for {:?} was generated concurrently", username);
+    }
+    tx.commit().await.map_err(ApiError::Database)?;
+
+    fetch_statement(pool, username, period_start)
+        .await?
+        .ok_or(ApiError::InternalServerError)
+}
+
+/// Generates the statement for the month starting `period_start` for every
+/// user who does not have one yet. Closed accounts only get one if they had
+/// activity since the period began, and system accounts are skipped. A
+/// failure for one user is logged and does not stop the others. Returns the
+/// number of statements generated.
+pub async fn generate_month(pool: &PgPool, period_start: NaiveDate) -> Result<usize> {
+    debug!(
+        "Generating statements for the month starting {}",
+        period_start
+    );
+    let start = period_start.and_time(NaiveTime::MIN).and_utc();
+    let usernames: Vec<String> = sqlx::query(
+        r#"
+        SELECT u.username FROM users u
+        WHERE u.username NOT IN ($3, $4, $5)
+            AND NOT EXISTS (
+                SELECT 1 FROM statements s WHERE s.userid = u.userid AND s.period_start = $1)
+            AND (u.status <> 'closed' OR EXISTS (
+                SELECT 1 FROM transactions t
+                WHERE (t.from_userid = u.userid OR t.to_userid = u.userid)
+                    AND t.created_at >= $2))
+        "#,
+    )
+    .bind(period_start)
+    .bind(start)
+    .bind(fees::FEE_ACCOUNT_USERNAME)
+    .bind(interest::INTEREST_ACCOUNT_USERNAME)
+    .bind(funding::FUNDING_ACCOUNT_USERNAME)
+    .fetch_all(pool)
+    .await
+    .map_err(ApiError::Database)?
+    .iter()
+    .map(|row| row.get("username"))
+    .collect();
+
+    let mut generated = 0;
+    for username in usernames {
+        match generate_statement(pool, &username, period_start).await {
+            Ok(_) => generated += 1,
+            Err(e) => error!(
+                "Statement for {} starting {} failed: {}",
+                username, period_start, e
+            ),
+        }
+    }
+    Ok(generated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::model::TransactionFilter;
+    use crate::http::db::queries::{fetch_transactions, insert_transaction};
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+    use chrono::Days;
+
+    /// Posts a transfer and moves it into the middle of last month.
+    async fn post_last_month(pool: &PgPool, from: &str, to: &str, amount: f64) {
+        let txn = transaction(from, to, amount);
+        insert_transaction(pool, &txn).await.unwrap();
+        let mid_month = previous_month(Utc::now().date_naive())
+            .unwrap()
+            .checked_add_days(Days::new(14))
+            .unwrap()
+            .and_time(NaiveTime::MIN)
+            .and_utc();
+        sqlx::query(r#"UPDATE transactions SET created_at = $1 WHERE txn_id = $2"#)
+            .bind(mid_month)
+            .bind(txn.txn_id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_statement_is_generated_once_and_never_changes() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 1000.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        post_last_month(&pool, &alice.username, &bob.username, 50.0).await;
+        post_last_month(&pool, &bob.username, &alice.username, 20.0).await;
+        // Posted this month, so it belongs to the next statement.
+        insert_transaction(&pool, &transaction(&alice.username, &bob.username, 100.0))
+            .await
+            .unwrap();
+
+        let month = previous_month(Utc::now().date_naive()).unwrap();
+        let statement = generate_statement(&pool, &alice.username, month)
+            .await
+            .unwrap();
+        assert_eq!(statement.opening_balance, 1000.0);
+        assert_eq!(statement.closing_balance, 970.0);
+        assert_eq!(statement.total_in, 20.0);
+        assert_eq!(statement.total_out, 50.0);
+        assert_eq!(statement.entries.len(), 2);
+        assert_eq!(
+            statement.entries.last().unwrap().balance_after,
+            statement.closing_balance
+        );
+        // Each line shows the same balance as the transaction history.
+        let history = fetch_transactions(&pool, &alice.username, &TransactionFilter::default())
+            .await
+            .unwrap();
+        for entry in &statement.entries {
+            let listed = history
+                .transactions
+                .iter()
+                .find(|t| t.txn_id == entry.txn_id)
+                .unwrap();
+            assert_eq!(listed.balance_after, entry.balance_after);
+        }
+
+        // A late posting into the period does not rewrite the statement.
+        post_last_month(&pool, &alice.username, &bob.username, 5.0).await;
+        let again = generate_statement(&pool, &alice.username, month)
+            .await
+            .unwrap();
+        assert_eq!(again.statement_id, statement.statement_id);
+        assert_eq!(again.closing_balance, 970.0);
+        assert_eq!(again.entries.len(), 2);
+
+        let tampered =
+            sqlx::query(r#"UPDATE statements SET closing_balance = 0 WHERE statement_id = $1"#)
+                .bind(statement.statement_id)
+                .execute(&pool)
+                .await;
+        assert!(tampered.is_err());
+
+        let this_month = Utc::now().date_naive().with_day(1).unwrap();
+        assert!(matches!(
+            generate_statement(&pool, &alice.username, this_month).await,
+            Err(ApiError::Validation(_))
+        ));
+        assert_eq!(
+            list_statements(&pool, &alice.username).await.unwrap().len(),
+            1
+        );
+    }
+}
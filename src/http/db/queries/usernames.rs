@@ -0,0 +1,171 @@
+use crate::http::errors::{ApiError, Result};
+use log::debug;
+use sqlx::{PgConnection, PgPool, Row};
+use uuid::Uuid;
+
+/// How long a previous username stays reserved for its owner and keeps
+/// resolving to them as a transfer recipient.
+pub const USERNAME_RESERVATION_DAYS: i32 = 30;
+
+/// Resolves a username to an account, following renames whose old name is
+/// still within its reservation window. Current usernames always win.
+pub async fn resolve_username(conn: &mut PgConnection, username: &str) -> Result<Option<Uuid>> {
+    debug!("Resolving username: {:?}", username);
+    let rec = sqlx::query(
+        r#"
+        SELECT userid FROM (
+            SELECT userid, 0 AS priority, NOW() AS changed_at FROM users WHERE username = $1
+            UNION ALL
+            SELECT userid, 1 AS priority, changed_at FROM username_history
+            WHERE old_username = $1 AND reserved_until > NOW()
+        ) candidates
+        ORDER BY priority, changed_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(username)
+    .fetch_optional(conn)
+    .await
+    .map_err(ApiError::Database)?;
+
+    let userid = rec.map(|row| row.get("userid"));
+    debug!("Resolved username {:?} to {:?}", username, userid);
+    Ok(userid)
+}
+
+/// Takes transaction-scoped advisory locks on the given usernames, so that
+/// a signup or rename checking a name's reservation cannot interleave with
+/// another transaction claiming or releasing the same name.
+pub async fn lock_usernames(conn: &mut PgConnection, usernames: &[&str]) -> Result<()> {
+    let mut usernames = usernames.to_vec();
+    usernames.sort_unstable();
+    usernames.dedup();
+    for username in usernames {
+        sqlx::query(r#"SELECT pg_advisory_xact_lock(hashtext($1))"#)
+            .bind(username)
+            .execute(&mut *conn)
+            .await
+            .map_err(ApiError::Database)?;
+    }
+    Ok(())
+}
+
+pub async fn rename_user(pool: &PgPool, username: &str, new_username: &str) -> Result<()> {
+    debug!("Renaming user {:?} to {:?}", username, new_username);
+    let mut tx = pool.begin().await.map_err(ApiError::Database)?;
+    lock_usernames(&mut tx, &[username, new_username]).await?;
+
+    let userid: Uuid =
+        match sqlx::query(r#"SELECT userid FROM users WHERE username = $1 FOR UPDATE"#)
+            .bind(username)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(ApiError::Database)?
+        {
+            Some(row) => row.get("userid"),
+            None => return Err(ApiError::UserNotFound),
+        };
+
+    // A name is unavailable while someone holds it, or while it is reserved
+    // for another account that recently renamed away from it.
+    let taken = sqlx::query(
+        r#"
+        SELECT 1 FROM users WHERE username = $1
+        UNION ALL
+        SELECT 1 FROM username_history
+        WHERE old_username = $1 AND reserved_until > NOW() AND userid <> $2
+        LIMIT 1
+        "#,
+    )
+    .bind(new_username)
+    .bind(userid)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+    if taken.is_some() {
+        debug!("Username {:?} is not available", new_username);
+        return Err(ApiError::UsernameTaken);
+    }
+
+    sqlx::query(r#"UPDATE users SET username = $1 WHERE userid = $2"#)
+        .bind(new_username)
+        .bind(userid)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => ApiError::UsernameTaken,
+            e => ApiError::Database(e),
+        })?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO username_history (old_username, userid, changed_at, reserved_until)
+        VALUES ($1, $2, NOW(), NOW() + make_interval(days => $3))
+        "#,
+    )
+    .bind(username)
+    .bind(userid)
+    .bind(USERNAME_RESERVATION_DAYS)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::Database)?;
+
+    tx.commit().await.map_err(ApiError::Database)?;
+    debug!("Renamed user {:?} to {:?}", username, new_username);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::model::{Transaction, TransferKind, User};
+    use crate::http::db::queries::{fetch_balance, insert_transaction, new_user};
+    use crate::http::db::test_utils::{create_test_user, phone_number, setup_test_db};
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_rename_reserves_old_name_and_redirects_transfers() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let new_name = format!("alice_new_{}", Uuid::new_v4());
+
+        rename_user(&pool, &alice.username, &new_name)
+            .await
+            .unwrap();
+
+        // The old handle still resolves to alice during the grace window.
+        let txn = Transaction {
+            txn_id: Uuid::new_v4(),
+            amount: 25.0,
+            from_username: bob.username.clone(),
+            to_username: alice.username.clone(),
+            time: Utc::now(),
+            memo: None,
+            kind: TransferKind::P2p,
+            fee: 0.0,
+        };
+        insert_transaction(&pool, &txn).await.unwrap();
+        assert_eq!(
+            fetch_balance(&pool, &new_name)
+                .await
+                .unwrap()
+                .map(|b| b.ledger_balance),
+            Some(125.0)
+        );
+
+        // Nobody else can claim the reserved name.
+        let res = rename_user(&pool, &bob.username, &alice.username).await;
+        assert!(matches!(res, Err(ApiError::UsernameTaken)));
+
+        // Signing up with it is blocked as well.
+        let squatter = User {
+            userid: Uuid::new_v4(),
+            username: alice.username.clone(),
+            phno: phone_number(),
+            ..alice.clone()
+        };
+        let res = new_user(&pool, &squatter).await;
+        assert!(matches!(res, Err(ApiError::UsernameTaken)));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::http::config::Config;
+use crate::http::db::model::{Transaction, TransferKind, User};
+use crate::http::db::queries::new_user;
+use chrono::Utc;
+use sqlx::{PgPool, postgres::PgPoolOptions};
+use uuid::Uuid;
+
+pub async fn setup_test_db() -> PgPool {
+    let database_url = dotenvy::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&database_url)
+        .await
+        .expect("Failed to connect to test database")
+}
+
+/// A config for handler tests, with fixed secrets and the test database.
+pub fn test_config() -> Config {
+    Config::from_sources(None, |key| match key {
+        "FUNDING_PROVIDER" => Some("simulated".to_string()),
+        "JWT_SECRET" => Some("jwt_secret_for_handler_tests_0123456789".to_string()),
+        "FUNDING_WEBHOOK_SECRET" => Some("webhook_secret_for_handler_tests_0123456789".to_string()),
+        _ => dotenvy::var(key).ok(),
+    })
+    .expect("test config must be valid")
+}
+
+/// A random phone number in a range the fixed fixtures never use.
+pub fn phone_number() -> String {
+    format!("+1{:010}", Uuid::new_v4().as_u128() % 10_000_000_000)
+}
+
+pub async fn create_test_user(pool: &PgPool, prefix: &str, balance: f64) -> User {
+    let user = User {
+        userid: Uuid::new_v4(),
+        name: format!("Test {}", prefix),
+        username: format!("{}_{}", prefix, Uuid::new_v4()),
+        phno: phone_number(),
+        address: "Test Address".to_string(),
+        balance,
+        password_hash: "hash".to_string(),
+        email: None,
+        discoverable: false,
+    };
+    new_user(pool, &user)
+        .await
+        .expect("Failed to create test user");
+    set_balance(pool, user.userid, balance).await;
+    user
+}
+
+/// Gives a test account a starting balance. Outside tests, money only
+/// arrives through deposits.
+pub async fn set_balance(pool: &PgPool, userid: Uuid, balance: f64) {
+    sqlx::query("UPDATE users SET balance = $1 WHERE userid = $2")
+        .bind(balance)
+        .bind(userid)
+        .execute(pool)
+        .await
+        .expect("Failed to set test balance");
+}
+
+pub fn transaction(from: &str, to: &str, amount: f64) -> Transaction {
+    Transaction {
+        txn_id: Uuid::new_v4(),
+        amount,
+        from_username: from.to_string(),
+        to_username: to.to_string(),
+        time: Utc::now(),
+        memo: None,
+        kind: TransferKind::P2p,
+        fee: 0.0,
+    }
+}
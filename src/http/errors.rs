@@ -13,9 +13,36 @@ pub enum ApiError {
     #[error("User not found")]
     UserNotFound,
 
+    #[error("Username is not available")]
+    UsernameTaken,
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Too many requests")]
+    RateLimited,
+
     #[error("Balance too low for transaction")]
     BalanceLow,
 
+    #[error("Account is frozen")]
+    AccountFrozen,
+
+    #[error("Account is closed")]
+    AccountClosed,
+
+    #[error("KYC limit exceeded: {0}")]
+    KycLimitExceeded(String),
+
+    #[error("Spending limit exceeded: {0}")]
+    SpendingLimitExceeded(String),
+
+    #[error("Not found")]
+    NotFound,
+
+    #[error("Transaction not found")]
+    TransactionNotFound,
+
     #[error("JWT error: {0}")]
     Jwt(String),
 
@@ -42,8 +69,20 @@ impl ResponseError for ApiError {
             ApiError::InvalidCredentials | ApiError::Unauthorized => {
                 HttpResponse::Unauthorized().body(self.to_string())
             }
-            ApiError::UserNotFound => HttpResponse::NotFound().body(self.to_string()),
+            ApiError::UserNotFound | ApiError::NotFound | ApiError::TransactionNotFound => {
+                HttpResponse::NotFound().body(self.to_string())
+            }
+            ApiError::UsernameTaken | ApiError::Conflict(_) => {
+                HttpResponse::Conflict().body(self.to_string())
+            }
+            ApiError::RateLimited => HttpResponse::TooManyRequests().body(self.to_string()),
             ApiError::BalanceLow => HttpResponse::BadRequest().body(self.to_string()),
+            ApiError::AccountFrozen
+            | ApiError::AccountClosed
+            | ApiError::KycLimitExceeded(_)
+            | ApiError::SpendingLimitExceeded(_) => {
+                HttpResponse::Forbidden().body(self.to_string())
+            }
             ApiError::Validation(_) => HttpResponse::BadRequest().body(self.to_string()),
             ApiError::Payload(_) => HttpResponse::BadRequest().body(self.to_string()),
             ApiError::Database(_) | ApiError::InternalServerError | ApiError::Jwt(_) => {
@@ -0,0 +1,309 @@
+//! Transaction history in formats accounting tools and spreadsheets import.
+//! An export is a header, the encoded entries in history order (newest
+//! first) and a footer, so it can be written out a page at a time.
+
+use crate::http::db::model::{TransactionEntry, TransferDirection, TransferKind};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use std::fmt::{self, Write};
+
+/// The ledger has no currency of its own, so OFX files declare this one.
+pub const OFX_CURRENCY: &str = "USD";
+/// Stands in for a bank id in OFX account blocks.
+pub const OFX_BANK_ID: &str = "PAYFREE";
+/// OFX limits payee names to 32 characters.
+const OFX_NAME_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ofx,
+    Qif,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ofx => "application/x-ofx",
+            ExportFormat::Qif => "application/qif",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ofx => "ofx",
+            ExportFormat::Qif => "qif",
+        }
+    }
+}
+
+/// What the header and footer say about the export as a whole.
+#[derive(Debug, Clone)]
+pub struct ExportContext {
+    pub username: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub generated_at: DateTime<Utc>,
+    /// The balance after the newest exported entry, or the current balance
+    /// when there are none, and when that was.
+    pub ledger_balance: f64,
+    pub ledger_balance_at: DateTime<Utc>,
+}
+
+pub fn header(format: ExportFormat, ctx: &ExportContext) -> String {
+    match format {
+        ExportFormat::Csv => {
+            "date,txn_id,direction,kind,counterparty,counterparty_name,amount,fee,\
+            balance_after,memo,category,tags\r\n"
+                .to_string()
+        }
+        ExportFormat::Ofx => format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n\
+            <?OFX OFXHEADER=\"200\" VERSION=\"211\" SECURITY=\"NONE\" OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"?>\n\
+            <OFX>\n\
+            <SIGNONMSGSRSV1><SONRS><STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\
+            <DTSERVER>{}</DTSERVER><LANGUAGE>ENG</LANGUAGE></SONRS></SIGNONMSGSRSV1>\n\
+            <BANKMSGSRSV1><STMTTRNRS><TRNUID>0</TRNUID>\
+            <STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\n\
+            <STMTRS><CURDEF>{}</CURDEF>\n\
+            <BANKACCTFROM><BANKID>{}</BANKID><ACCTID>{}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>\n\
+            <BANKTRANLIST><DTSTART>{}</DTSTART><DTEND>{}</DTEND>\n",
+            ofx_date(ctx.generated_at),
+            OFX_CURRENCY,
+            OFX_BANK_ID,
+            escape_xml(&ctx.username),
+            ofx_date(ctx.from.unwrap_or(DateTime::UNIX_EPOCH)),
+            ofx_date(ctx.to.unwrap_or(ctx.generated_at)),
+        ),
+        ExportFormat::Qif => "!Type:Bank\n".to_string(),
+    }
+}
+
+/// Appends one entry to `out`.
+pub fn write_entry(format: ExportFormat, entry: &TransactionEntry, out: &mut String) {
+    // Writing to a String cannot fail.
+    let _ = encode_entry(format, entry, out);
+}
+
+fn encode_entry(format: ExportFormat, entry: &TransactionEntry, out: &mut String) -> fmt::Result {
+    match format {
+        ExportFormat::Csv => write!(
+            out,
+            "{},{},{},{},{},{},{:.2},{:.2},{:.2},{},{},{}\r\n",
+            entry.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            entry.txn_id,
+            direction_label(entry.direction),
+            kind_label(entry.kind),
+            csv_field(&entry.counterparty_username),
+            csv_field(&entry.counterparty_name),
+            entry.signed_amount,
+            entry.fee,
+            entry.balance_after,
+            csv_field(entry.memo.as_deref().unwrap_or("")),
+            csv_field(entry.category.as_deref().unwrap_or("")),
+            csv_field(&entry.tags.join(";")),
+        ),
+        ExportFormat::Ofx => {
+            let name: String = entry.counterparty_name.chars().take(OFX_NAME_LEN).collect();
+            write!(
+                out,
+                "<STMTTRN><TRNTYPE>{}</TRNTYPE><DTPOSTED>{}</DTPOSTED><TRNAMT>{:.2}</TRNAMT>\
+                <FITID>{}</FITID><NAME>{}</NAME>",
+                match entry.direction {
+                    TransferDirection::Incoming => "CREDIT",
+                    TransferDirection::Outgoing => "DEBIT",
+                },
+                ofx_date(entry.time),
+                entry.signed_amount,
+                entry.txn_id,
+                escape_xml(&single_line(&name)),
+            )?;
+            if let Some(memo) = &entry.memo {
+                write!(out, "<MEMO>{}</MEMO>", escape_xml(&single_line(memo)))?;
+            }
+            writeln!(out, "</STMTTRN>")
+        }
+        ExportFormat::Qif => {
+            write!(
+                out,
+                "D{}\nT{:.2}\nP{}\n",
+                entry.time.format("%m/%d/%Y"),
+                entry.signed_amount,
+                single_line(&entry.counterparty_name),
+            )?;
+            if let Some(memo) = &entry.memo {
+                writeln!(out, "M{}", single_line(memo))?;
+            }
+            if let Some(category) = &entry.category {
+                writeln!(out, "L{}", category)?;
+            }
+            writeln!(out, "^")
+        }
+    }
+}
+
+pub fn footer(format: ExportFormat, ctx: &ExportContext) -> String {
+    match format {
+        ExportFormat::Csv | ExportFormat::Qif => String::new(),
+        ExportFormat::Ofx => format!(
+            "</BANKTRANLIST>\n\
+            <LEDGERBAL><BALAMT>{:.2}</BALAMT><DTASOF>{}</DTASOF></LEDGERBAL>\n\
+            </STMTRS></STMTTRNRS></BANKMSGSRSV1>\n\
+            </OFX>\n",
+            ctx.ledger_balance,
+            ofx_date(ctx.ledger_balance_at),
+        ),
+    }
+}
+
+fn direction_label(direction: TransferDirection) -> &'static str {
+    match direction {
+        TransferDirection::Incoming => "incoming",
+        TransferDirection::Outgoing => "outgoing",
+    }
+}
+
+fn kind_label(kind: TransferKind) -> String {
+    format!("{:?}", kind).to_lowercase()
+}
+
+/// OFX datetimes, always given in UTC.
+fn ofx_date(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%d%H%M%S%.3f[0:UTC]").to_string()
+}
+
+/// Quotes a CSV field when it needs it (RFC 4180), and defuses text a
+/// spreadsheet would otherwise run as a formula.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// QIF and OFX fields are one line each, so control characters become spaces.
+fn single_line(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn context() -> ExportContext {
+        ExportContext {
+            username: "alice".to_string(),
+            from: Some(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap()),
+            generated_at: Utc.with_ymd_and_hms(2024, 6, 2, 8, 30, 0).unwrap(),
+            ledger_balance: 955.5,
+            ledger_balance_at: Utc.with_ymd_and_hms(2024, 5, 20, 18, 45, 10).unwrap(),
+        }
+    }
+
+    /// Newest first, as history returns them. The text fields carry
+    /// everything each format has to escape.
+    fn entries() -> Vec<TransactionEntry> {
+        vec![
+            TransactionEntry {
+                txn_id: Uuid::parse_str("3f1e7a52-9c4b-4d2e-8f6a-1b2c3d4e5f60").unwrap(),
+                amount: 25.0,
+                from_username: "bob".to_string(),
+                to_username: "alice".to_string(),
+                time: Utc.with_ymd_and_hms(2024, 5, 20, 18, 45, 10).unwrap(),
+                posted_at: Utc.with_ymd_and_hms(2024, 5, 20, 18, 45, 10).unwrap(),
+                kind: TransferKind::Request,
+                fee: 0.0,
+                direction: TransferDirection::Incoming,
+                counterparty_username: "bob".to_string(),
+                counterparty_name: "Bob \"Bobby\" O'Neil & Sons <Ltd>, Leeds".to_string(),
+                signed_amount: 25.0,
+                balance_after: 955.5,
+                memo: Some("=SUM(A1:A9)\nline two".to_string()),
+                category: None,
+                tags: Vec::new(),
+            },
+            TransactionEntry {
+                txn_id: Uuid::parse_str("9a7b5c3d-1e2f-4a6b-8c0d-2e4f6a8b0c1d").unwrap(),
+                amount: 70.0,
+                from_username: "alice".to_string(),
+                to_username: "carol".to_string(),
+                time: Utc.with_ymd_and_hms(2024, 5, 3, 9, 5, 0).unwrap(),
+                posted_at: Utc.with_ymd_and_hms(2024, 5, 3, 9, 5, 0).unwrap(),
+                kind: TransferKind::P2p,
+                fee: 0.5,
+                direction: TransferDirection::Outgoing,
+                counterparty_username: "carol".to_string(),
+                counterparty_name: "Carol, Esq.".to_string(),
+                signed_amount: -70.5,
+                balance_after: 930.5,
+                memo: Some("Rent, May".to_string()),
+                category: Some("housing".to_string()),
+                tags: vec!["rent".to_string(), "monthly".to_string()],
+            },
+        ]
+    }
+
+    fn render(format: ExportFormat) -> String {
+        let ctx = context();
+        let mut out = header(format, &ctx);
+        for entry in entries() {
+            write_entry(format, &entry, &mut out);
+        }
+        out.push_str(&footer(format, &ctx));
+        out
+    }
+
+    #[test]
+    fn test_csv_matches_golden_file() {
+        assert_eq!(
+            render(ExportFormat::Csv),
+            include_str!("../../tests/golden/transactions.csv")
+        );
+    }
+
+    #[test]
+    fn test_ofx_matches_golden_file() {
+        assert_eq!(
+            render(ExportFormat::Ofx),
+            include_str!("../../tests/golden/transactions.ofx")
+        );
+    }
+
+    #[test]
+    fn test_qif_matches_golden_file() {
+        assert_eq!(
+            render(ExportFormat::Qif),
+            include_str!("../../tests/golden/transactions.qif")
+        );
+    }
+}
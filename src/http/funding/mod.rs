@@ -0,0 +1,368 @@
+//! Deposits and withdrawals through an external payment provider. A
+//! provider is told about a transfer when it is created and reports how it
+//! ended later, through a callback signed with a shared secret.
+
+pub mod simulated;
+
+use crate::http::db::model::{FundingStatus, FundingTransfer, TransferKind};
+use crate::http::db::queries::merchants::{self, SettlementDue};
+use crate::http::db::queries::{self, fees, funding};
+use crate::http::errors::{ApiError, Result};
+use futures::future::BoxFuture;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, crypto};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+/// Carries the signature of a provider callback's body.
+pub const SIGNATURE_HEADER: &str = "X-Payfree-Signature";
+/// Merchant settlements smaller than this are skipped until the next period.
+pub const MIN_SETTLEMENT_AMOUNT: f64 = 1.0;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BankAccount {
+    pub account_number: String,
+    pub bank_code: String,
+    pub holder_name: String,
+}
+
+impl BankAccount {
+    pub fn validate(&self) -> Result<()> {
+        let number = self.account_number.trim();
+        if !(4..=34).contains(&number.len()) || !number.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ApiError::Validation(
+                "account_number must be 4 to 34 letters or digits".to_string(),
+            ));
+        }
+        if self.bank_code.trim().is_empty() || self.holder_name.trim().is_empty() {
+            return Err(ApiError::Validation(
+                "bank_code and holder_name are required".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The account as stored and shown: only its last four characters.
+    pub fn masked(&self) -> String {
+        let number = self.account_number.trim();
+        format!("****{}", &number[number.len().saturating_sub(4)..])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FundingOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// The body of a provider callback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FundingEvent {
+    pub provider_ref: String,
+    pub outcome: FundingOutcome,
+    pub reason: Option<String>,
+}
+
+/// A payment provider that collects deposits and pays out withdrawals.
+/// Implementations only talk to the provider; the ledger is updated here.
+pub trait FundingProvider: Send + Sync {
+    /// Identifies the provider in stored transfers and callback URLs.
+    fn name(&self) -> &'static str;
+
+    /// Asks the provider to collect a deposit. Returns its reference.
+    fn start_deposit<'a>(&'a self, funding: &'a FundingTransfer) -> BoxFuture<'a, Result<String>>;
+
+    /// Asks the provider to pay a withdrawal to `account`. Returns its
+    /// reference.
+    fn start_withdrawal<'a>(
+        &'a self,
+        funding: &'a FundingTransfer,
+        account: &'a BankAccount,
+    ) -> BoxFuture<'a, Result<String>>;
+
+    /// Checks a callback's signature and parses its body.
+    fn verify_callback(&self, body: &[u8], signature: &str) -> Result<FundingEvent>;
+}
+
+/// Signs a callback body with HMAC-SHA256, base64url encoded.
+pub fn sign_callback(body: &[u8], secret: &str) -> Result<String> {
+    crypto::sign(
+        body,
+        &EncodingKey::from_secret(secret.as_ref()),
+        Algorithm::HS256,
+    )
+    .map_err(|e| {
+        error!("Failed to sign funding callback: {:?}", e);
+        ApiError::InternalServerError
+    })
+}
+
+pub fn verify_signature(body: &[u8], signature: &str, secret: &str) -> bool {
+    crypto::verify(
+        signature,
+        body,
+        &DecodingKey::from_secret(secret.as_ref()),
+        Algorithm::HS256,
+    )
+    .unwrap_or(false)
+}
+
+/// Creates a pending deposit and hands it to the provider.
+pub async fn request_deposit(
+    pool: &PgPool,
+    provider: &dyn FundingProvider,
+    username: &str,
+    amount: f64,
+) -> Result<FundingTransfer> {
+    let pending = funding::create_deposit(pool, username, amount, provider.name()).await?;
+    match provider.start_deposit(&pending).await {
+        Ok(provider_ref) => {
+            funding::set_provider_ref(pool, pending.funding_id, &provider_ref).await
+        }
+        Err(e) => {
+            warn!(
+                "{} refused deposit {}: {}",
+                provider.name(),
+                pending.funding_id,
+                e
+            );
+            funding::fail_unstarted(pool, pending.funding_id, "provider refused the deposit")
+                .await?;
+            Err(e)
+        }
+    }
+}
+
+/// Takes the money out of the account and hands the withdrawal to the
+/// provider. If the provider refuses it, the money goes straight back.
+pub async fn request_withdrawal(
+    pool: &PgPool,
+    provider: &dyn FundingProvider,
+    username: &str,
+    amount: f64,
+    account: &BankAccount,
+) -> Result<FundingTransfer> {
+    account.validate()?;
+    let pending =
+        funding::create_withdrawal(pool, username, amount, provider.name(), &account.masked())
+            .await?;
+    match provider.start_withdrawal(&pending, account).await {
+        Ok(provider_ref) => {
+            funding::set_provider_ref(pool, pending.funding_id, &provider_ref).await
+        }
+        Err(e) => {
+            warn!(
+                "{} refused withdrawal {}: {}",
+                provider.name(),
+                pending.funding_id,
+                e
+            );
+            funding::fail_unstarted(pool, pending.funding_id, "provider refused the withdrawal")
+                .await?;
+            Err(e)
+        }
+    }
+}
+
+/// Verifies a provider callback and settles the transfer it refers to.
+pub async fn handle_callback(
+    pool: &PgPool,
+    provider: &dyn FundingProvider,
+    body: &[u8],
+    signature: &str,
+) -> Result<FundingTransfer> {
+    let event = provider.verify_callback(body, signature)?;
+    debug!("{} callback: {:?}", provider.name(), event);
+    let status = match event.outcome {
+        FundingOutcome::Succeeded => FundingStatus::Completed,
+        FundingOutcome::Failed => FundingStatus::Failed,
+    };
+    funding::apply_outcome(
+        pool,
+        provider.name(),
+        &event.provider_ref,
+        status,
+        event.reason.as_deref(),
+    )
+    .await
+}
+
+/// Pays a merchant's available balance above their reserve out to their
+/// settlement account, leaving enough for the withdrawal fee, and starts
+/// their next settlement period. Returns `None` when there was too little
+/// to settle.
+pub async fn settle_merchant(
+    pool: &PgPool,
+    provider: &dyn FundingProvider,
+    due: &SettlementDue,
+) -> Result<Option<FundingTransfer>> {
+    let available = queries::fetch_balance(pool, &due.username)
+        .await?
+        .ok_or(ApiError::UserNotFound)?
+        .available_balance;
+    let spendable = available - due.reserve;
+    let mut settlement = None;
+    if spendable >= MIN_SETTLEMENT_AMOUNT {
+        let fee = fees::quote(pool, &due.username, TransferKind::Withdrawal, spendable)
+            .await?
+            .fee;
+        let amount = ((spendable - fee) * 100.0).floor() / 100.0;
+        if amount >= MIN_SETTLEMENT_AMOUNT {
+            settlement = Some(
+                request_withdrawal(pool, provider, &due.username, amount, &due.account).await?,
+            );
+        }
+    }
+    merchants::mark_settled(pool, &due.username).await?;
+    Ok(settlement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simulated::SimulatedProvider;
+    use super::*;
+    use crate::http::db::model::{FundingDirection, SettlementSchedule};
+    use crate::http::db::queries::fetch_balance;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db};
+    use std::time::Duration;
+
+    fn provider() -> SimulatedProvider {
+        SimulatedProvider::new("test_funding_secret", Duration::ZERO)
+    }
+
+    async fn ledger_balance(pool: &PgPool, username: &str) -> f64 {
+        fetch_balance(pool, username)
+            .await
+            .unwrap()
+            .unwrap()
+            .ledger_balance
+    }
+
+    #[tokio::test]
+    async fn test_deposit_is_credited_once_by_a_signed_callback() {
+        let pool = setup_test_db().await;
+        let provider = provider();
+        let user = create_test_user(&pool, "depositor", 0.0).await;
+        let deposit = request_deposit(&pool, &provider, &user.username, 40.0)
+            .await
+            .unwrap();
+        assert_eq!(deposit.status, FundingStatus::Pending);
+        assert_eq!(ledger_balance(&pool, &user.username).await, 0.0);
+
+        let (body, signature) = provider.callback(&deposit).unwrap();
+        let forged = sign_callback(&body, "not_the_secret").unwrap();
+        assert!(matches!(
+            handle_callback(&pool, &provider, &body, &forged).await,
+            Err(ApiError::Unauthorized)
+        ));
+
+        let settled = handle_callback(&pool, &provider, &body, &signature)
+            .await
+            .unwrap();
+        assert_eq!(settled.status, FundingStatus::Completed);
+        assert!(settled.txn_id.is_some());
+        handle_callback(&pool, &provider, &body, &signature)
+            .await
+            .unwrap();
+        assert_eq!(ledger_balance(&pool, &user.username).await, 40.0);
+    }
+
+    #[tokio::test]
+    async fn test_deposit_to_a_frozen_account_is_refused() {
+        let pool = setup_test_db().await;
+        let provider = provider();
+        let user = create_test_user(&pool, "frozen_depositor", 0.0).await;
+        let deposit = request_deposit(&pool, &provider, &user.username, 40.0)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE users SET status = 'frozen' WHERE userid = $1")
+            .bind(user.userid)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let (body, signature) = provider.callback(&deposit).unwrap();
+        let settled = handle_callback(&pool, &provider, &body, &signature)
+            .await
+            .unwrap();
+        assert_eq!(settled.status, FundingStatus::Failed);
+        assert!(settled.txn_id.is_none());
+        assert_eq!(ledger_balance(&pool, &user.username).await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_withdrawal_returns_the_money() {
+        let pool = setup_test_db().await;
+        let provider = provider();
+        let user = create_test_user(&pool, "withdrawer", 100.0).await;
+        let account = BankAccount {
+            account_number: "GB000000000000".to_string(),
+            bank_code: "TESTBANK".to_string(),
+            holder_name: "Test Withdrawer".to_string(),
+        };
+        let withdrawal = request_withdrawal(&pool, &provider, &user.username, 60.0, &account)
+            .await
+            .unwrap();
+        assert_eq!(withdrawal.direction, FundingDirection::Withdrawal);
+        assert_eq!(withdrawal.destination.as_deref(), Some("****0000"));
+        assert_eq!(ledger_balance(&pool, &user.username).await, 40.0);
+        assert!(matches!(
+            request_withdrawal(&pool, &provider, &user.username, 60.0, &account).await,
+            Err(ApiError::BalanceLow)
+        ));
+
+        let (body, signature) = provider.callback(&withdrawal).unwrap();
+        let settled = handle_callback(&pool, &provider, &body, &signature)
+            .await
+            .unwrap();
+        assert_eq!(settled.status, FundingStatus::Failed);
+        assert!(settled.return_txn_id.is_some());
+        assert_eq!(ledger_balance(&pool, &user.username).await, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_merchant_settlement_keeps_the_reserve() {
+        let pool = setup_test_db().await;
+        let provider = provider();
+        let merchant = create_test_user(&pool, "settler", 100.0).await;
+        let account = BankAccount {
+            account_number: "GB29NWBK60161331926819".to_string(),
+            bank_code: "NWBK".to_string(),
+            holder_name: "Settler Ltd".to_string(),
+        };
+        merchants::save_merchant(
+            &pool,
+            &merchant.username,
+            "Settler",
+            SettlementSchedule::Daily,
+            20.0,
+            Some(&account),
+        )
+        .await
+        .unwrap();
+        let due = SettlementDue {
+            username: merchant.username.clone(),
+            reserve: 20.0,
+            account,
+        };
+
+        let settlement = settle_merchant(&pool, &provider, &due)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(settlement.amount, 80.0);
+        assert_eq!(settlement.destination.as_deref(), Some("****6819"));
+        assert_eq!(ledger_balance(&pool, &merchant.username).await, 20.0);
+        let profile = merchants::fetch_merchant(&pool, &merchant.username)
+            .await
+            .unwrap();
+        assert!(profile.last_settled_at.is_some());
+        assert!(
+            settle_merchant(&pool, &provider, &due)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
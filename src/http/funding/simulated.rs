@@ -0,0 +1,98 @@
+//! A provider that stands in for a real one in development and tests. It
+//! accepts every transfer and, once `settle_after` has passed, reports the
+//! outcome through the same signed callback a real provider would send.
+
+use super::{
+    BankAccount, FundingEvent, FundingOutcome, FundingProvider, sign_callback, verify_signature,
+};
+use crate::http::db::model::{FundingDirection, FundingTransfer};
+use crate::http::errors::{ApiError, Result};
+use futures::future::BoxFuture;
+use log::{debug, warn};
+use std::time::Duration;
+
+pub const SIMULATED_PROVIDER: &str = "simulated";
+/// Withdrawals to accounts ending in this fail, so failures can be tried out.
+pub const FAILING_ACCOUNT_SUFFIX: &str = "0000";
+
+pub struct SimulatedProvider {
+    secret: String,
+    settle_after: Duration,
+}
+
+impl SimulatedProvider {
+    pub fn new(secret: impl Into<String>, settle_after: Duration) -> Self {
+        Self {
+            secret: secret.into(),
+            settle_after,
+        }
+    }
+
+    /// How long a transfer stays pending before the simulated bank settles it.
+    pub fn settle_after(&self) -> Duration {
+        self.settle_after
+    }
+
+    /// How the simulated bank settles a transfer: deposits always arrive and
+    /// withdrawals fail only for accounts ending in
+    /// [`FAILING_ACCOUNT_SUFFIX`].
+    pub fn outcome(&self, funding: &FundingTransfer) -> FundingEvent {
+        let bounced = funding.direction == FundingDirection::Withdrawal
+            && funding
+                .destination
+                .as_deref()
+                .is_some_and(|destination| destination.ends_with(FAILING_ACCOUNT_SUFFIX));
+        FundingEvent {
+            provider_ref: funding.provider_ref.clone().unwrap_or_default(),
+            outcome: if bounced {
+                FundingOutcome::Failed
+            } else {
+                FundingOutcome::Succeeded
+            },
+            reason: bounced.then(|| "account closed".to_string()),
+        }
+    }
+
+    /// The signed callback body the simulated bank sends for a transfer.
+    pub fn callback(&self, funding: &FundingTransfer) -> Result<(Vec<u8>, String)> {
+        let body = serde_json::to_vec(&self.outcome(funding)).map_err(|e| {
+            warn!("Failed to encode simulated callback: {:?}", e);
+            ApiError::InternalServerError
+        })?;
+        let signature = sign_callback(&body, &self.secret)?;
+        Ok((body, signature))
+    }
+}
+
+impl FundingProvider for SimulatedProvider {
+    fn name(&self) -> &'static str {
+        SIMULATED_PROVIDER
+    }
+
+    fn start_deposit<'a>(&'a self, funding: &'a FundingTransfer) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            debug!("Simulating deposit {}", funding.funding_id);
+            Ok(format!("sim_dep_{}", funding.funding_id.simple()))
+        })
+    }
+
+    fn start_withdrawal<'a>(
+        &'a self,
+        funding: &'a FundingTransfer,
+        _account: &'a BankAccount,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            debug!("Simulating withdrawal {}", funding.funding_id);
+            Ok(format!("sim_wd_{}", funding.funding_id.simple()))
+        })
+    }
+
+    fn verify_callback(&self, body: &[u8], signature: &str) -> Result<FundingEvent> {
+        if !verify_signature(body, signature, &self.secret) {
+            warn!("Rejected simulated callback with a bad signature");
+            return Err(ApiError::Unauthorized);
+        }
+        serde_json::from_slice(body)
+            .map_err(|e| ApiError::Validation(format!("invalid callback body: {}", e)))
+    }
+}
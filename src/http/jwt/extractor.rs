@@ -1,55 +1,106 @@
+use crate::http::config::Config;
+use crate::http::db::model::AccountStatus;
+use crate::http::db::queries::accounts;
 use crate::http::jwt::decode_jwt;
-use actix_web::{Error, FromRequest, HttpRequest, dev::Payload};
-use futures::future::{Ready, ready};
+use actix_web::{Error, FromRequest, HttpRequest, dev::Payload, web};
+use futures::future::{LocalBoxFuture, ready};
 use log::debug;
-use std::env;
+use sqlx::PgPool;
 
 pub struct AuthenticatedUser {
     pub username: String,
+    pub is_admin: bool,
 }
 
 impl FromRequest for AuthenticatedUser {
     type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        let secret = match env::var("JWT_SECRET") {
-            Ok(s) => s,
-            Err(_) => {
-                log::error!("JWT secret not set in environment");
-                return ready(Err(actix_web::error::ErrorInternalServerError(
-                    "JWT secret not set",
-                )));
+        let Some(config) = req.app_data::<web::Data<Config>>() else {
+            log::error!("Config not registered for JWT authentication");
+            return Box::pin(ready(Err(actix_web::error::ErrorInternalServerError(
+                "Config not loaded",
+            ))));
+        };
+        let secret = config.auth.jwt_secret.expose();
+
+        let username = if let Some(auth_header) = req.headers().get("Authorization")
+            && let Ok(auth_str) = auth_header.to_str()
+            && let Some(token) = auth_str.strip_prefix("Bearer ")
+        {
+            debug!("Attempting to decode JWT for incoming request");
+            match decode_jwt(token, secret) {
+                Ok(token_data) => {
+                    debug!(
+                        "JWT successfully decoded for user: {}",
+                        token_data.claims.sub
+                    );
+                    token_data.claims.sub
+                }
+                Err(e) => {
+                    log::warn!("Invalid JWT token: {:?}", e);
+                    return Box::pin(ready(Err(actix_web::error::ErrorUnauthorized(
+                        "Invalid JWT token",
+                    ))));
+                }
             }
+        } else {
+            debug!("Missing or malformed Authorization header for JWT authentication");
+            return Box::pin(ready(Err(actix_web::error::ErrorUnauthorized(
+                "Missing or malformed Authorization header",
+            ))));
         };
 
-        if let Some(auth_header) = req.headers().get("Authorization") {
-            if let Ok(auth_str) = auth_header.to_str() {
-                if let Some(token) = auth_str.strip_prefix("Bearer ") {
-                    debug!("Attempting to decode JWT for incoming request");
-                    match decode_jwt(token, &secret) {
-                        Ok(token_data) => {
-                            debug!(
-                                "JWT successfully decoded for user: {}",
-                                token_data.claims.sub
-                            );
-                            return ready(Ok(AuthenticatedUser {
-                                username: token_data.claims.sub,
-                            }));
-                        }
-                        Err(e) => {
-                            log::warn!("Invalid JWT token: {:?}", e);
-                            return ready(Err(actix_web::error::ErrorUnauthorized(
-                                "Invalid JWT token",
-                            )));
-                        }
-                    }
+        let Some(pool) = req.app_data::<web::Data<PgPool>>().cloned() else {
+            log::error!("Database pool not configured for JWT authentication");
+            return Box::pin(ready(Err(actix_web::error::ErrorInternalServerError(
+                "Database not configured",
+            ))));
+        };
+
+        Box::pin(async move {
+            let access = accounts::fetch_access(&pool, &username)
+                .await
+                .map_err(|e| {
+                    log::error!("Failed to load account status for {}: {:?}", username, e);
+                    actix_web::error::ErrorInternalServerError("Failed to load account")
+                })?;
+            match access {
+                Some((AccountStatus::Closed, _)) => {
+                    log::warn!("Rejected request from closed account: {}", username);
+                    Err(actix_web::error::ErrorUnauthorized("Account is closed"))
                 }
+                Some((_, is_admin)) => Ok(AuthenticatedUser { username, is_admin }),
+                None => {
+                    log::warn!("Rejected token for unknown account: {}", username);
+                    Err(actix_web::error::ErrorUnauthorized("Account not found"))
+                }
+            }
+        })
+    }
+}
+
+/// An authenticated user whose account carries the admin flag.
+pub struct AdminUser {
+    pub username: String,
+}
+
+impl FromRequest for AdminUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthenticatedUser::from_request(req, payload);
+        Box::pin(async move {
+            let user = user.await?;
+            if !user.is_admin {
+                log::warn!("Non-admin {} attempted an admin action", user.username);
+                return Err(actix_web::error::ErrorForbidden("Admin access required"));
             }
-        }
-        debug!("Missing or malformed Authorization header for JWT authentication");
-        ready(Err(actix_web::error::ErrorUnauthorized(
-            "Missing or malformed Authorization header",
-        )))
+            Ok(AdminUser {
+                username: user.username,
+            })
+        })
     }
 }
@@ -0,0 +1,73 @@
+//! Signed payment-link tokens. They use the same secret as login tokens but
+//! carry their own audience and claims, so neither can stand in for the other.
+
+use jsonwebtoken::{
+    DecodingKey, EncodingKey, Header, Validation, decode, encode, errors::Error as JwtError,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const LINK_AUDIENCE: &str = "payment_link";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkClaims {
+    pub aud: String,
+    pub link_id: Uuid,
+    pub recipient: String,
+    pub amount: Option<f64>,
+    pub memo: Option<String>,
+    pub exp: usize,
+}
+
+pub fn sign_link(claims: &LinkClaims, secret: &str) -> Result<String, JwtError> {
+    debug!("Signing payment link {}", claims.link_id);
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    )
+}
+
+/// Checks the signature, audience and expiry of a payment-link token.
+pub fn verify_link(token: &str, secret: &str) -> Result<LinkClaims, JwtError> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[LINK_AUDIENCE]);
+    decode::<LinkClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &validation,
+    )
+    .map(|data| data.claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::jwt::{decode_jwt, generate_jwt};
+
+    fn claims() -> LinkClaims {
+        LinkClaims {
+            aud: LINK_AUDIENCE.to_string(),
+            link_id: Uuid::new_v4(),
+            recipient: "stall_42".to_string(),
+            amount: Some(4.5),
+            memo: Some("Coffee".to_string()),
+            exp: (chrono::Utc::now().timestamp() + 60) as usize,
+        }
+    }
+
+    #[test]
+    fn test_link_tokens_are_not_login_tokens() {
+        let token = sign_link(&claims(), "test_secret").unwrap();
+        assert_eq!(
+            verify_link(&token, "test_secret").unwrap().amount,
+            Some(4.5)
+        );
+        assert!(verify_link(&token, "wrong_secret").is_err());
+        assert!(decode_jwt(&token, "test_secret").is_err());
+
+        let login = generate_jwt("stall_42", "test_secret", 60).unwrap();
+        assert!(verify_link(&login, "test_secret").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 pub mod extractor;
+pub mod links;
 
 use serde::{Serialize, Deserialize};
 use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey, TokenData, errors::Error as JwtError};
@@ -3,3 +3,8 @@ pub mod errors;
 pub mod passwd;
 pub mod routes;
 pub mod jwt;
+pub mod workers;
+pub mod policy;
+pub mod export;
+pub mod funding;
+pub mod config;
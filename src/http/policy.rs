@@ -0,0 +1,189 @@
+//! Who may read or act on what. Handlers ask here instead of comparing
+//! usernames themselves, so every resource follows the same rules.
+
+use crate::http::db::model::Transaction;
+use crate::http::db::queries::{self, delegations};
+use crate::http::errors::{ApiError, Result};
+use crate::http::jwt::extractor::AuthenticatedUser;
+use log::{debug, warn};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Why a user may view a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewAccess {
+    /// The user owns the resource or took part in it.
+    Participant,
+    Admin,
+    /// One of the owners delegated read access to the user.
+    DelegatedViewer,
+}
+
+/// Lets only `owner` themselves through, for resources that admins and
+/// delegated viewers may not touch either.
+pub fn ensure_owner(user: &AuthenticatedUser, owner: &str) -> Result<()> {
+    if user.username != owner {
+        warn!(
+            "Unauthorized access attempt by {} to resources of {}",
+            user.username, owner
+        );
+        return Err(ApiError::Unauthorized);
+    }
+    Ok(())
+}
+
+/// Decides whether `user` may view a resource owned by `owners`. Returns
+/// `None` when they may not.
+pub async fn view_access(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    owners: &[&str],
+) -> Result<Option<ViewAccess>> {
+    if owners.contains(&user.username.as_str()) {
+        return Ok(Some(ViewAccess::Participant));
+    }
+    if user.is_admin {
+        return Ok(Some(ViewAccess::Admin));
+    }
+    if delegations::is_delegated_viewer(pool, &user.username, owners).await? {
+        return Ok(Some(ViewAccess::DelegatedViewer));
+    }
+    Ok(None)
+}
+
+/// Like [`view_access`], but fails with `Unauthorized` when access is denied.
+pub async fn authorize_view(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    owners: &[&str],
+) -> Result<ViewAccess> {
+    match view_access(pool, user, owners).await? {
+        Some(access) => Ok(access),
+        None => {
+            warn!(
+                "Unauthorized view attempt by {} on resources of {:?}",
+                user.username, owners
+            );
+            Err(ApiError::Unauthorized)
+        }
+    }
+}
+
+/// Fetches a transaction the user may view. Missing transactions and ones
+/// the user may not see both fail with `TransactionNotFound`, so ids cannot
+/// be probed for existence.
+pub async fn viewable_transaction(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    txn_id: Uuid,
+) -> Result<Transaction> {
+    let Some(txn) = queries::fetch_transaction(pool, txn_id).await? else {
+        debug!("Transaction not found for txn_id: {}", txn_id);
+        return Err(ApiError::TransactionNotFound);
+    };
+    let owners = [txn.from_username.as_str(), txn.to_username.as_str()];
+    match view_access(pool, user, &owners).await? {
+        Some(access) => {
+            debug!(
+                "{} may view transaction {} as {:?}",
+                user.username, txn_id, access
+            );
+            Ok(txn)
+        }
+        None => {
+            warn!(
+                "Hiding transaction {} from non-participant {}",
+                txn_id, user.username
+            );
+            Err(ApiError::TransactionNotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::queries::insert_transaction;
+    use crate::http::db::test_utils::{create_test_user, setup_test_db, transaction};
+
+    fn as_user(username: &str, is_admin: bool) -> AuthenticatedUser {
+        AuthenticatedUser {
+            username: username.to_string(),
+            is_admin,
+        }
+    }
+
+    #[test]
+    fn test_ensure_owner() {
+        assert!(ensure_owner(&as_user("alice", false), "alice").is_ok());
+        let res = ensure_owner(&as_user("admin", true), "alice");
+        assert!(matches!(res, Err(ApiError::Unauthorized)));
+    }
+
+    #[tokio::test]
+    async fn test_view_access() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 0.0).await;
+        let bob = create_test_user(&pool, "bob", 0.0).await;
+        let viewer = create_test_user(&pool, "viewer", 0.0).await;
+        let stranger = create_test_user(&pool, "stranger", 0.0).await;
+        delegations::grant_viewer(&pool, &alice.username, &viewer.username, None)
+            .await
+            .unwrap();
+        let owners = [alice.username.as_str(), bob.username.as_str()];
+
+        let access = view_access(&pool, &as_user(&bob.username, false), &owners).await;
+        assert_eq!(access.unwrap(), Some(ViewAccess::Participant));
+        let access = view_access(&pool, &as_user(&stranger.username, true), &owners).await;
+        assert_eq!(access.unwrap(), Some(ViewAccess::Admin));
+        let access = view_access(&pool, &as_user(&viewer.username, false), &owners).await;
+        assert_eq!(access.unwrap(), Some(ViewAccess::DelegatedViewer));
+        let access = view_access(&pool, &as_user(&stranger.username, false), &owners).await;
+        assert_eq!(access.unwrap(), None);
+        let res = authorize_view(&pool, &as_user(&stranger.username, false), &owners).await;
+        assert!(matches!(res, Err(ApiError::Unauthorized)));
+
+        sqlx::query(
+            "UPDATE viewer_delegations SET expires_at = NOW() - interval '1 minute' WHERE owner_userid = $1",
+        )
+        .bind(alice.userid)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let access = view_access(&pool, &as_user(&viewer.username, false), &owners).await;
+        assert_eq!(access.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_viewable_transaction_hides_from_strangers() {
+        let pool = setup_test_db().await;
+        let alice = create_test_user(&pool, "alice", 100.0).await;
+        let bob = create_test_user(&pool, "bob", 100.0).await;
+        let viewer = create_test_user(&pool, "viewer", 0.0).await;
+        let stranger = create_test_user(&pool, "stranger", 0.0).await;
+        delegations::grant_viewer(&pool, &bob.username, &viewer.username, None)
+            .await
+            .unwrap();
+        let txn = transaction(&alice.username, &bob.username, 10.0);
+        insert_transaction(&pool, &txn).await.unwrap();
+
+        for user in [
+            as_user(&alice.username, false),
+            as_user(&bob.username, false),
+            as_user(&viewer.username, false),
+            as_user(&stranger.username, true),
+        ] {
+            let found = viewable_transaction(&pool, &user, txn.txn_id)
+                .await
+                .unwrap();
+            assert_eq!(found.txn_id, txn.txn_id);
+        }
+
+        let res =
+            viewable_transaction(&pool, &as_user(&stranger.username, false), txn.txn_id).await;
+        assert!(matches!(res, Err(ApiError::TransactionNotFound)));
+        let res =
+            viewable_transaction(&pool, &as_user(&alice.username, false), Uuid::new_v4()).await;
+        assert!(matches!(res, Err(ApiError::TransactionNotFound)));
+    }
+}
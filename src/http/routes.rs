@@ -2,24 +2,48 @@ use crate::http::db::model;
 use crate::http::db::queries;
 use crate::http::errors::ApiError;
 use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
 use actix_web::{HttpResponse, Responder, get, post, web};
+use chrono::{DateTime, Utc};
 use log::{debug, error, warn};
 use serde::Deserialize;
 use sqlx::PgPool;
 use uuid::Uuid;
 
+pub mod accounts;
+pub mod annotations;
+pub mod batches;
+pub mod delegations;
+pub mod discovery;
+pub mod disputes;
+pub mod export;
+pub mod fees;
+pub mod funding;
+pub mod groups;
+pub mod holds;
+pub mod interest;
+pub mod invoices;
+pub mod kyc;
+pub mod limits;
+pub mod links;
+pub mod merchants;
+pub mod payees;
+pub mod requests;
+pub mod schedules;
+pub mod statements;
+pub mod usernames;
+
 #[get("/")]
 pub async fn hello() -> impl Responder {
     debug!("Received request: GET /");
     HttpResponse::Ok().body("Hi Raghav!")
 }
 
+use crate::http::config::Config;
 use crate::http::db::model::User;
 use crate::http::jwt::generate_jwt;
 use crate::http::passwd;
 
-use std::env;
-
 #[derive(Deserialize)]
 pub struct SignupRequest {
     pub userid: Uuid,
@@ -27,17 +51,27 @@ pub struct SignupRequest {
     pub username: String,
     pub phno: String,
     pub address: String,
-    pub balance: f64,
     pub password: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub discoverable: bool,
 }
 
 #[post("/auth/signup")]
 pub async fn new_user(
     pool: web::Data<PgPool>,
+    config: web::Data<Config>,
     req: web::Json<SignupRequest>,
 ) -> Result<HttpResponse, ApiError> {
     debug!("POST /auth/signup called with username: {}", req.username);
     let req = req.into_inner();
+    usernames::validate_username(&req.username)?;
+    let phno = queries::discovery::normalize_phone(&req.phno)?;
+    let email = req
+        .email
+        .as_deref()
+        .map(queries::discovery::normalize_email)
+        .transpose()?;
     let password_hash = passwd::hash(req.password).await.map_err(|_| {
         error!("Password hashing failed for signup");
         ApiError::InternalServerError
@@ -46,15 +80,21 @@ pub async fn new_user(
         userid: req.userid,
         name: req.name,
         username: req.username.clone(),
-        phno: req.phno,
+        phno,
         address: req.address,
-        balance: req.balance,
+        balance: 0.0,
         password_hash,
+        email,
+        discoverable: req.discoverable,
     };
     queries::new_user(&pool, &user).await?;
     debug!("User created: {}", user.username);
-    let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "dev_secret".to_string());
-    let token = generate_jwt(&req.username, &secret, 3600).map_err(|_| {
+    let token = generate_jwt(
+        &req.username,
+        config.auth.jwt_secret.expose(),
+        config.auth.token_expiry_secs,
+    )
+    .map_err(|_| {
         error!("JWT generation failed for signup");
         ApiError::InternalServerError
     })?;
@@ -70,6 +110,7 @@ pub struct LoginRequest {
 #[post("/auth/login")]
 pub async fn login(
     pool: web::Data<PgPool>,
+    config: web::Data<Config>,
     req: web::Json<LoginRequest>,
 ) -> Result<HttpResponse, ApiError> {
     debug!("POST /auth/login called for username: {}", req.username);
@@ -91,8 +132,18 @@ pub async fn login(
         );
         return Err(ApiError::InvalidCredentials);
     }
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "dev_secret".to_string());
-    let token = generate_jwt(&user.username, &secret, 3600).map_err(|_| {
+    if let Some(state) = queries::accounts::fetch_account_state(&pool, &user.username).await?
+        && state.status == model::AccountStatus::Closed
+    {
+        warn!("Login rejected for closed account: {}", user.username);
+        return Err(ApiError::AccountClosed);
+    }
+    let token = generate_jwt(
+        &user.username,
+        config.auth.jwt_secret.expose(),
+        config.auth.token_expiry_secs,
+    )
+    .map_err(|_| {
         error!("JWT generation failed for login");
         ApiError::InternalServerError
     })?;
@@ -107,13 +158,7 @@ pub async fn profile(
 ) -> Result<HttpResponse, ApiError> {
     debug!("GET /users/{}/profile called by {}", path, user.username);
     let username = path.into_inner();
-    if username != user.username {
-        warn!(
-            "Unauthorized profile access attempt: {} tried to access {}",
-            user.username, username
-        );
-        return Err(ApiError::Unauthorized);
-    }
+    policy::ensure_owner(&user, &username)?;
     let user = queries::fetch_profile(&pool, &username)
         .await?
         .ok_or_else(|| {
@@ -127,20 +172,15 @@ pub async fn profile(
 pub async fn get_transactions(
     pool: web::Data<PgPool>,
     path: web::Path<String>,
+    filter: web::Query<model::TransactionFilter>,
     user: AuthenticatedUser,
 ) -> Result<HttpResponse, ApiError> {
     debug!("Received request: GET /users/{}/transactions", path);
     let username = path.into_inner();
-    if username != user.username {
-        warn!(
-            "Unauthorized transactions access attempt: {} as {}",
-            user.username, username
-        );
-        return Err(ApiError::Unauthorized);
-    }
-    let txns = queries::fetch_transactions(&pool, &username).await?;
+    policy::authorize_view(&pool, &user, &[&username]).await?;
+    let page = queries::fetch_transactions(&pool, &username, &filter).await?;
     debug!("Transactions fetched for username: {}", username);
-    Ok(HttpResponse::Ok().json(txns))
+    Ok(HttpResponse::Ok().json(page))
 }
 
 #[get("/users/{username}/balance")]
@@ -151,13 +191,7 @@ pub async fn check_balance(
 ) -> Result<HttpResponse, ApiError> {
     debug!("GET /users/{}/balance called by {}", path, user.username);
     let username = path.into_inner();
-    if username != user.username {
-        warn!(
-            "Unauthorized balance access attempt: {} as {}",
-            user.username, username
-        );
-        return Err(ApiError::Unauthorized);
-    }
+    policy::ensure_owner(&user, &username)?;
     let balance = queries::fetch_balance(&pool, &username)
         .await?
         .ok_or_else(|| {
@@ -168,21 +202,70 @@ pub async fn check_balance(
     Ok(HttpResponse::Ok().json(balance))
 }
 
+#[derive(Deserialize)]
+pub struct TransferRequest {
+    pub txn_id: Uuid,
+    pub amount: f64,
+    pub from_username: String,
+    pub to_username: Option<String>,
+    pub to_payee_id: Option<Uuid>,
+    pub to_alias: Option<String>,
+    pub time: DateTime<Utc>,
+    pub memo: Option<String>,
+}
+
+/// Turns the recipient fields of a transfer request into a username.
+/// Exactly one of them must be set.
+async fn resolve_recipient(
+    pool: &PgPool,
+    owner: &str,
+    req: &TransferRequest,
+) -> Result<String, ApiError> {
+    match (&req.to_username, req.to_payee_id, &req.to_alias) {
+        (Some(username), None, None) => Ok(username.clone()),
+        (None, Some(payee_id), None) => {
+            queries::payees::fetch_payee_username(pool, owner, payee_id)
+                .await?
+                .ok_or(ApiError::NotFound)
+        }
+        (None, None, Some(alias)) => {
+            let alias = queries::discovery::parse_alias(alias)?;
+            queries::discovery::resolve_alias(pool, owner, &alias)
+                .await?
+                .map(|recipient| recipient.username)
+                .ok_or(ApiError::UserNotFound)
+        }
+        _ => Err(ApiError::Validation(
+            "exactly one of to_username, to_payee_id or to_alias is required".to_string(),
+        )),
+    }
+}
+
 #[post("/transactions/new")]
 pub async fn new_transaction(
     pool: web::Data<PgPool>,
-    txn: web::Json<model::Transaction>,
+    req: web::Json<TransferRequest>,
     user: AuthenticatedUser,
 ) -> Result<HttpResponse, ApiError> {
     debug!("POST /transactions/new called by {}", user.username);
-    if txn.from_username != user.username {
-        warn!(
-            "Unauthorized transaction attempt: {} tried to send from {}",
-            user.username, txn.from_username
-        );
-        return Err(ApiError::Unauthorized);
-    }
-    match queries::insert_transaction(&pool, &txn.into_inner()).await {
+    policy::ensure_owner(&user, &req.from_username)?;
+    let req = req.into_inner();
+    let to_username = resolve_recipient(&pool, &user.username, &req).await?;
+    let memo = match req.memo.as_deref() {
+        Some(memo) => queries::annotations::sanitize_memo(memo)?,
+        None => None,
+    };
+    let txn = model::Transaction {
+        txn_id: req.txn_id,
+        amount: req.amount,
+        from_username: req.from_username,
+        to_username,
+        time: req.time,
+        memo,
+        kind: model::TransferKind::P2p,
+        fee: 0.0,
+    };
+    match queries::insert_transaction(&pool, &txn).await {
         Ok(_) => {
             debug!("Transaction inserted by {}", user.username);
             Ok(HttpResponse::Ok().body("Transaction inserted"))
@@ -202,16 +285,10 @@ pub async fn new_transaction(
 pub async fn get_transaction(
     pool: web::Data<PgPool>,
     path: web::Path<Uuid>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
 ) -> Result<HttpResponse, ApiError> {
-    debug!("GET /transactions/{} called", path);
-    let txn_id = path.into_inner();
-    let txn = queries::fetch_transaction(&pool, txn_id)
-        .await?
-        .ok_or_else(|| {
-            warn!("Transaction not found for txn_id: {}", txn_id);
-            ApiError::UserNotFound
-        })?;
+    debug!("GET /transactions/{} called by {}", path, user.username);
+    let txn = policy::viewable_transaction(&pool, &user, path.into_inner()).await?;
     Ok(HttpResponse::Ok().json(txn))
 }
 
@@ -223,13 +300,101 @@ pub fn init_routes(cfg: &mut actix_web::web::ServiceConfig) {
         .service(get_transactions)
         .service(check_balance)
         .service(new_transaction)
-        .service(get_transaction);
+        .service(get_transaction)
+        .service(usernames::rename_user)
+        .service(accounts::account_status)
+        .service(accounts::close_account)
+        .service(accounts::freeze_account)
+        .service(accounts::unfreeze_account)
+        .service(kyc::kyc_limits)
+        .service(kyc::submit_verification)
+        .service(kyc::list_verifications)
+        .service(kyc::approve_verification)
+        .service(kyc::reject_verification)
+        .service(payees::list_payees)
+        .service(payees::save_payee)
+        .service(payees::delete_payee)
+        .service(discovery::resolve_recipient)
+        .service(discovery::update_discovery)
+        .service(requests::create_request)
+        .service(requests::incoming_requests)
+        .service(requests::outgoing_requests)
+        .service(requests::approve_request)
+        .service(requests::decline_request)
+        .service(requests::cancel_request)
+        .service(schedules::create_schedule)
+        .service(schedules::list_schedules)
+        .service(schedules::list_runs)
+        .service(schedules::pause_schedule)
+        .service(schedules::resume_schedule)
+        .service(schedules::cancel_schedule)
+        .service(groups::create_group)
+        .service(groups::list_groups)
+        .service(groups::get_group)
+        .service(groups::add_member)
+        .service(groups::accept_invite)
+        .service(groups::decline_invite)
+        .service(groups::add_expense)
+        .service(groups::list_expenses)
+        .service(groups::group_balances)
+        .service(groups::settle_up)
+        .service(groups::settle_expense)
+        .service(groups::contest_expense)
+        .service(groups::withdraw_contest)
+        .service(batches::create_batch)
+        .service(batches::get_batch)
+        .service(holds::create_hold)
+        .service(holds::list_holds)
+        .service(holds::capture_hold)
+        .service(holds::void_hold)
+        .service(annotations::annotate_transaction)
+        .service(limits::get_limits)
+        .service(limits::set_limits)
+        .service(fees::quote_transfer)
+        .service(fees::list_fee_rules)
+        .service(fees::create_fee_rule)
+        .service(fees::deactivate_fee_rule)
+        .service(interest::list_products)
+        .service(interest::save_product)
+        .service(interest::interest_summary)
+        .service(interest::enroll)
+        .service(interest::unenroll)
+        .service(delegations::list_viewers)
+        .service(delegations::grant_viewer)
+        .service(delegations::revoke_viewer)
+        .service(links::create_link)
+        .service(links::list_links)
+        .service(links::revoke_link)
+        .service(links::link_details)
+        .service(links::link_qr)
+        .service(links::redeem_link)
+        .service(statements::list_statements)
+        .service(statements::get_statement)
+        .service(export::export_transactions)
+        .service(funding::list_funding)
+        .service(funding::deposit)
+        .service(funding::withdraw)
+        .service(funding::callback)
+        .service(disputes::open_dispute)
+        .service(disputes::list_disputes)
+        .service(disputes::get_dispute)
+        .service(disputes::add_evidence)
+        .service(disputes::list_all_disputes)
+        .service(disputes::review_dispute)
+        .service(disputes::resolve_dispute)
+        .service(merchants::get_merchant)
+        .service(merchants::save_merchant)
+        .service(invoices::create_invoice)
+        .service(invoices::list_invoices)
+        .service(invoices::get_invoice)
+        .service(invoices::pay_invoice)
+        .service(invoices::void_invoice);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use actix_web::{test, web, App};
+    use actix_web::{App, test, web};
     use serde_json::json;
     use sqlx::postgres::PgPoolOptions;
     use std::env;
@@ -260,20 +425,21 @@ mod tests {
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(crate::http::db::test_utils::test_config()))
                 .service(new_user)
-                .service(login)
-        ).await;
+                .service(login),
+        )
+        .await;
 
-        let username = format!("testuser_{}", Uuid::new_v4());
+        let username = format!("testuser_{}", &Uuid::new_v4().simple().to_string()[..12]);
         let signup_req = test::TestRequest::post()
             .uri("/auth/signup")
-            .set_json(&json!({
+            .set_json(json!({
                 "userid": Uuid::new_v4(),
                 "name": "Test User",
                 "username": username,
-                "phno": "1234567890",
+                "phno": crate::http::db::test_utils::phone_number(),
                 "address": "Test Address",
-                "balance": 100.0,
                 "password": "testpassword"
             }))
             .to_request();
@@ -282,9 +448,24 @@ mod tests {
         let body: serde_json::Value = test::read_body_json(signup_resp).await;
         assert!(body.get("token").is_some());
 
+        // Signup applies the same username rules as rename.
+        let bad_req = test::TestRequest::post()
+            .uri("/auth/signup")
+            .set_json(json!({
+                "userid": Uuid::new_v4(),
+                "name": "Test User",
+                "username": "bad name",
+                "phno": crate::http::db::test_utils::phone_number(),
+                "address": "Test Address",
+                "password": "testpassword"
+            }))
+            .to_request();
+        let bad_resp = test::call_service(&app, bad_req).await;
+        assert_eq!(bad_resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
         let login_req = test::TestRequest::post()
             .uri("/auth/login")
-            .set_json(&json!({
+            .set_json(json!({
                 "username": username,
                 "password": "testpassword"
             }))
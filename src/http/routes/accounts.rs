@@ -0,0 +1,101 @@
+use crate::http::db::model::AccountStatus;
+use crate::http::db::queries::accounts;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::{AdminUser, AuthenticatedUser};
+use crate::http::policy;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[get("/users/{username}/status")]
+pub async fn account_status(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/status called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let state = accounts::fetch_account_state(&pool, &username)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    Ok(HttpResponse::Ok().json(state))
+}
+
+#[derive(Deserialize)]
+pub struct CloseAccountRequest {
+    pub reason: Option<String>,
+    pub payout_to: Option<String>,
+}
+
+#[post("/users/{username}/close")]
+pub async fn close_account(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<CloseAccountRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/close called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let req = req.into_inner();
+    accounts::close_account(
+        &pool,
+        &username,
+        req.reason.as_deref(),
+        req.payout_to.as_deref(),
+    )
+    .await?;
+    debug!("Account closed: {}", username);
+    Ok(HttpResponse::Ok().body("Account closed"))
+}
+
+#[derive(Deserialize)]
+pub struct StatusChangeRequest {
+    pub reason: Option<String>,
+}
+
+#[post("/admin/users/{username}/freeze")]
+pub async fn freeze_account(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<StatusChangeRequest>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /admin/users/{}/freeze called by {}",
+        path, admin.username
+    );
+    let username = path.into_inner();
+    accounts::set_account_status(
+        &pool,
+        &username,
+        AccountStatus::Frozen,
+        req.reason.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().body("Account frozen"))
+}
+
+#[post("/admin/users/{username}/unfreeze")]
+pub async fn unfreeze_account(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<StatusChangeRequest>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /admin/users/{}/unfreeze called by {}",
+        path, admin.username
+    );
+    let username = path.into_inner();
+    accounts::set_account_status(
+        &pool,
+        &username,
+        AccountStatus::Active,
+        req.reason.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().body("Account unfrozen"))
+}
@@ -0,0 +1,40 @@
+use crate::http::db::queries::annotations;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpResponse, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct AnnotationRequest {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub category: Option<String>,
+}
+
+#[post("/users/{username}/transactions/{txn_id}/annotations")]
+pub async fn annotate_transaction(
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, Uuid)>,
+    req: web::Json<AnnotationRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (username, txn_id) = path.into_inner();
+    debug!(
+        "POST /users/{}/transactions/{}/annotations called by {}",
+        username, txn_id, user.username
+    );
+    policy::ensure_owner(&user, &username)?;
+    let annotation = annotations::annotate_transaction(
+        &pool,
+        &username,
+        txn_id,
+        &req.tags,
+        req.category.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(annotation))
+}
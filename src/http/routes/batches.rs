@@ -0,0 +1,34 @@
+use crate::http::db::model::NewBatch;
+use crate::http::db::queries::batches;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[post("/transfers/batch")]
+pub async fn create_batch(
+    pool: web::Data<PgPool>,
+    req: web::Json<NewBatch>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /transfers/batch called by {} with {} items",
+        user.username,
+        req.items.len()
+    );
+    let batch = batches::run_batch(&pool, &user.username, &req).await?;
+    Ok(HttpResponse::Ok().json(batch))
+}
+
+#[get("/transfers/batch/{batch_id}")]
+pub async fn get_batch(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /transfers/batch/{} called by {}", path, user.username);
+    let batch = batches::fetch_batch(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(batch))
+}
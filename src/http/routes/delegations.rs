@@ -0,0 +1,60 @@
+use crate::http::db::queries::delegations;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpResponse, delete, get, post, web};
+use chrono::{DateTime, Utc};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[get("/users/{username}/viewers")]
+pub async fn list_viewers(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/viewers called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let viewers = delegations::list_viewers(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(viewers))
+}
+
+#[derive(Deserialize)]
+pub struct GrantViewerRequest {
+    pub username: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[post("/users/{username}/viewers")]
+pub async fn grant_viewer(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<GrantViewerRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/viewers called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    delegations::grant_viewer(&pool, &username, req.username.trim(), req.expires_at).await?;
+    let viewers = delegations::list_viewers(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(viewers))
+}
+
+#[delete("/users/{username}/viewers/{viewer}")]
+pub async fn revoke_viewer(
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, String)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (username, viewer) = path.into_inner();
+    debug!(
+        "DELETE /users/{}/viewers/{} called by {}",
+        username, viewer, user.username
+    );
+    policy::ensure_owner(&user, &username)?;
+    delegations::revoke_viewer(&pool, &username, &viewer).await?;
+    let viewers = delegations::list_viewers(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(viewers))
+}
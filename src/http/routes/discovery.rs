@@ -0,0 +1,52 @@
+use crate::http::db::queries::discovery;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize)]
+pub struct ResolveQuery {
+    pub alias: String,
+}
+
+#[get("/recipients/resolve")]
+pub async fn resolve_recipient(
+    pool: web::Data<PgPool>,
+    query: web::Query<ResolveQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /recipients/resolve called by {}", user.username);
+    let alias = discovery::parse_alias(&query.alias)?;
+    let recipient = discovery::resolve_alias(&pool, &user.username, &alias)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(HttpResponse::Ok().json(recipient))
+}
+
+#[derive(Deserialize)]
+pub struct DiscoveryRequest {
+    pub discoverable: bool,
+    pub email: Option<String>,
+}
+
+#[post("/users/{username}/discovery")]
+pub async fn update_discovery(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<DiscoveryRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/discovery called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let email = req
+        .email
+        .as_deref()
+        .map(discovery::normalize_email)
+        .transpose()?;
+    discovery::update_discovery(&pool, &username, req.discoverable, email.as_deref()).await?;
+    Ok(HttpResponse::Ok().body("Discovery settings updated"))
+}
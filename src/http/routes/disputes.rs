@@ -0,0 +1,162 @@
+use crate::http::db::model::{DisputeReason, DisputeStatus};
+use crate::http::db::queries::disputes;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::{AdminUser, AuthenticatedUser};
+use crate::http::policy;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct OpenDisputeRequest {
+    pub reason: DisputeReason,
+    pub description: Option<String>,
+}
+
+#[post("/transactions/{txn_id}/disputes")]
+pub async fn open_dispute(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<OpenDisputeRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /transactions/{}/disputes called by {}",
+        path, user.username
+    );
+    let dispute = disputes::open_dispute(
+        &pool,
+        &user.username,
+        path.into_inner(),
+        req.reason,
+        req.description.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(dispute))
+}
+
+#[get("/users/{username}/disputes")]
+pub async fn list_disputes(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/disputes called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::authorize_view(&pool, &user, &[username.as_str()]).await?;
+    let disputes = disputes::list_disputes(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(disputes))
+}
+
+#[get("/disputes/{dispute_id}")]
+pub async fn get_dispute(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /disputes/{} called by {}", path, user.username);
+    let dispute =
+        disputes::fetch_dispute(&pool, path.into_inner(), &user.username, user.is_admin).await?;
+    Ok(HttpResponse::Ok().json(dispute))
+}
+
+#[derive(Deserialize)]
+pub struct EvidenceRequest {
+    pub note: String,
+}
+
+#[post("/disputes/{dispute_id}/evidence")]
+pub async fn add_evidence(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<EvidenceRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /disputes/{}/evidence called by {}",
+        path, user.username
+    );
+    let dispute = disputes::add_evidence(
+        &pool,
+        path.into_inner(),
+        &user.username,
+        user.is_admin,
+        &req.note,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(dispute))
+}
+
+#[derive(Deserialize)]
+pub struct DisputeQueueQuery {
+    pub status: Option<DisputeStatus>,
+}
+
+#[get("/admin/disputes")]
+pub async fn list_all_disputes(
+    pool: web::Data<PgPool>,
+    query: web::Query<DisputeQueueQuery>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /admin/disputes called by {}", admin.username);
+    let disputes = disputes::list_all_disputes(&pool, query.status).await?;
+    Ok(HttpResponse::Ok().json(disputes))
+}
+
+#[derive(Deserialize)]
+pub struct ReviewDisputeRequest {
+    #[serde(default)]
+    pub freeze_funds: Option<bool>,
+}
+
+#[post("/admin/disputes/{dispute_id}/review")]
+pub async fn review_dispute(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<ReviewDisputeRequest>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /admin/disputes/{}/review called by {}",
+        path, admin.username
+    );
+    let dispute = disputes::review_dispute(&pool, path.into_inner(), req.freeze_funds).await?;
+    Ok(HttpResponse::Ok().json(dispute))
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisputeDecision {
+    Payer,
+    Payee,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveDisputeRequest {
+    pub decision: DisputeDecision,
+    pub note: Option<String>,
+}
+
+#[post("/admin/disputes/{dispute_id}/resolve")]
+pub async fn resolve_dispute(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<ResolveDisputeRequest>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /admin/disputes/{}/resolve called by {}",
+        path, admin.username
+    );
+    let dispute = disputes::resolve_dispute(
+        &pool,
+        path.into_inner(),
+        &admin.username,
+        matches!(req.decision, DisputeDecision::Payer),
+        req.note.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(dispute))
+}
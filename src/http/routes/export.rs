@@ -0,0 +1,129 @@
+use crate::http::db::model::{TransactionFilter, TransactionPage};
+use crate::http::db::queries::{self, MAX_PAGE_SIZE};
+use crate::http::errors::ApiError;
+use crate::http::export::{self, ExportContext, ExportFormat};
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::web::Bytes;
+use actix_web::{HttpResponse, get, web};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use log::{debug, error};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Where the export stream is: the page to encode next, if fetched, and the
+/// cursor for the one after it.
+struct Pages {
+    pool: PgPool,
+    username: String,
+    format: ExportFormat,
+    filter: TransactionFilter,
+    page: Option<TransactionPage>,
+}
+
+/// Encodes the fetched page and fetches the next one, until history runs out.
+async fn next_chunk(mut pages: Pages) -> Option<(Result<Bytes, ApiError>, Pages)> {
+    let page = match pages.page.take() {
+        Some(page) => page,
+        None => {
+            pages.filter.cursor.as_ref()?;
+            match queries::fetch_transactions(&pages.pool, &pages.username, &pages.filter).await {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("Export for {} failed mid-stream: {}", pages.username, e);
+                    pages.filter.cursor = None;
+                    return Some((Err(e), pages));
+                }
+            }
+        }
+    };
+    let mut chunk = String::new();
+    for entry in &page.transactions {
+        export::write_entry(pages.format, entry, &mut chunk);
+    }
+    pages.filter.cursor = page.next_cursor;
+    Some((Ok(Bytes::from(chunk)), pages))
+}
+
+/// Streams a user's history between `from` and `to` as CSV, OFX or QIF, a
+/// page at a time, so large histories are never held in memory. The first
+/// page is fetched before the response starts, so bad parameters still get
+/// a proper error status.
+#[get("/users/{username}/transactions/export")]
+pub async fn export_transactions(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    query: web::Query<ExportQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "GET /users/{}/transactions/export as {:?} called by {}",
+        path, query.format, user.username
+    );
+    let username = path.into_inner();
+    policy::authorize_view(&pool, &user, &[username.as_str()]).await?;
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from >= to
+    {
+        return Err(ApiError::Validation("from must be before to".to_string()));
+    }
+    let balance = queries::fetch_balance(&pool, &username)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    let filter = TransactionFilter {
+        limit: Some(MAX_PAGE_SIZE),
+        from: query.from,
+        to: query.to,
+        ..Default::default()
+    };
+    let first = queries::fetch_transactions(&pool, &username, &filter).await?;
+
+    let generated_at = Utc::now();
+    let (ledger_balance, ledger_balance_at) = first
+        .transactions
+        .first()
+        .map(|entry| (entry.balance_after, entry.posted_at))
+        .unwrap_or((balance.ledger_balance, generated_at));
+    let ctx = ExportContext {
+        username: username.clone(),
+        from: query.from,
+        to: query.to,
+        generated_at,
+        ledger_balance,
+        ledger_balance_at,
+    };
+    let format = query.format;
+    let header = export::header(format, &ctx);
+    let footer = export::footer(format, &ctx);
+    let pages = Pages {
+        pool: pool.get_ref().clone(),
+        username: username.clone(),
+        format,
+        filter,
+        page: Some(first),
+    };
+    let body = stream::once(async move { Ok::<_, ApiError>(Bytes::from(header)) })
+        .chain(stream::unfold(pages, next_chunk))
+        .chain(stream::once(async move { Ok(Bytes::from(footer)) }));
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"transactions-{}.{}\"",
+                username.replace(['"', '\\'], "_"),
+                format.extension()
+            ),
+        ))
+        .streaming(body))
+}
@@ -0,0 +1,62 @@
+use crate::http::db::model::{NewFeeRule, TransferKind};
+use crate::http::db::queries::fees;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::{AdminUser, AuthenticatedUser};
+use actix_web::{HttpResponse, delete, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct QuoteQuery {
+    pub amount: f64,
+    #[serde(default)]
+    pub kind: TransferKind,
+}
+
+#[get("/transfers/quote")]
+pub async fn quote_transfer(
+    pool: web::Data<PgPool>,
+    query: web::Query<QuoteQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /transfers/quote called by {}", user.username);
+    let quote = fees::quote(&pool, &user.username, query.kind, query.amount).await?;
+    Ok(HttpResponse::Ok().json(quote))
+}
+
+#[get("/admin/fee-rules")]
+pub async fn list_fee_rules(
+    pool: web::Data<PgPool>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /admin/fee-rules called by {}", admin.username);
+    let rules = fees::list_rules(&pool).await?;
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+#[post("/admin/fee-rules")]
+pub async fn create_fee_rule(
+    pool: web::Data<PgPool>,
+    req: web::Json<NewFeeRule>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /admin/fee-rules called by {}", admin.username);
+    let rule = fees::create_rule(&pool, &req).await?;
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+#[delete("/admin/fee-rules/{rule_id}")]
+pub async fn deactivate_fee_rule(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "DELETE /admin/fee-rules/{} called by {}",
+        path, admin.username
+    );
+    let rule = fees::deactivate_rule(&pool, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(rule))
+}
@@ -0,0 +1,100 @@
+use crate::http::db::queries::funding as funding_queries;
+use crate::http::errors::ApiError;
+use crate::http::funding::{self, BankAccount, FundingProvider, SIGNATURE_HEADER};
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpRequest, HttpResponse, get, post, web};
+use log::{debug, warn};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[get("/users/{username}/funding")]
+pub async fn list_funding(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/funding called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let transfers = funding_queries::list_funding(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(transfers))
+}
+
+#[derive(Deserialize)]
+pub struct DepositRequest {
+    pub amount: f64,
+}
+
+#[post("/users/{username}/deposits")]
+pub async fn deposit(
+    pool: web::Data<PgPool>,
+    provider: web::Data<dyn FundingProvider>,
+    path: web::Path<String>,
+    req: web::Json<DepositRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/deposits called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let deposit =
+        funding::request_deposit(&pool, provider.get_ref(), &username, req.amount).await?;
+    Ok(HttpResponse::Accepted().json(deposit))
+}
+
+#[derive(Deserialize)]
+pub struct WithdrawalRequest {
+    pub amount: f64,
+    pub account: BankAccount,
+}
+
+#[post("/users/{username}/withdrawals")]
+pub async fn withdraw(
+    pool: web::Data<PgPool>,
+    provider: web::Data<dyn FundingProvider>,
+    path: web::Path<String>,
+    req: web::Json<WithdrawalRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /users/{}/withdrawals called by {}",
+        path, user.username
+    );
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let withdrawal = funding::request_withdrawal(
+        &pool,
+        provider.get_ref(),
+        &username,
+        req.amount,
+        &req.account,
+    )
+    .await?;
+    Ok(HttpResponse::Accepted().json(withdrawal))
+}
+
+/// Where providers report how a transfer ended. There is no login here; the
+/// body's signature is checked instead.
+#[post("/funding/callbacks/{provider}")]
+pub async fn callback(
+    pool: web::Data<PgPool>,
+    provider: web::Data<dyn FundingProvider>,
+    path: web::Path<String>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /funding/callbacks/{} called", path);
+    if path.as_str() != provider.name() {
+        return Err(ApiError::NotFound);
+    }
+    let signature = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| {
+            warn!("Funding callback without a signature");
+            ApiError::Unauthorized
+        })?;
+    let settled = funding::handle_callback(&pool, provider.get_ref(), &body, signature).await?;
+    Ok(HttpResponse::Ok().json(settled))
+}
@@ -0,0 +1,196 @@
+use crate::http::db::model::NewExpense;
+use crate::http::db::queries::groups;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use actix_web::{HttpResponse, delete, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const MAX_NAME_LEN: usize = 64;
+const MAX_DESCRIPTION_LEN: usize = 140;
+
+fn required_text(value: &str, field: &str, max_len: usize) -> Result<String, ApiError> {
+    let value = value.trim();
+    if value.is_empty() || value.chars().count() > max_len {
+        return Err(ApiError::Validation(format!(
+            "{} must be between 1 and {} characters",
+            field, max_len
+        )));
+    }
+    Ok(value.to_string())
+}
+
+#[derive(Deserialize)]
+pub struct CreateGroupRequest {
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+#[post("/groups")]
+pub async fn create_group(
+    pool: web::Data<PgPool>,
+    req: web::Json<CreateGroupRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /groups called by {}", user.username);
+    let name = required_text(&req.name, "name", MAX_NAME_LEN)?;
+    let group = groups::create_group(&pool, &user.username, &name, &req.members).await?;
+    Ok(HttpResponse::Ok().json(group))
+}
+
+#[get("/groups")]
+pub async fn list_groups(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /groups called by {}", user.username);
+    let groups = groups::list_groups(&pool, &user.username).await?;
+    Ok(HttpResponse::Ok().json(groups))
+}
+
+#[get("/groups/{group_id}")]
+pub async fn get_group(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /groups/{} called by {}", path, user.username);
+    let group = groups::get_group(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(group))
+}
+
+#[derive(Deserialize)]
+pub struct AddMemberRequest {
+    pub username: String,
+}
+
+#[post("/groups/{group_id}/members")]
+pub async fn add_member(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<AddMemberRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /groups/{}/members called by {}", path, user.username);
+    let group = groups::add_member(&pool, path.into_inner(), &user.username, &req.username).await?;
+    Ok(HttpResponse::Ok().json(group))
+}
+
+#[post("/groups/{group_id}/accept")]
+pub async fn accept_invite(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /groups/{}/accept called by {}", path, user.username);
+    let group = groups::accept_invite(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(group))
+}
+
+#[post("/groups/{group_id}/decline")]
+pub async fn decline_invite(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /groups/{}/decline called by {}", path, user.username);
+    groups::decline_invite(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().body("Invitation declined"))
+}
+
+#[post("/groups/{group_id}/expenses")]
+pub async fn add_expense(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<NewExpense>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /groups/{}/expenses called by {}", path, user.username);
+    let mut expense = req.into_inner();
+    expense.description = required_text(&expense.description, "description", MAX_DESCRIPTION_LEN)?;
+    let expense = groups::add_expense(&pool, path.into_inner(), &user.username, &expense).await?;
+    Ok(HttpResponse::Ok().json(expense))
+}
+
+#[get("/groups/{group_id}/expenses")]
+pub async fn list_expenses(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /groups/{}/expenses called by {}", path, user.username);
+    let expenses = groups::list_expenses(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(expenses))
+}
+
+#[get("/groups/{group_id}/balances")]
+pub async fn group_balances(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /groups/{}/balances called by {}", path, user.username);
+    let balances = groups::group_balances(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(balances))
+}
+
+#[post("/groups/{group_id}/settle")]
+pub async fn settle_up(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /groups/{}/settle called by {}", path, user.username);
+    let settled = groups::settle_up(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(settled))
+}
+
+#[post("/groups/{group_id}/expenses/{expense_id}/settle")]
+pub async fn settle_expense(
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (group_id, expense_id) = path.into_inner();
+    debug!(
+        "POST /groups/{}/expenses/{}/settle called by {}",
+        group_id, expense_id, user.username
+    );
+    let settled = groups::settle_expense(&pool, group_id, expense_id, &user.username).await?;
+    Ok(HttpResponse::Ok().json(settled))
+}
+
+#[post("/groups/{group_id}/expenses/{expense_id}/contest")]
+pub async fn contest_expense(
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (group_id, expense_id) = path.into_inner();
+    debug!(
+        "POST /groups/{}/expenses/{}/contest called by {}",
+        group_id, expense_id, user.username
+    );
+    let expense =
+        groups::contest_expense(&pool, group_id, expense_id, &user.username, true).await?;
+    Ok(HttpResponse::Ok().json(expense))
+}
+
+#[delete("/groups/{group_id}/expenses/{expense_id}/contest")]
+pub async fn withdraw_contest(
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (group_id, expense_id) = path.into_inner();
+    debug!(
+        "DELETE /groups/{}/expenses/{}/contest called by {}",
+        group_id, expense_id, user.username
+    );
+    let expense =
+        groups::contest_expense(&pool, group_id, expense_id, &user.username, false).await?;
+    Ok(HttpResponse::Ok().json(expense))
+}
@@ -0,0 +1,71 @@
+use crate::http::db::queries::holds;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct CreateHoldRequest {
+    pub payee: String,
+    pub amount: f64,
+    pub expires_in_secs: Option<i64>,
+}
+
+#[post("/holds")]
+pub async fn create_hold(
+    pool: web::Data<PgPool>,
+    req: web::Json<CreateHoldRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /holds called by {}", user.username);
+    let hold = holds::create_hold(
+        &pool,
+        &user.username,
+        &req.payee,
+        req.amount,
+        req.expires_in_secs,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(hold))
+}
+
+#[get("/holds")]
+pub async fn list_holds(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /holds called by {}", user.username);
+    let holds = holds::list_holds(&pool, &user.username).await?;
+    Ok(HttpResponse::Ok().json(holds))
+}
+
+#[derive(Deserialize)]
+pub struct CaptureRequest {
+    pub amount: Option<f64>,
+}
+
+#[post("/holds/{hold_id}/capture")]
+pub async fn capture_hold(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<CaptureRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /holds/{}/capture called by {}", path, user.username);
+    let hold = holds::capture_hold(&pool, path.into_inner(), &user.username, req.amount).await?;
+    Ok(HttpResponse::Ok().json(hold))
+}
+
+#[post("/holds/{hold_id}/void")]
+pub async fn void_hold(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /holds/{}/void called by {}", path, user.username);
+    let hold = holds::void_hold(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(hold))
+}
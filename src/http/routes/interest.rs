@@ -0,0 +1,78 @@
+use crate::http::db::model::NewInterestProduct;
+use crate::http::db::queries::interest;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::{AdminUser, AuthenticatedUser};
+use crate::http::policy;
+use actix_web::{HttpResponse, delete, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[get("/interest/products")]
+pub async fn list_products(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /interest/products called by {}", user.username);
+    let products = interest::list_products(&pool).await?;
+    Ok(HttpResponse::Ok().json(products))
+}
+
+#[post("/admin/interest/products")]
+pub async fn save_product(
+    pool: web::Data<PgPool>,
+    req: web::Json<NewInterestProduct>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /admin/interest/products called by {}", admin.username);
+    let product = interest::save_product(&pool, &req).await?;
+    Ok(HttpResponse::Ok().json(product))
+}
+
+#[get("/users/{username}/interest")]
+pub async fn interest_summary(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/interest called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let summary = interest::fetch_summary(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[derive(Deserialize)]
+pub struct EnrollRequest {
+    pub product_id: String,
+}
+
+#[post("/users/{username}/interest")]
+pub async fn enroll(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<EnrollRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/interest called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let summary = interest::enroll(&pool, &username, &req.product_id).await?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[delete("/users/{username}/interest")]
+pub async fn unenroll(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "DELETE /users/{}/interest called by {}",
+        path, user.username
+    );
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let summary = interest::unenroll(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(summary))
+}
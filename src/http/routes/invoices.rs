@@ -0,0 +1,86 @@
+use crate::http::db::model::{InvoiceStatus, NewInvoiceItem};
+use crate::http::db::queries::invoices;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use actix_web::{HttpResponse, get, post, web};
+use chrono::NaiveDate;
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct CreateInvoiceRequest {
+    pub customer: String,
+    pub due_date: NaiveDate,
+    pub memo: Option<String>,
+    pub items: Vec<NewInvoiceItem>,
+}
+
+#[post("/invoices")]
+pub async fn create_invoice(
+    pool: web::Data<PgPool>,
+    req: web::Json<CreateInvoiceRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /invoices called by {}", user.username);
+    let invoice = invoices::create_invoice(
+        &pool,
+        &user.username,
+        &req.customer,
+        req.due_date,
+        req.memo.as_deref(),
+        &req.items,
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(invoice))
+}
+
+#[derive(Deserialize)]
+pub struct InvoiceListQuery {
+    pub status: Option<InvoiceStatus>,
+}
+
+#[get("/invoices")]
+pub async fn list_invoices(
+    pool: web::Data<PgPool>,
+    query: web::Query<InvoiceListQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /invoices called by {}", user.username);
+    let invoices = invoices::list_invoices(&pool, &user.username, query.status).await?;
+    Ok(HttpResponse::Ok().json(invoices))
+}
+
+#[get("/invoices/{invoice_id}")]
+pub async fn get_invoice(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /invoices/{} called by {}", path, user.username);
+    let invoice = invoices::fetch_invoice(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(invoice))
+}
+
+#[post("/invoices/{invoice_id}/pay")]
+pub async fn pay_invoice(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /invoices/{}/pay called by {}", path, user.username);
+    let invoice = invoices::pay_invoice(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(invoice))
+}
+
+#[post("/invoices/{invoice_id}/void")]
+pub async fn void_invoice(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /invoices/{}/void called by {}", path, user.username);
+    let invoice = invoices::void_invoice(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(invoice))
+}
@@ -0,0 +1,124 @@
+use crate::http::db::model::KycLevel;
+use crate::http::db::queries::kyc;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::{AdminUser, AuthenticatedUser};
+use crate::http::policy;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[get("/users/{username}/kyc")]
+pub async fn kyc_limits(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/kyc called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let limits = kyc::fetch_user_kyc_limits(&pool, &username)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    Ok(HttpResponse::Ok().json(limits))
+}
+
+#[derive(Deserialize)]
+pub struct SubmitVerificationRequest {
+    pub requested_level: KycLevel,
+    pub document_type: String,
+    pub document_number: String,
+    pub notes: Option<String>,
+}
+
+#[post("/admin/users/{username}/kyc")]
+pub async fn submit_verification(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<SubmitVerificationRequest>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /admin/users/{}/kyc called by {}",
+        path, admin.username
+    );
+    let username = path.into_inner();
+    let req = req.into_inner();
+    if req.document_type.trim().is_empty() || req.document_number.trim().is_empty() {
+        return Err(ApiError::Validation(
+            "document_type and document_number are required".to_string(),
+        ));
+    }
+    let verification = kyc::submit_verification(
+        &pool,
+        &username,
+        req.requested_level,
+        req.document_type.trim(),
+        req.document_number.trim(),
+        req.notes.as_deref(),
+        &admin.username,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(verification))
+}
+
+#[get("/admin/users/{username}/kyc")]
+pub async fn list_verifications(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /admin/users/{}/kyc called by {}", path, admin.username);
+    let verifications = kyc::fetch_verifications(&pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(verifications))
+}
+
+#[derive(Deserialize)]
+pub struct ReviewRequest {
+    pub notes: Option<String>,
+}
+
+#[post("/admin/kyc/{id}/approve")]
+pub async fn approve_verification(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<ReviewRequest>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /admin/kyc/{}/approve called by {}",
+        path, admin.username
+    );
+    let verification = kyc::review_verification(
+        &pool,
+        path.into_inner(),
+        true,
+        &admin.username,
+        req.notes.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(verification))
+}
+
+#[post("/admin/kyc/{id}/reject")]
+pub async fn reject_verification(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    req: web::Json<ReviewRequest>,
+    admin: AdminUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /admin/kyc/{}/reject called by {}",
+        path, admin.username
+    );
+    let verification = kyc::review_verification(
+        &pool,
+        path.into_inner(),
+        false,
+        &admin.username,
+        req.notes.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(verification))
+}
@@ -0,0 +1,35 @@
+use crate::http::db::model::NewSpendingLimits;
+use crate::http::db::queries::limits;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use sqlx::PgPool;
+
+#[get("/users/{username}/limits")]
+pub async fn get_limits(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/limits called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let limits = limits::fetch_spending_limits(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(limits))
+}
+
+#[post("/users/{username}/limits")]
+pub async fn set_limits(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<NewSpendingLimits>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/limits called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let limits = limits::set_spending_limits(&pool, &username, &req).await?;
+    Ok(HttpResponse::Ok().json(limits))
+}
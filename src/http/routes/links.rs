@@ -0,0 +1,221 @@
+use crate::http::config::Config;
+use crate::http::db::model::{PaymentLink, SignedPaymentLink};
+use crate::http::db::queries::{annotations, links};
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::jwt::links::{LINK_AUDIENCE, LinkClaims, sign_link, verify_link};
+use actix_web::{HttpResponse, delete, get, post, web};
+use image::{ImageFormat, Luma};
+use log::{debug, error, warn};
+use qrcode::QrCode;
+use qrcode::render::svg;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::io::Cursor;
+use uuid::Uuid;
+
+/// Scanned QR codes open this URI followed by the link token.
+pub const PAYMENT_LINK_URI_PREFIX: &str = "payfree://pay/";
+const QR_MIN_SIZE: u32 = 256;
+
+/// Signs a link with the login token secret, under its own audience.
+fn sign(link: PaymentLink, config: &Config) -> Result<SignedPaymentLink, ApiError> {
+    let claims = LinkClaims {
+        aud: LINK_AUDIENCE.to_string(),
+        link_id: link.link_id,
+        recipient: link.recipient_username.clone(),
+        amount: link.amount,
+        memo: link.memo.clone(),
+        exp: link.expires_at.timestamp() as usize,
+    };
+    let token = sign_link(&claims, config.auth.jwt_secret.expose()).map_err(|e| {
+        error!("Failed to sign payment link {}: {:?}", link.link_id, e);
+        ApiError::InternalServerError
+    })?;
+    Ok(SignedPaymentLink {
+        link,
+        uri: format!("{}{}", PAYMENT_LINK_URI_PREFIX, token),
+        token,
+    })
+}
+
+/// Verifies a token and loads the link it points to.
+async fn load(pool: &PgPool, config: &Config, token: &str) -> Result<PaymentLink, ApiError> {
+    let claims = verify_link(token, config.auth.jwt_secret.expose()).map_err(|e| {
+        warn!("Rejected payment link token: {:?}", e);
+        ApiError::Validation("invalid or expired payment link".to_string())
+    })?;
+    links::fetch_link(pool, claims.link_id).await
+}
+
+#[derive(Deserialize)]
+pub struct CreateLinkRequest {
+    pub amount: Option<f64>,
+    pub memo: Option<String>,
+    pub ttl_secs: Option<i64>,
+    #[serde(default)]
+    pub multi_use: bool,
+}
+
+#[post("/payment-links")]
+pub async fn create_link(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    req: web::Json<CreateLinkRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /payment-links called by {}", user.username);
+    let memo = match req.memo.as_deref() {
+        Some(memo) => annotations::sanitize_memo(memo)?,
+        None => None,
+    };
+    let link = links::create_link(
+        &pool,
+        &user.username,
+        req.amount,
+        memo.as_deref(),
+        req.ttl_secs,
+        req.multi_use,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(sign(link, &config)?))
+}
+
+#[get("/payment-links")]
+pub async fn list_links(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /payment-links called by {}", user.username);
+    let links = links::list_links(&pool, &user.username)
+        .await?
+        .into_iter()
+        .map(|link| sign(link, &config))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(HttpResponse::Ok().json(links))
+}
+
+#[delete("/payment-links/{link_id}")]
+pub async fn revoke_link(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("DELETE /payment-links/{} called by {}", path, user.username);
+    let link = links::revoke_link(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(link))
+}
+
+#[get("/payment-links/{token}")]
+pub async fn link_details(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /payment-links/<token> called by {}", user.username);
+    let link = load(&pool, &config, &path).await?;
+    Ok(HttpResponse::Ok().json(link))
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+#[derive(Deserialize)]
+pub struct QrQuery {
+    #[serde(default)]
+    pub format: QrFormat,
+}
+
+/// Renders `data` as a QR code image.
+fn render_qr(data: &str, format: QrFormat) -> Result<(&'static str, Vec<u8>), ApiError> {
+    let code = QrCode::new(data.as_bytes()).map_err(|e| {
+        error!("Failed to encode QR code: {:?}", e);
+        ApiError::InternalServerError
+    })?;
+    match format {
+        QrFormat::Svg => {
+            let image = code
+                .render::<svg::Color>()
+                .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+                .build();
+            Ok(("image/svg+xml", image.into_bytes()))
+        }
+        QrFormat::Png => {
+            let image = code
+                .render::<Luma<u8>>()
+                .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+                .build();
+            let mut png = Cursor::new(Vec::new());
+            image.write_to(&mut png, ImageFormat::Png).map_err(|e| {
+                error!("Failed to write QR code PNG: {:?}", e);
+                ApiError::InternalServerError
+            })?;
+            Ok(("image/png", png.into_inner()))
+        }
+    }
+}
+
+#[get("/payment-links/{token}/qr")]
+pub async fn link_qr(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<QrQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "GET /payment-links/<token>/qr as {:?} called by {}",
+        query.format, user.username
+    );
+    let token = path.into_inner();
+    load(&pool, &config, &token).await?;
+    let (content_type, body) = render_qr(
+        &format!("{}{}", PAYMENT_LINK_URI_PREFIX, token),
+        query.format,
+    )?;
+    Ok(HttpResponse::Ok().content_type(content_type).body(body))
+}
+
+#[derive(Deserialize)]
+pub struct RedeemRequest {
+    pub amount: Option<f64>,
+}
+
+#[post("/payment-links/{token}/redeem")]
+pub async fn redeem_link(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    req: web::Json<RedeemRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /payment-links/<token>/redeem called by {}",
+        user.username
+    );
+    let link = load(&pool, &config, &path).await?;
+    let txn = links::redeem_link(&pool, link.link_id, &user.username, req.amount).await?;
+    Ok(HttpResponse::Ok().json(txn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_qr_formats() {
+        let (content_type, png) = render_qr("payfree://pay/token", QrFormat::Png).unwrap();
+        assert_eq!(content_type, "image/png");
+        assert!(png.starts_with(b"\x89PNG"));
+        let (content_type, svg) = render_qr("payfree://pay/token", QrFormat::Svg).unwrap();
+        assert_eq!(content_type, "image/svg+xml");
+        assert!(String::from_utf8(svg).unwrap().contains("<svg"));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::http::db::model::SettlementSchedule;
+use crate::http::db::queries::merchants;
+use crate::http::errors::ApiError;
+use crate::http::funding::BankAccount;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[get("/users/{username}/merchant")]
+pub async fn get_merchant(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/merchant called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::authorize_view(&pool, &user, &[username.as_str()]).await?;
+    let merchant = merchants::fetch_merchant(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(merchant))
+}
+
+#[derive(Deserialize)]
+pub struct MerchantRequest {
+    pub display_name: String,
+    pub settlement_schedule: Option<SettlementSchedule>,
+    #[serde(default)]
+    pub settlement_reserve: f64,
+    pub settlement_account: Option<BankAccount>,
+}
+
+#[post("/users/{username}/merchant")]
+pub async fn save_merchant(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<MerchantRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/merchant called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let merchant = merchants::save_merchant(
+        &pool,
+        &username,
+        &req.display_name,
+        req.settlement_schedule
+            .unwrap_or(SettlementSchedule::Manual),
+        req.settlement_reserve,
+        req.settlement_account.as_ref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(merchant))
+}
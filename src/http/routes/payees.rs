@@ -0,0 +1,74 @@
+use crate::http::db::queries::payees;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpResponse, delete, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const MAX_NICKNAME_LEN: usize = 64;
+
+#[get("/users/{username}/payees")]
+pub async fn list_payees(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/payees called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let payees = payees::list_payees(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(payees))
+}
+
+#[derive(Deserialize)]
+pub struct SavePayeeRequest {
+    pub username: String,
+    pub nickname: Option<String>,
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+#[post("/users/{username}/payees")]
+pub async fn save_payee(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<SavePayeeRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/payees called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let req = req.into_inner();
+    let nickname = req
+        .nickname
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty());
+    if nickname.is_some_and(|n| n.chars().count() > MAX_NICKNAME_LEN) {
+        return Err(ApiError::Validation(format!(
+            "nickname must be at most {} characters",
+            MAX_NICKNAME_LEN
+        )));
+    }
+    let payee = payees::save_payee(&pool, &username, &req.username, nickname, req.favorite).await?;
+    Ok(HttpResponse::Ok().json(payee))
+}
+
+#[delete("/users/{username}/payees/{payee_id}")]
+pub async fn delete_payee(
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (username, payee_id) = path.into_inner();
+    debug!(
+        "DELETE /users/{}/payees/{} called by {}",
+        username, payee_id, user.username
+    );
+    policy::ensure_owner(&user, &username)?;
+    payees::delete_payee(&pool, &username, payee_id).await?;
+    Ok(HttpResponse::Ok().body("Payee deleted"))
+}
@@ -0,0 +1,117 @@
+use crate::http::db::model::RequestStatus;
+use crate::http::db::queries::{annotations, requests};
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use actix_web::{HttpResponse, get, post, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct CreateRequest {
+    pub payer: String,
+    pub amount: f64,
+    pub memo: Option<String>,
+}
+
+#[post("/requests")]
+pub async fn create_request(
+    pool: web::Data<PgPool>,
+    req: web::Json<CreateRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /requests called by {}", user.username);
+    let req = req.into_inner();
+    let memo = match req.memo.as_deref() {
+        Some(memo) => annotations::sanitize_memo(memo)?,
+        None => None,
+    };
+    let request = requests::create_request(
+        &pool,
+        &user.username,
+        &req.payer,
+        req.amount,
+        memo.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+#[derive(Deserialize)]
+pub struct ListRequestsQuery {
+    pub status: Option<RequestStatus>,
+}
+
+#[get("/requests/incoming")]
+pub async fn incoming_requests(
+    pool: web::Data<PgPool>,
+    query: web::Query<ListRequestsQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /requests/incoming called by {}", user.username);
+    let requests = requests::list_requests(&pool, &user.username, true, query.status).await?;
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+#[get("/requests/outgoing")]
+pub async fn outgoing_requests(
+    pool: web::Data<PgPool>,
+    query: web::Query<ListRequestsQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /requests/outgoing called by {}", user.username);
+    let requests = requests::list_requests(&pool, &user.username, false, query.status).await?;
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+#[post("/requests/{id}/approve")]
+pub async fn approve_request(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /requests/{}/approve called by {}",
+        path, user.username
+    );
+    let request = requests::approve_request(&pool, path.into_inner(), &user.username).await?;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+#[post("/requests/{id}/decline")]
+pub async fn decline_request(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!(
+        "POST /requests/{}/decline called by {}",
+        path, user.username
+    );
+    let request = requests::reject_request(
+        &pool,
+        path.into_inner(),
+        &user.username,
+        RequestStatus::Declined,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+#[post("/requests/{id}/cancel")]
+pub async fn cancel_request(
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /requests/{}/cancel called by {}", path, user.username);
+    let request = requests::reject_request(
+        &pool,
+        path.into_inner(),
+        &user.username,
+        RequestStatus::Cancelled,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(request))
+}
@@ -0,0 +1,106 @@
+use crate::http::db::model::{NewSchedule, ScheduleStatus};
+use crate::http::db::queries::schedules;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpResponse, delete, get, post, web};
+use log::debug;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[post("/users/{username}/schedules")]
+pub async fn create_schedule(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    req: web::Json<NewSchedule>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/schedules called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let schedule = schedules::create_schedule(&pool, &username, &req).await?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+#[get("/users/{username}/schedules")]
+pub async fn list_schedules(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/schedules called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let schedules = schedules::list_schedules(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(schedules))
+}
+
+#[get("/users/{username}/schedules/{schedule_id}/runs")]
+pub async fn list_runs(
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (username, schedule_id) = path.into_inner();
+    debug!(
+        "GET /users/{}/schedules/{}/runs called by {}",
+        username, schedule_id, user.username
+    );
+    policy::ensure_owner(&user, &username)?;
+    let runs = schedules::list_runs(&pool, &username, schedule_id).await?;
+    Ok(HttpResponse::Ok().json(runs))
+}
+
+#[post("/users/{username}/schedules/{schedule_id}/pause")]
+pub async fn pause_schedule(
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (username, schedule_id) = path.into_inner();
+    debug!(
+        "POST /users/{}/schedules/{}/pause called by {}",
+        username, schedule_id, user.username
+    );
+    policy::ensure_owner(&user, &username)?;
+    let schedule =
+        schedules::set_schedule_status(&pool, &username, schedule_id, ScheduleStatus::Paused)
+            .await?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+#[post("/users/{username}/schedules/{schedule_id}/resume")]
+pub async fn resume_schedule(
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (username, schedule_id) = path.into_inner();
+    debug!(
+        "POST /users/{}/schedules/{}/resume called by {}",
+        username, schedule_id, user.username
+    );
+    policy::ensure_owner(&user, &username)?;
+    let schedule =
+        schedules::set_schedule_status(&pool, &username, schedule_id, ScheduleStatus::Active)
+            .await?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+#[delete("/users/{username}/schedules/{schedule_id}")]
+pub async fn cancel_schedule(
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, Uuid)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (username, schedule_id) = path.into_inner();
+    debug!(
+        "DELETE /users/{}/schedules/{} called by {}",
+        username, schedule_id, user.username
+    );
+    policy::ensure_owner(&user, &username)?;
+    let schedule =
+        schedules::set_schedule_status(&pool, &username, schedule_id, ScheduleStatus::Cancelled)
+            .await?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
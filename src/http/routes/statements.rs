@@ -0,0 +1,189 @@
+use crate::http::db::model::{Statement, TransferKind};
+use crate::http::db::queries::statements;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::policy;
+use actix_web::{HttpResponse, get, web};
+use log::debug;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[get("/users/{username}/statements")]
+pub async fn list_statements(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("GET /users/{}/statements called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::authorize_view(&pool, &user, &[username.as_str()]).await?;
+    let statements = statements::list_statements(&pool, &username).await?;
+    Ok(HttpResponse::Ok().json(statements))
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    #[default]
+    Json,
+    Html,
+}
+
+#[derive(Deserialize)]
+pub struct StatementQuery {
+    #[serde(default)]
+    pub format: StatementFormat,
+}
+
+#[get("/users/{username}/statements/{month}")]
+pub async fn get_statement(
+    pool: web::Data<PgPool>,
+    path: web::Path<(String, String)>,
+    query: web::Query<StatementQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (username, month) = path.into_inner();
+    debug!(
+        "GET /users/{}/statements/{} as {:?} called by {}",
+        username, month, query.format, user.username
+    );
+    policy::authorize_view(&pool, &user, &[username.as_str()]).await?;
+    let period_start = statements::parse_month(&month)?;
+    let statement = statements::generate_statement(&pool, &username, period_start).await?;
+    match query.format {
+        StatementFormat::Json => Ok(HttpResponse::Ok().json(statement)),
+        StatementFormat::Html => Ok(HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(render_html(&statement))),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn kind_label(kind: TransferKind) -> String {
+    format!("{:?}", kind).to_lowercase()
+}
+
+/// Renders a statement as a self-contained page meant to be printed or saved
+/// as PDF from a browser.
+fn render_html(statement: &Statement) -> String {
+    let last_day = statement
+        .period_end
+        .pred_opt()
+        .unwrap_or(statement.period_end);
+    let mut rows = String::new();
+    for entry in &statement.entries {
+        // Writing to a String cannot fail.
+        let _ = writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"num\">{:.2}</td><td class=\"num\">{:.2}</td><td class=\"num\">{:.2}</td></tr>",
+            entry.posted_at.format("%Y-%m-%d %H:%M"),
+            escape_html(&entry.counterparty_username),
+            kind_label(entry.kind),
+            escape_html(entry.memo.as_deref().unwrap_or("")),
+            entry.fee,
+            entry.amount,
+            entry.balance_after,
+        );
+    }
+    if statement.entries.is_empty() {
+        rows.push_str("<tr><td colspan=\"7\">No transactions in this period.</td></tr>\n");
+    }
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Payfree statement {start} to {end}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ border-bottom: 1px solid #ccc; padding: 4px 8px; text-align: left; }}
+.num {{ text-align: right; }}
+@media print {{ body {{ margin: 0; }} }}
+</style>
+</head>
+<body>
+<h1>Account statement</h1>
+<p>{name} ({username})<br>Period: {start} to {end}<br>Statement {id}, generated {generated}</p>
+<table>
+<tr><th>Opening balance</th><td class="num">{opening:.2}</td></tr>
+<tr><th>Money in</th><td class="num">{total_in:.2}</td></tr>
+<tr><th>Money out</th><td class="num">{total_out:.2}</td></tr>
+<tr><th>Closing balance</th><td class="num">{closing:.2}</td></tr>
+</table>
+<h2>Transactions</h2>
+<table>
+<tr><th>Posted (UTC)</th><th>Counterparty</th><th>Type</th><th>Memo</th><th class="num">Fee</th><th class="num">Amount</th><th class="num">Balance</th></tr>
+{rows}</table>
+</body>
+</html>
+"#,
+        name = escape_html(&statement.account_name),
+        username = escape_html(&statement.username),
+        start = statement.period_start,
+        end = last_day,
+        id = statement.statement_id,
+        generated = statement.generated_at.format("%Y-%m-%d %H:%M UTC"),
+        opening = statement.opening_balance,
+        total_in = statement.total_in,
+        total_out = statement.total_out,
+        closing = statement.closing_balance,
+        rows = rows,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::db::model::{StatementEntry, TransferDirection};
+    use chrono::{NaiveDate, Utc};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_render_html_escapes_user_text() {
+        let statement = Statement {
+            statement_id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            account_name: "Alice <Admin>".to_string(),
+            period_start: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            period_end: NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(),
+            opening_balance: 100.0,
+            closing_balance: 75.5,
+            total_in: 0.0,
+            total_out: 24.5,
+            generated_at: Utc::now(),
+            entries: vec![StatementEntry {
+                txn_id: Uuid::new_v4(),
+                posted_at: Utc::now(),
+                kind: TransferKind::P2p,
+                direction: TransferDirection::Outgoing,
+                counterparty_username: "bob".to_string(),
+                memo: Some("<script>alert(1)</script>".to_string()),
+                amount: -24.5,
+                fee: 0.0,
+                balance_after: 75.5,
+            }],
+        };
+        let html = render_html(&statement);
+        assert!(html.contains("Period: 2024-05-01 to 2024-05-31"));
+        assert!(html.contains("Alice &lt;Admin&gt;"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("-24.50"));
+    }
+}
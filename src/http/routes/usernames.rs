@@ -0,0 +1,81 @@
+use crate::http::config::Config;
+use crate::http::db::queries::usernames;
+use crate::http::errors::ApiError;
+use crate::http::jwt::extractor::AuthenticatedUser;
+use crate::http::jwt::generate_jwt;
+use crate::http::policy;
+use actix_web::{HttpResponse, post, web};
+use log::{debug, error};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+const MIN_USERNAME_LEN: usize = 3;
+const MAX_USERNAME_LEN: usize = 32;
+
+pub fn validate_username(username: &str) -> Result<(), ApiError> {
+    if !(MIN_USERNAME_LEN..=MAX_USERNAME_LEN).contains(&username.len()) {
+        return Err(ApiError::Validation(format!(
+            "username must be between {} and {} characters",
+            MIN_USERNAME_LEN, MAX_USERNAME_LEN
+        )));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+    {
+        return Err(ApiError::Validation(
+            "username may only contain letters, digits, '_', '.' and '-'".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    pub new_username: String,
+}
+
+#[post("/users/{username}/rename")]
+pub async fn rename_user(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    req: web::Json<RenameRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    debug!("POST /users/{}/rename called by {}", path, user.username);
+    let username = path.into_inner();
+    policy::ensure_owner(&user, &username)?;
+    let new_username = req.into_inner().new_username;
+    validate_username(&new_username)?;
+    usernames::rename_user(&pool, &username, &new_username).await?;
+    debug!("User {} renamed to {}", username, new_username);
+
+    // Claims.sub carries the username, so the old token no longer matches.
+    let token = generate_jwt(
+        &new_username,
+        config.auth.jwt_secret.expose(),
+        config.auth.token_expiry_secs,
+    )
+    .map_err(|_| {
+        error!("JWT generation failed for rename");
+        ApiError::InternalServerError
+    })?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "username": new_username,
+        "token": token,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_username() {
+        assert!(validate_username("new.name_1").is_ok());
+        assert!(validate_username("ab").is_err());
+        assert!(validate_username(&"a".repeat(33)).is_err());
+        assert!(validate_username("bad name").is_err());
+    }
+}
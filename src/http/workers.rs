@@ -0,0 +1,198 @@
+//! Background jobs that run inside the server process.
+
+use crate::http::db::queries::{self, holds, interest, merchants, schedules, statements};
+use crate::http::funding::{
+    self, FundingProvider,
+    simulated::{SIMULATED_PROVIDER, SimulatedProvider},
+};
+use chrono::{Days, Utc};
+use log::{error, info};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often the scheduled transfer worker looks for due schedules.
+pub const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Schedules run per poll, so one tick cannot hold the worker indefinitely.
+pub const SCHEDULE_BATCH_SIZE: usize = 100;
+/// How often stale holds are marked expired.
+pub const HOLD_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the interest worker accrues finished days and pays out
+/// finished months.
+pub const INTEREST_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Finished days the interest worker revisits on every run, so days missed
+/// while the server was down are still accrued.
+pub const INTEREST_CATCH_UP_DAYS: u64 = 7;
+/// How often the simulated funding provider settles pending transfers.
+pub const FUNDING_SIMULATOR_INTERVAL: Duration = Duration::from_secs(5);
+/// Transfers the simulated provider settles per run.
+pub const FUNDING_SIMULATOR_BATCH_SIZE: i64 = 100;
+/// How often the statement worker checks for users still missing last
+/// month's statement.
+pub const STATEMENT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How often the settlement worker looks for merchants due a payout.
+pub const MERCHANT_SETTLEMENT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Merchants settled per run.
+pub const MERCHANT_SETTLEMENT_BATCH_SIZE: i64 = 100;
+
+/// Spawns the scheduled transfer worker. Safe to run in several processes at
+/// once, since schedules are claimed with `FOR UPDATE SKIP LOCKED`.
+pub fn spawn_scheduled_transfers(pool: PgPool) {
+    tokio::spawn(async move {
+        info!("Scheduled transfer worker started");
+        let mut interval = tokio::time::interval(SCHEDULE_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            match schedules::run_due_transfers(&pool, SCHEDULE_BATCH_SIZE).await {
+                Ok(0) => {}
+                Ok(ran) => info!("Ran {} scheduled transfers", ran),
+                Err(e) => error!("Scheduled transfer worker failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Spawns the sweeper that marks holds past their expiry as expired. Expired
+/// holds stop counting against available balance as soon as they lapse; the
+/// sweeper only records it.
+pub fn spawn_hold_sweeper(pool: PgPool) {
+    tokio::spawn(async move {
+        info!("Hold sweeper started");
+        let mut interval = tokio::time::interval(HOLD_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match holds::expire_stale_holds(&pool).await {
+                Ok(0) => {}
+                Ok(expired) => info!("Expired {} stale holds", expired),
+                Err(e) => error!("Hold sweeper failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Spawns the interest worker. Both steps are idempotent: a day is accrued
+/// at most once per user and accruals are marked paid with their payout.
+pub fn spawn_interest_worker(pool: PgPool) {
+    tokio::spawn(async move {
+        info!("Interest worker started");
+        let mut interval = tokio::time::interval(INTEREST_INTERVAL);
+        loop {
+            interval.tick().await;
+            let today = Utc::now().date_naive();
+            for days_ago in (1..=INTEREST_CATCH_UP_DAYS).rev() {
+                let Some(day) = today.checked_sub_days(Days::new(days_ago)) else {
+                    continue;
+                };
+                match interest::accrue_day(&pool, day).await {
+                    Ok(0) => {}
+                    Ok(accrued) => info!("Accrued interest for {} users on {}", accrued, day),
+                    Err(e) => error!("Interest accrual for {} failed: {}", day, e),
+                }
+            }
+            match interest::pay_out_month(&pool, today).await {
+                Ok(0) => {}
+                Ok(paid) => info!("Paid out interest to {} users", paid),
+                Err(e) => error!("Interest payout failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Spawns the month-end statement worker, which generates last month's
+/// statement for every user who does not have one yet. Statements are stored
+/// once per user and month, so reruns and several processes are harmless.
+pub fn spawn_statement_worker(pool: PgPool) {
+    tokio::spawn(async move {
+        info!("Statement worker started");
+        let mut interval = tokio::time::interval(STATEMENT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(month) = statements::previous_month(Utc::now().date_naive()) else {
+                continue;
+            };
+            match statements::generate_month(&pool, month).await {
+                Ok(0) => {}
+                Ok(generated) => info!("Generated {} statements for {}", generated, month),
+                Err(e) => error!("Statement generation for {} failed: {}", month, e),
+            }
+        }
+    });
+}
+
+/// Spawns the simulated funding provider's bank side: transfers pending for
+/// longer than its settle delay are settled by feeding its signed callbacks
+/// through the same path as a real provider's.
+pub fn spawn_funding_simulator(pool: PgPool, provider: Arc<SimulatedProvider>) {
+    tokio::spawn(async move {
+        info!("Funding simulator started");
+        let mut interval = tokio::time::interval(FUNDING_SIMULATOR_INTERVAL);
+        loop {
+            interval.tick().await;
+            let before = Utc::now()
+                - chrono::Duration::from_std(provider.settle_after()).unwrap_or_default();
+            let pending = match queries::funding::pending_before(
+                &pool,
+                SIMULATED_PROVIDER,
+                before,
+                FUNDING_SIMULATOR_BATCH_SIZE,
+            )
+            .await
+            {
+                Ok(pending) => pending,
+                Err(e) => {
+                    error!("Funding simulator failed: {}", e);
+                    continue;
+                }
+            };
+            for transfer in pending {
+                let settled = match provider.callback(&transfer) {
+                    Ok((body, signature)) => {
+                        funding::handle_callback(&pool, provider.as_ref(), &body, &signature).await
+                    }
+                    Err(e) => Err(e),
+                };
+                match settled {
+                    Ok(settled) => info!(
+                        "Simulated {:?} {} is {:?}",
+                        settled.direction, settled.funding_id, settled.status
+                    ),
+                    Err(e) => error!(
+                        "Simulated settlement of {} failed: {}",
+                        transfer.funding_id, e
+                    ),
+                }
+            }
+        }
+    });
+}
+
+/// Spawns the merchant settlement worker, which pays out merchants on a
+/// daily or weekly schedule once their period has passed. A merchant whose
+/// payout fails is retried on the next run.
+pub fn spawn_merchant_settlement(pool: PgPool, provider: Arc<dyn FundingProvider>) {
+    tokio::spawn(async move {
+        info!("Merchant settlement worker started");
+        let mut interval = tokio::time::interval(MERCHANT_SETTLEMENT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let due =
+                match merchants::due_for_settlement(&pool, MERCHANT_SETTLEMENT_BATCH_SIZE).await {
+                    Ok(due) => due,
+                    Err(e) => {
+                        error!("Merchant settlement worker failed: {}", e);
+                        continue;
+                    }
+                };
+            for merchant in due {
+                match funding::settle_merchant(&pool, provider.as_ref(), &merchant).await {
+                    Ok(Some(settlement)) => info!(
+                        "Settling {} for {} as {}",
+                        settlement.amount, merchant.username, settlement.funding_id
+                    ),
+                    Ok(None) => {}
+                    Err(e) => error!("Settlement for {} failed: {}", merchant.username, e),
+                }
+            }
+        }
+    });
+}
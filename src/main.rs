@@ -1,24 +1,24 @@
 use actix_web::{App, HttpServer, web};
 use anyhow::Context;
-use http::routes::{
-    check_balance, get_transaction, get_transactions, hello, login, new_transaction, new_user,
-    profile,
-};
-use log::info;
+use http::config::{Config, FundingProviderKind};
+use http::funding::FundingProvider;
+use http::funding::simulated::SimulatedProvider;
+use log::{info, warn};
 use sqlx::postgres::PgPoolOptions;
+use std::sync::Arc;
+use std::time::Duration;
 pub mod http;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
 
-    let database_url = dotenvy::var("DATABASE_URL")
-        .context("DATABASE_URL must be set")
-        .unwrap();
+    let config = Config::load().context("invalid configuration").unwrap();
+    info!("Loaded configuration: {:?}", config);
 
     let db = PgPoolOptions::new()
-        .max_connections(20)
-        .connect(&database_url)
+        .max_connections(config.database.max_connections)
+        .connect(config.database.url.expose())
         .await
         .context("failed to connect to DATABASE_URL")
         .unwrap();
@@ -29,21 +29,37 @@ async fn main() -> std::io::Result<()> {
         .context("failed to initialize database tables")
         .unwrap();
 
-    info!("Starting server at http://127.0.0.1:4040");
+    http::workers::spawn_scheduled_transfers(db.clone());
+    http::workers::spawn_hold_sweeper(db.clone());
+    http::workers::spawn_interest_worker(db.clone());
+    http::workers::spawn_statement_worker(db.clone());
+
+    let provider: Arc<dyn FundingProvider> = match config.funding.provider {
+        FundingProviderKind::Simulated => {
+            warn!("Using the simulated funding provider: deposits complete without real money");
+            let simulated = Arc::new(SimulatedProvider::new(
+                config.funding.webhook_secret.expose(),
+                Duration::from_secs(config.funding.settle_after_secs),
+            ));
+            http::workers::spawn_funding_simulator(db.clone(), simulated.clone());
+            simulated
+        }
+    };
+    // Scheduled merchant payouts only ever go through the configured provider.
+    http::workers::spawn_merchant_settlement(db.clone(), provider.clone());
+
+    let bind = (config.server.host.clone(), config.server.port);
+    info!("Starting server at http://{}:{}", bind.0, bind.1);
+    let config = web::Data::new(config);
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(db.clone()))
-            .service(hello)
-            .service(new_user)
-            .service(login)
-            .service(profile)
-            .service(get_transactions)
-            .service(check_balance)
-            .service(new_transaction)
-            .service(get_transaction)
+            .app_data(config.clone())
+            .app_data(web::Data::from(provider.clone()))
+            .configure(http::routes::init_routes)
     })
-    .bind(("127.0.0.1", 4040))?
+    .bind(bind)?
     .run()
     .await
 }
@@ -4,7 +4,16 @@ use serde_json::json;
 use uuid::Uuid;
 
 use actix_web::web;
+use payfree::http::config::Config;
+use payfree::http::funding::simulated::SimulatedProvider;
+use payfree::http::funding::{
+    FundingEvent, FundingOutcome, FundingProvider, SIGNATURE_HEADER, sign_callback,
+};
 use sqlx::postgres::PgPoolOptions;
+use std::sync::Arc;
+use std::time::Duration;
+
+const WEBHOOK_SECRET: &str = "webhook_secret_for_integration_tests_0123456789";
 
 #[actix_rt::test]
 async fn test_signup_login_profile_balance_transaction() {
@@ -15,9 +24,21 @@ async fn test_signup_login_profile_balance_transaction() {
         .await
         .expect("Failed to connect to test database");
 
+    let config = Config::from_sources(None, |key| match key {
+        "FUNDING_PROVIDER" => Some("simulated".to_string()),
+        "JWT_SECRET" => Some("jwt_secret_for_integration_tests_0123456789".to_string()),
+        "FUNDING_WEBHOOK_SECRET" => Some(WEBHOOK_SECRET.to_string()),
+        _ => dotenvy::var(key).ok(),
+    })
+    .expect("test config must be valid");
+    let provider: Arc<dyn FundingProvider> =
+        Arc::new(SimulatedProvider::new(WEBHOOK_SECRET, Duration::ZERO));
+
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::from(provider))
             .configure(payfree::http::routes::init_routes),
     )
     .await;
@@ -79,16 +100,15 @@ async fn test_signup_login_profile_balance_transaction() {
         ),
     ];
 
-    for (userid, name, username, phno, address, balance, password) in &test_users {
+    for (userid, name, username, phno, address, _, password) in &test_users {
         let req = test::TestRequest::post()
             .uri("/auth/signup")
-            .set_json(&json!({
+            .set_json(json!({
                 "userid": userid,
                 "name": name,
                 "username": username,
                 "phno": phno,
                 "address": address,
-                "balance": balance,
                 "password": password
             }))
             .to_request();
@@ -107,7 +127,7 @@ async fn test_signup_login_profile_balance_transaction() {
     for (_, _, username, _, _, _, password) in &test_users {
         let req = test::TestRequest::post()
             .uri("/auth/login")
-            .set_json(&json!({
+            .set_json(json!({
                 "username": username,
                 "password": password
             }))
@@ -119,6 +139,36 @@ async fn test_signup_login_profile_balance_transaction() {
         tokens.push((username.to_string(), token));
     }
 
+    // Accounts open empty, so fund each one with a deposit that the
+    // simulated bank then reports as completed.
+    for (i, (_, _, username, _, _, balance, _)) in test_users.iter().enumerate() {
+        let req = test::TestRequest::post()
+            .uri(&format!("/users/{}/deposits", username))
+            .insert_header(("Authorization", format!("Bearer {}", tokens[i].1)))
+            .set_json(json!({ "amount": balance }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+        let deposit: serde_json::Value = test::read_body_json(resp).await;
+
+        let event = FundingEvent {
+            provider_ref: deposit["provider_ref"].as_str().unwrap().to_string(),
+            outcome: FundingOutcome::Succeeded,
+            reason: None,
+        };
+        let body = serde_json::to_vec(&event).unwrap();
+        let signature = sign_callback(&body, WEBHOOK_SECRET).unwrap();
+        let req = test::TestRequest::post()
+            .uri("/funding/callbacks/simulated")
+            .insert_header((SIGNATURE_HEADER, signature))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let settled: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(settled["status"], "completed");
+    }
+
     for (i, (_, _, username, _, _, balance, _)) in test_users.iter().enumerate() {
         let token = &tokens[i].1;
         let req = test::TestRequest::get()
@@ -137,14 +187,15 @@ async fn test_signup_login_profile_balance_transaction() {
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
         let body: serde_json::Value = test::read_body_json(resp).await;
-        assert_eq!(body, json!(balance));
+        assert_eq!(body["ledger_balance"], json!(balance));
+        assert_eq!(body["available_balance"], json!(balance));
     }
 
     let txn_id = Uuid::new_v4();
     let req = test::TestRequest::post()
         .uri("/transactions/new")
         .insert_header(("Authorization", format!("Bearer {}", tokens[0].1)))
-        .set_json(&json!({
+        .set_json(json!({
             "txn_id": txn_id,
             "amount": 100,
             "from_username": "rishabh",
@@ -162,7 +213,7 @@ async fn test_signup_login_profile_balance_transaction() {
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
     let body: serde_json::Value = test::read_body_json(resp).await;
-    assert!(body.as_array().unwrap().len() > 0);
+    assert!(!body["transactions"].as_array().unwrap().is_empty());
 
     let req = test::TestRequest::get()
         .uri("/users/anurag/transactions")
@@ -171,7 +222,7 @@ async fn test_signup_login_profile_balance_transaction() {
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
     let body: serde_json::Value = test::read_body_json(resp).await;
-    assert!(body.as_array().unwrap().len() > 0);
+    assert!(!body["transactions"].as_array().unwrap().is_empty());
 
     let req = test::TestRequest::get()
         .uri(&format!("/transactions/{}", txn_id))
@@ -181,4 +232,34 @@ async fn test_signup_login_profile_balance_transaction() {
     assert!(resp.status().is_success());
     let body: serde_json::Value = test::read_body_json(resp).await;
     assert_eq!(body["txn_id"], json!(txn_id.to_string()));
+
+    // Non-participants cannot tell the transaction from a missing one.
+    for uri in [
+        format!("/transactions/{}", txn_id),
+        format!("/transactions/{}", Uuid::new_v4()),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&uri)
+            .insert_header(("Authorization", format!("Bearer {}", tokens[2].1)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+        let body = test::read_body(resp).await;
+        assert_eq!(body, "Transaction not found");
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/users/rishabh/viewers")
+        .insert_header(("Authorization", format!("Bearer {}", tokens[0].1)))
+        .set_json(json!({ "username": "deep" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/transactions/{}", txn_id))
+        .insert_header(("Authorization", format!("Bearer {}", tokens[2].1)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
 }